@@ -0,0 +1,154 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use ascn_rs::BitBuffer;
+
+/// The `Vec<bool>` implementation the packed buffer replaced, kept to compare against
+struct LegacyBitBuffer {
+    data: Vec<bool>,
+}
+
+impl LegacyBitBuffer {
+    fn new() -> Self {
+        Self { data: Vec::new() }
+    }
+
+    fn add(&mut self, data: u8, length: u8) {
+        for i in 0..length {
+            self.data.insert(0, ((data >> i) & 1) == 1);
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut byte_pos = 0;
+        let mut current_byte = 0u8;
+        let mut result = Vec::new();
+
+        for bit in self.data.iter().rev() {
+            current_byte |= (*bit as u8) << byte_pos;
+            byte_pos += 1;
+
+            if byte_pos == 8 {
+                result.push(current_byte);
+                current_byte = 0u8;
+                byte_pos = 0;
+            }
+        }
+
+        if byte_pos != 0 {
+            result.push(current_byte);
+        }
+
+        result.reverse();
+
+        result
+    }
+
+    fn from_bytes(data: &[u8]) -> Self {
+        let mut bit_buffer = Self::new();
+
+        for byte in data.iter().rev() {
+            for i in 0..8 {
+                bit_buffer.data.insert(0, ((byte >> i) & 1) == 1);
+            }
+        }
+
+        bit_buffer
+    }
+
+    fn read(&mut self, length: u8) -> u8 {
+        let mut result = 0u8;
+
+        for i in 0..length {
+            result |= (self.data.pop().unwrap() as u8) << i;
+        }
+
+        result
+    }
+}
+
+/// Overflow entries shaped like a game: mostly two bit indexes with some three bit knight indexes
+fn get_entries(count: usize) -> Vec<(u8, u8)> {
+    (0..count)
+        .map(|i| {
+            if i % 5 == 0 {
+                (i as u8 & 7, 3)
+            } else {
+                (i as u8 & 3, 2)
+            }
+        })
+        .collect()
+}
+
+fn write(c: &mut Criterion) {
+    let mut group = c.benchmark_group("write");
+
+    for count in [40, 400, 4000] {
+        let entries = get_entries(count);
+
+        group.bench_with_input(BenchmarkId::new("legacy", count), &entries, |b, entries| {
+            b.iter(|| {
+                let mut bit_buffer = LegacyBitBuffer::new();
+
+                for (data, length) in entries {
+                    bit_buffer.add(*data, *length);
+                }
+
+                black_box(bit_buffer.to_bytes())
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("packed", count), &entries, |b, entries| {
+            b.iter(|| {
+                let mut bit_buffer = BitBuffer::new();
+
+                for (data, length) in entries {
+                    bit_buffer.add((*data).into(), *length);
+                }
+
+                black_box(bit_buffer.to_bytes())
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn read(c: &mut Criterion) {
+    let mut group = c.benchmark_group("read");
+
+    for count in [40, 400, 4000] {
+        let entries = get_entries(count);
+        let mut bit_buffer = BitBuffer::new();
+
+        for (data, length) in &entries {
+            bit_buffer.add((*data).into(), *length);
+        }
+
+        let bytes = bit_buffer.to_bytes();
+
+        group.bench_with_input(BenchmarkId::new("legacy", count), &bytes, |b, bytes| {
+            b.iter(|| {
+                let mut bit_buffer = LegacyBitBuffer::from_bytes(bytes);
+
+                for (_, length) in &entries {
+                    black_box(bit_buffer.read(*length));
+                }
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("packed", count), &bytes, |b, bytes| {
+            b.iter(|| {
+                let mut bit_buffer = BitBuffer::from_bytes(bytes);
+
+                for (_, length) in &entries {
+                    black_box(bit_buffer.read(*length));
+                }
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, write, read);
+criterion_main!(benches);
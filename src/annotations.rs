@@ -0,0 +1,580 @@
+//! Comments, numeric annotation glyphs (NAGs), clock times and engine evaluations attached to the
+//! moves of a game
+//!
+//! Annotations are keyed by ply, where ply 0 is the start of the game and ply `n` is the position
+//! after the `n`th move, so the annotation of a ply follows the move that led to it like it does in
+//! PGN movetext.
+//!
+//! Each kind of annotation is stored in its own section of the header, so readers only parse the
+//! ones they need. Clocks are stored in the `CLOCKS_SECTION` as described in `clock` and
+//! evaluations in the `EVALS_SECTION` as described in `eval`.
+//!
+//! Comments and NAGs are stored in the `ANNOTATIONS_SECTION`. The section starts with a string
+//! table, a varint count followed by each distinct comment as a varint length and its UTF-8 bytes,
+//! so comments that repeat across a game are only stored once. Then comes a varint count of the
+//! annotated plies, each of which is:
+//!
+//! * the distance to the previous annotated ply, or to ply 0 for the first one, as a varint
+//! * a varint count of comments followed by their indices into the string table as varints
+//! * a varint count of NAGs followed by one byte for each
+//!
+//! The moves of variations have no ply of their own, so their annotations are keyed by node
+//! instead, which is the index of the move or variation marker they follow in the core stream. They
+//! are stored in the `VARIATION_ANNOTATIONS_SECTION`, which holds their comments and NAGs in the
+//! layout above, then a varint length followed by their clocks in the layout of the
+//! `CLOCKS_SECTION` and then their evaluations in the layout of the `EVALS_SECTION` for the rest of
+//! the section.
+
+mod clock;
+mod eval;
+
+use std::collections::{btree_map, BTreeMap, HashMap};
+
+use crate::{
+    header::{ANNOTATIONS_SECTION, CLOCKS_SECTION, EVALS_SECTION, VARIATION_ANNOTATIONS_SECTION},
+    varint::{read_varint, write_varint},
+};
+
+pub use self::{clock::Clock, eval::Eval};
+
+/// The comments and NAGs of a ply or node as read from a section
+type Comments = (usize, Vec<String>, Vec<u8>);
+
+/// The comments, NAGs, clock time and evaluation of a single ply
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Annotation {
+    pub comments: Vec<String>,
+    pub nags: Vec<u8>,
+    /// The time left on the clock of the side that made the move
+    pub clock: Option<Clock>,
+    /// The evaluation of the position after the move
+    pub eval: Option<Eval>,
+}
+
+impl Annotation {
+    /// Returns whether there is nothing in the annotation
+    pub fn is_empty(&self) -> bool {
+        self.comments.is_empty()
+            && self.nags.is_empty()
+            && self.clock.is_none()
+            && self.eval.is_none()
+    }
+
+    fn has_comments(&self) -> bool {
+        !self.comments.is_empty() || !self.nags.is_empty()
+    }
+}
+
+/// The annotations of a game by ply of the main line and by node inside variations
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Annotations {
+    plies: BTreeMap<usize, Annotation>,
+    nodes: BTreeMap<usize, Annotation>,
+    /// The increment of the time control in milliseconds, which the clocks are predicted with
+    clock_increment: u32,
+}
+
+impl Annotations {
+    /// Creates an empty set of annotations
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of annotated plies, not counting the nodes inside variations
+    pub fn len(&self) -> usize {
+        self.plies.len()
+    }
+
+    /// Returns whether no ply and no node inside a variation has been annotated
+    pub fn is_empty(&self) -> bool {
+        self.plies.is_empty() && self.nodes.is_empty()
+    }
+
+    /// The annotation of a ply, if it has one
+    pub fn get(&self, ply: usize) -> Option<&Annotation> {
+        self.plies.get(&ply)
+    }
+
+    /// Adds a comment after the comments a ply already has
+    pub fn add_comment(&mut self, ply: usize, comment: impl Into<String>) {
+        self.plies
+            .entry(ply)
+            .or_default()
+            .comments
+            .push(comment.into());
+    }
+
+    /// Adds a NAG after the NAGs a ply already has
+    pub fn add_nag(&mut self, ply: usize, nag: u8) {
+        self.plies.entry(ply).or_default().nags.push(nag);
+    }
+
+    /// Sets the clock time of a ply
+    pub fn set_clock(&mut self, ply: usize, clock: Clock) {
+        self.plies.entry(ply).or_default().clock = Some(clock);
+    }
+
+    /// Sets the evaluation of a ply
+    pub fn set_eval(&mut self, ply: usize, eval: Eval) {
+        self.plies.entry(ply).or_default().eval = Some(eval);
+    }
+
+    /// The increment of the time control in milliseconds
+    pub fn get_clock_increment(&self) -> u32 {
+        self.clock_increment
+    }
+
+    /// Sets the increment of the time control in milliseconds. It is only used to store the
+    /// clocks more compactly, so a wrong increment makes them larger but still correct.
+    pub fn set_clock_increment(&mut self, increment: u32) {
+        self.clock_increment = increment;
+    }
+
+    /// The time the side that made the move at `ply` spent on it in milliseconds, if it and the
+    /// previous move of that side have clock times. It is negative when time was added.
+    pub fn get_move_time(&self, ply: usize) -> Option<i64> {
+        let before = self.get(ply.checked_sub(2)?)?.clock?;
+        let after = self.get(ply)?.clock?;
+
+        Some(before.millis as i64 + self.clock_increment as i64 - after.millis as i64)
+    }
+
+    /// Returns an iterator over the annotated plies in order
+    pub fn iter(&self) -> btree_map::Iter<'_, usize, Annotation> {
+        self.plies.iter()
+    }
+
+    /// The annotation of a node inside a variation, if it has one
+    pub fn get_node(&self, node: usize) -> Option<&Annotation> {
+        self.nodes.get(&node)
+    }
+
+    /// The annotation of a node inside a variation, which is added empty if it has none yet
+    pub fn get_node_mut(&mut self, node: usize) -> &mut Annotation {
+        self.nodes.entry(node).or_default()
+    }
+
+    /// Returns an iterator over the annotated nodes inside variations in order
+    pub fn iter_nodes(&self) -> btree_map::Iter<'_, usize, Annotation> {
+        self.nodes.iter()
+    }
+
+    /// Removes the annotation of a ply and returns it
+    pub(crate) fn take(&mut self, ply: usize) -> Option<Annotation> {
+        self.plies.remove(&ply)
+    }
+
+    /// Removes the annotation of a node inside a variation and returns it
+    pub(crate) fn take_node(&mut self, node: usize) -> Option<Annotation> {
+        self.nodes.remove(&node)
+    }
+
+    /// Creates the sections that hold the annotations, leaving out the ones that would be empty
+    pub(crate) fn encode(&self) -> Vec<(u8, Vec<u8>)> {
+        let mut sections = Vec::new();
+
+        if self.plies.values().any(Annotation::has_comments) {
+            sections.push((ANNOTATIONS_SECTION, encode_comments(&self.plies)));
+        }
+
+        let clocks = get_clocks(&self.plies);
+
+        if !clocks.is_empty() {
+            sections.push((CLOCKS_SECTION, clock::encode(&clocks, self.clock_increment)));
+        }
+
+        let evals = get_evals(&self.plies);
+
+        if !evals.is_empty() {
+            sections.push((EVALS_SECTION, eval::encode(&evals)));
+        }
+
+        if self.nodes.values().any(|annotation| !annotation.is_empty()) {
+            let mut data = encode_comments(&self.nodes);
+            let clocks = clock::encode(&get_clocks(&self.nodes), self.clock_increment);
+
+            write_varint(&mut data, clocks.len() as u64);
+            data.extend(clocks);
+            data.extend(eval::encode(&get_evals(&self.nodes)));
+
+            sections.push((VARIATION_ANNOTATIONS_SECTION, data));
+        }
+
+        sections
+    }
+
+    /// Reads the annotations from a section, ignoring sections that do not hold annotations.
+    /// Returns `None` if the section is invalid.
+    pub(crate) fn decode_section(&mut self, id: u8, data: &[u8]) -> Option<()> {
+        match id {
+            ANNOTATIONS_SECTION => {
+                let mut cursor = Cursor { data, offset: 0 };
+                let plies = read_comments(&mut cursor)?;
+
+                if cursor.offset != data.len() {
+                    return None;
+                }
+
+                for (ply, comments, nags) in plies {
+                    let annotation = self.plies.entry(ply).or_default();
+
+                    annotation.comments = comments;
+                    annotation.nags = nags;
+                }
+
+                Some(())
+            }
+            CLOCKS_SECTION => {
+                let (clocks, increment) = clock::decode(data)?;
+
+                for (ply, clock) in clocks {
+                    self.set_clock(ply, clock);
+                }
+
+                self.clock_increment = increment;
+
+                Some(())
+            }
+            EVALS_SECTION => {
+                for (ply, eval) in eval::decode(data)? {
+                    self.set_eval(ply, eval);
+                }
+
+                Some(())
+            }
+            VARIATION_ANNOTATIONS_SECTION => {
+                let mut cursor = Cursor { data, offset: 0 };
+                let nodes = read_comments(&mut cursor)?;
+                let length = cursor.read_number()?;
+                let (clocks, _) = clock::decode(cursor.read_bytes(length)?)?;
+                let evals = eval::decode(&data[cursor.offset..])?;
+
+                for (node, comments, nags) in nodes {
+                    let annotation = self.get_node_mut(node);
+
+                    annotation.comments = comments;
+                    annotation.nags = nags;
+                }
+
+                for (node, clock) in clocks {
+                    self.get_node_mut(node).clock = Some(clock);
+                }
+
+                for (node, eval) in evals {
+                    self.get_node_mut(node).eval = Some(eval);
+                }
+
+                Some(())
+            }
+            _ => Some(()),
+        }
+    }
+}
+
+/// The clocks of the annotations in order
+fn get_clocks(annotations: &BTreeMap<usize, Annotation>) -> Vec<(usize, Clock)> {
+    annotations
+        .iter()
+        .filter_map(|(key, annotation)| Some((*key, annotation.clock?)))
+        .collect()
+}
+
+/// The evaluations of the annotations in order
+fn get_evals(annotations: &BTreeMap<usize, Annotation>) -> Vec<(usize, Eval)> {
+    annotations
+        .iter()
+        .filter_map(|(key, annotation)| Some((*key, annotation.eval?)))
+        .collect()
+}
+
+/// Creates the comments and NAGs of an annotation section from the annotations by ply or node
+fn encode_comments(annotations: &BTreeMap<usize, Annotation>) -> Vec<u8> {
+    let mut strings: Vec<&str> = Vec::new();
+    let mut indices = HashMap::new();
+
+    for annotation in annotations.values() {
+        for comment in &annotation.comments {
+            indices.entry(comment.as_str()).or_insert_with(|| {
+                strings.push(comment);
+                strings.len() - 1
+            });
+        }
+    }
+
+    let mut result = Vec::new();
+
+    write_varint(&mut result, strings.len() as u64);
+
+    for string in strings {
+        write_varint(&mut result, string.len() as u64);
+        result.extend_from_slice(string.as_bytes());
+    }
+
+    let annotated: Vec<_> = annotations
+        .iter()
+        .filter(|(_, annotation)| annotation.has_comments())
+        .collect();
+
+    write_varint(&mut result, annotated.len() as u64);
+
+    let mut previous = 0;
+
+    for (key, annotation) in annotated {
+        write_varint(&mut result, (key - previous) as u64);
+        previous = *key;
+
+        write_varint(&mut result, annotation.comments.len() as u64);
+
+        for comment in &annotation.comments {
+            write_varint(&mut result, indices[comment.as_str()] as u64);
+        }
+
+        write_varint(&mut result, annotation.nags.len() as u64);
+        result.extend_from_slice(&annotation.nags);
+    }
+
+    result
+}
+
+/// Reads the comments and NAGs of an annotation section by ply or node, returning `None` if they
+/// are truncated, have a comment that is not UTF-8 or an index past the end of the string table
+fn read_comments(cursor: &mut Cursor) -> Option<Vec<Comments>> {
+    // every string takes at least a byte, which bounds the count before anything is allocated
+    let count = cursor
+        .read_number()
+        .filter(|count| *count <= cursor.data.len())?;
+    let mut strings = Vec::with_capacity(count);
+
+    for _ in 0..count {
+        let length = cursor.read_number()?;
+        strings.push(String::from_utf8(cursor.read_bytes(length)?.to_vec()).ok()?);
+    }
+
+    let mut annotated = Vec::new();
+    let mut key = 0usize;
+
+    for index in 0..cursor.read_number()? {
+        let delta = cursor.read_number()?;
+
+        // keys are strictly increasing, except that the first one may be 0
+        if index > 0 && delta == 0 {
+            return None;
+        }
+
+        key = key.checked_add(delta)?;
+
+        let mut comments = Vec::new();
+
+        for _ in 0..cursor.read_number()? {
+            comments.push(strings.get(cursor.read_number()?)?.clone());
+        }
+
+        let length = cursor.read_number()?;
+        let nags = cursor.read_bytes(length)?.to_vec();
+
+        annotated.push((key, comments, nags));
+    }
+
+    Some(annotated)
+}
+
+/// Reads the fields of a section from the front
+struct Cursor<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn read_number(&mut self) -> Option<usize> {
+        let (value, length) = read_varint(&self.data[self.offset..])?;
+        self.offset += length;
+        usize::try_from(value).ok()
+    }
+
+    fn read_bytes(&mut self, length: usize) -> Option<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.offset..self.offset.checked_add(length)?)?;
+        self.offset += length;
+        Some(bytes)
+    }
+}
+
+impl<'a> IntoIterator for &'a Annotations {
+    type Item = (&'a usize, &'a Annotation);
+    type IntoIter = btree_map::Iter<'a, usize, Annotation>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::header::{
+        ANNOTATIONS_SECTION, CLOCKS_SECTION, EVALS_SECTION, VARIATION_ANNOTATIONS_SECTION,
+    };
+
+    use super::{Annotations, Clock, Eval};
+
+    fn decode(sections: &[(u8, Vec<u8>)]) -> Option<Annotations> {
+        let mut annotations = Annotations::new();
+
+        for (id, data) in sections {
+            annotations.decode_section(*id, data)?;
+        }
+
+        Some(annotations)
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut annotations = Annotations::new();
+
+        annotations.add_comment(0, "Opening comment");
+        annotations.add_nag(3, 2);
+        annotations.add_comment(3, "A mistake");
+        annotations.add_comment(3, "héllo");
+        annotations.add_nag(3, 14);
+        annotations.add_comment(10, "A mistake");
+
+        let sections = annotations.encode();
+
+        assert_eq!(sections.len(), 1);
+        assert_eq!(decode(&sections), Some(annotations.clone()));
+
+        // the repeated comment is only in the string table once
+        assert_eq!(
+            sections[0]
+                .1
+                .windows(9)
+                .filter(|bytes| bytes == b"A mistake")
+                .count(),
+            1
+        );
+
+        let annotation = annotations.get(3).unwrap();
+
+        assert_eq!(annotation.comments, ["A mistake", "héllo"]);
+        assert_eq!(annotation.nags, [2, 14]);
+        assert_eq!(annotations.get(1), None);
+        assert_eq!(
+            annotations.iter().map(|(ply, _)| *ply).collect::<Vec<_>>(),
+            [0, 3, 10]
+        );
+
+        assert_eq!(
+            decode(&[(ANNOTATIONS_SECTION, vec![0, 0])]),
+            Some(Annotations::new())
+        );
+        assert!(Annotations::new().encode().is_empty());
+    }
+
+    #[test]
+    fn channels() {
+        let clock = |millis| Clock {
+            millis,
+            decimals: 0,
+        };
+
+        let mut annotations = Annotations::new();
+
+        annotations.set_clock_increment(2000);
+        annotations.set_clock(1, clock(300_000));
+        annotations.set_clock(2, clock(300_000));
+        annotations.add_comment(3, "only a comment");
+        annotations.set_clock(3, clock(290_000));
+        annotations.set_clock(5, clock(293_000));
+        annotations.set_eval(5, Eval::Mate(2));
+
+        let sections = annotations.encode();
+
+        assert_eq!(
+            sections.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            [ANNOTATIONS_SECTION, CLOCKS_SECTION, EVALS_SECTION]
+        );
+        assert_eq!(decode(&sections), Some(annotations.clone()));
+
+        // the comments can be read without the clocks
+        let comments = decode(&sections[..1]).unwrap();
+
+        assert_eq!(comments.get(3).unwrap().clock, None);
+        assert_eq!(comments.get(3).unwrap().comments, ["only a comment"]);
+        assert_eq!(comments.get(1), None);
+
+        assert_eq!(annotations.get_move_time(3), Some(12_000));
+        assert_eq!(annotations.get_move_time(5), Some(-1000));
+        assert_eq!(annotations.get_move_time(4), None);
+        assert_eq!(annotations.get_move_time(1), None);
+    }
+
+    #[test]
+    fn variation_nodes() {
+        let mut annotations = Annotations::new();
+
+        annotations.add_comment(1, "main");
+        annotations
+            .get_node_mut(3)
+            .comments
+            .push("main".to_string());
+        annotations.get_node_mut(3).nags.push(6);
+        annotations.get_node_mut(4).clock = Some(Clock {
+            millis: 60_000,
+            decimals: 0,
+        });
+        annotations.get_node_mut(7).eval = Some(Eval::Mate(-3));
+
+        let sections = annotations.encode();
+
+        assert_eq!(
+            sections.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            [ANNOTATIONS_SECTION, VARIATION_ANNOTATIONS_SECTION]
+        );
+        assert_eq!(decode(&sections), Some(annotations.clone()));
+        assert_eq!(annotations.get(3), None);
+        assert_eq!(
+            annotations
+                .iter_nodes()
+                .map(|(node, _)| *node)
+                .collect::<Vec<_>>(),
+            [3, 4, 7]
+        );
+
+        // the main line can be read without the variations
+        let main = decode(&sections[..1]).unwrap();
+
+        assert_eq!(main.get_node(3), None);
+        assert_eq!(main.get(1).unwrap().comments, ["main"]);
+
+        // the clocks are truncated and the evaluations have trailing data
+        let data = &sections[1].1;
+
+        for data in [&data[..data.len() - 3], &[data, &[0][..]].concat()] {
+            assert_eq!(
+                decode(&[(VARIATION_ANNOTATIONS_SECTION, data.to_vec())]),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_sections() {
+        // truncated, trailing data, an index past the string table, invalid UTF-8 and a repeated ply
+        for data in [
+            &[1, 3, b'a', b'b'][..],
+            &[0, 0, 0],
+            &[1, 1, b'a', 1, 2, 1, 1, 0],
+            &[1, 1, 0xFF, 0],
+            &[0, 2, 1, 0, 0, 0, 0, 0],
+        ] {
+            assert_eq!(
+                decode(&[(ANNOTATIONS_SECTION, data.to_vec())]),
+                None,
+                "{data:?}"
+            );
+        }
+
+        // sections that are not annotations are ignored
+        assert_eq!(decode(&[(200, vec![0xFF])]), Some(Annotations::new()));
+    }
+}
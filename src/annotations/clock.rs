@@ -0,0 +1,361 @@
+//! The clock channel, which stores the `[%clk]` time left after each move
+//!
+//! The section starts with a byte holding the number of decimals of the unit every time is counted
+//! in, in its low two bits, and whether the clocks are written with differing numbers of decimals,
+//! in its third bit. Then come the increment of the time control in milliseconds as a varint, the
+//! last ply with a clock as a varint and the order of the Exp-Golomb codes as a byte, followed by a
+//! bit stream laid out like the overflow section with, for every ply up to the last one:
+//!
+//! * a bit that is set if the ply has a clock
+//! * the number of decimals the clock is written with in two bits, if they differ
+//! * the zig-zag encoded difference between the predicted and the actual time, in units, as an
+//!   Exp-Golomb code
+//!
+//! The prediction is the previous clock of the same side plus the increment, so the difference is
+//! the time spent on the move. The first clock of a side is predicted from the last clock of the
+//! other side, or from zero if there is none.
+
+use std::fmt;
+
+use crate::{
+    bitbuffer::{BitBuffer, BitReader},
+    varint::{read_varint, write_varint, zigzag_decode, zigzag_encode},
+};
+
+/// The highest order of Exp-Golomb codes that is tried
+const MAX_ORDER: u8 = 32;
+
+/// The time left on a player's clock after a move
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Clock {
+    /// The time left in milliseconds
+    pub millis: u32,
+    /// The number of digits after the decimal point of the seconds when written, from 0 to 3
+    pub decimals: u8,
+}
+
+impl Clock {
+    /// Parses the time of a `[%clk]` command such as `1:02:03` or `0:00:04.5`, returning `None`
+    /// unless the clock is written back as the same text
+    pub fn parse(text: &str) -> Option<Self> {
+        let (time, fraction) = match text.split_once('.') {
+            Some((time, fraction)) => (time, Some(fraction)),
+            None => (text, None),
+        };
+
+        let mut fields = time.split(':');
+        let hours: u32 = fields.next()?.parse().ok()?;
+        let minutes: u32 = fields.next()?.parse().ok()?;
+        let seconds: u32 = fields.next()?.parse().ok()?;
+
+        if fields.next().is_some() || minutes >= 60 || seconds >= 60 {
+            return None;
+        }
+
+        let (fraction, decimals) = match fraction {
+            Some(fraction) if (1..=3).contains(&fraction.len()) => (
+                fraction.parse::<u32>().ok()? * 10u32.pow(3 - fraction.len() as u32),
+                fraction.len() as u8,
+            ),
+            Some(_) => return None,
+            None => (0, 0),
+        };
+
+        let millis = hours
+            .checked_mul(3_600_000)?
+            .checked_add(minutes * 60_000 + seconds * 1000 + fraction)?;
+
+        let clock = Clock { millis, decimals };
+
+        (clock.to_string() == text).then_some(clock)
+    }
+}
+
+impl fmt::Display for Clock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = self.millis / 1000;
+
+        write!(
+            f,
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )?;
+
+        let decimals = self.decimals.min(3);
+
+        if decimals > 0 {
+            let fraction = self.millis % 1000 / 10u32.pow(3 - decimals as u32);
+
+            write!(f, ".{fraction:0width$}", width = decimals as usize)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The number of decimals needed to write a time in milliseconds without losing anything
+fn get_precision(millis: u32) -> u8 {
+    (0..3)
+        .find(|decimals| millis.is_multiple_of(10u32.pow(3 - *decimals as u32)))
+        .unwrap_or(3)
+}
+
+/// The predicted time of the clock at `ply` in units, from the clocks before it
+fn predict(times: &[Option<u64>], ply: usize, increment: u64) -> u64 {
+    let same_side = (0..ply)
+        .rev()
+        .skip(1)
+        .step_by(2)
+        .find_map(|previous| times[previous]);
+
+    match same_side {
+        Some(time) => time + increment,
+        None => times[..ply]
+            .iter()
+            .rev()
+            .find_map(|time| *time)
+            .unwrap_or(0),
+    }
+}
+
+fn get_code_length(value: u64, order: u8) -> usize {
+    let bits = 63 - (value + (1 << order)).leading_zeros() as usize;
+
+    2 * bits + 1 - order as usize
+}
+
+fn write_code(buffer: &mut BitBuffer, value: u64, order: u8) {
+    let value = value + (1 << order);
+    let bits = 63 - value.leading_zeros() as u8;
+
+    for _ in order..bits {
+        buffer.add_bit(true);
+    }
+
+    buffer.add_bit(false);
+    buffer.add(value, bits);
+}
+
+fn read_code(reader: &mut BitReader, order: u8) -> Option<u64> {
+    let mut bits = order;
+
+    while reader.try_read(1)? == 1 {
+        bits += 1;
+
+        if bits > 62 {
+            return None;
+        }
+    }
+
+    Some(((1 << bits) | reader.try_read(bits)?) - (1 << order))
+}
+
+/// Creates the contents of the clock section from the clocks by ply in order
+pub(super) fn encode(clocks: &[(usize, Clock)], increment: u32) -> Vec<u8> {
+    let decimals = clocks
+        .iter()
+        .map(|(_, clock)| clock.decimals.min(3).max(get_precision(clock.millis)))
+        .chain([get_precision(increment)])
+        .max()
+        .unwrap_or(0);
+    let mixed = clocks
+        .iter()
+        .any(|(_, clock)| clock.decimals.min(3) != decimals);
+    let unit = 10u32.pow(3 - decimals as u32);
+
+    let last = clocks.last().map_or(0, |(ply, _)| *ply);
+    let mut times = vec![None; last + 1];
+    let mut written_decimals = vec![0; last + 1];
+
+    for (ply, clock) in clocks {
+        times[*ply] = Some((clock.millis / unit) as u64);
+        written_decimals[*ply] = clock.decimals.min(3);
+    }
+
+    let increment_units = (increment / unit) as u64;
+
+    let differences: Vec<u64> = (0..=last)
+        .filter_map(|ply| {
+            let time = times[ply]?;
+            let predicted = predict(&times, ply, increment_units);
+
+            Some(zigzag_encode(predicted as i64 - time as i64))
+        })
+        .collect();
+
+    let order = (0..=MAX_ORDER)
+        .min_by_key(|order| {
+            differences
+                .iter()
+                .map(|difference| get_code_length(*difference, *order))
+                .sum::<usize>()
+        })
+        .unwrap();
+
+    let mut buffer = BitBuffer::new();
+    let mut differences = differences.into_iter();
+
+    for (ply, time) in times.iter().enumerate() {
+        buffer.add_bit(time.is_some());
+
+        if time.is_none() {
+            continue;
+        }
+
+        if mixed {
+            buffer.add(written_decimals[ply] as u64, 2);
+        }
+
+        write_code(&mut buffer, differences.next().unwrap(), order);
+    }
+
+    let mut result = vec![decimals | (mixed as u8) << 2];
+
+    write_varint(&mut result, increment as u64);
+    write_varint(&mut result, last as u64);
+    result.push(order);
+    result.extend(buffer.to_bytes());
+
+    result
+}
+
+/// Reads the contents of a clock section, returning the clocks by ply in order and the increment,
+/// or `None` if the section is invalid
+pub(super) fn decode(data: &[u8]) -> Option<(Vec<(usize, Clock)>, u32)> {
+    let (&flags, data) = data.split_first()?;
+
+    if flags > 0b111 {
+        return None;
+    }
+
+    let decimals = flags & 0b11;
+    let mixed = flags & 0b100 != 0;
+    let unit = 10u64.pow(3 - decimals as u32);
+
+    let (increment, length) = read_varint(data)?;
+    let data = &data[length..];
+    let (last, length) = read_varint(data)?;
+    let data = &data[length..];
+    let (&order, data) = data.split_first()?;
+
+    let increment = u32::try_from(increment).ok()?;
+
+    // every ply takes at least a bit, which bounds the count before anything is allocated
+    if order > MAX_ORDER || last >= data.len() as u64 * 8 {
+        return None;
+    }
+
+    let mut reader = BitReader::new(data);
+    let mut times = vec![None; last as usize + 1];
+    let mut clocks = Vec::new();
+
+    for ply in 0..times.len() {
+        if reader.try_read(1)? == 0 {
+            continue;
+        }
+
+        let clock_decimals = if mixed {
+            reader.try_read(2)? as u8
+        } else {
+            decimals
+        };
+
+        let predicted = predict(&times, ply, increment as u64 / unit);
+        let difference = zigzag_decode(read_code(&mut reader, order)?);
+        let time = u64::try_from((predicted as i64).checked_sub(difference)?).ok()?;
+
+        times[ply] = Some(time);
+        clocks.push((
+            ply,
+            Clock {
+                millis: u32::try_from(time.checked_mul(unit)?).ok()?,
+                decimals: clock_decimals,
+            },
+        ));
+    }
+
+    // only the padding of the last byte may be left
+    (reader.len() < 8).then_some((clocks, increment))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, Clock};
+
+    #[test]
+    fn parse() {
+        for (text, millis, decimals) in [
+            ("0:03:00", 180_000, 0),
+            ("1:02:03", 3_723_000, 0),
+            ("0:00:04.5", 4_500, 1),
+            ("12:00:00.125", 43_200_125, 3),
+        ] {
+            assert_eq!(Clock::parse(text), Some(Clock { millis, decimals }));
+            assert_eq!(Clock { millis, decimals }.to_string(), text);
+        }
+
+        // text that would not be written back the same way
+        for text in [
+            "00:03:00",
+            "3:00",
+            "0:3:00",
+            "0:00:60",
+            "0:00:01.",
+            "0:00:01.1234",
+            "x",
+        ] {
+            assert_eq!(Clock::parse(text), None, "{text}");
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let clock = |millis, decimals| Clock { millis, decimals };
+
+        let clocks = vec![
+            (1, clock(180_000, 0)),
+            (2, clock(180_000, 0)),
+            (3, clock(178_000, 0)),
+            (4, clock(175_000, 0)),
+            (6, clock(170_000, 0)),
+            (7, clock(181_000, 0)),
+        ];
+
+        let data = encode(&clocks, 2000);
+
+        assert_eq!(decode(&data), Some((clocks.clone(), 2000)));
+
+        // the flags, increment, last ply, order and about a byte per clock
+        assert!(data.len() <= 5 + clocks.len(), "{data:?}");
+
+        let mixed = vec![
+            (0, clock(59_900, 1)),
+            (1, clock(60_000, 0)),
+            (5, clock(5, 3)),
+        ];
+
+        assert_eq!(decode(&encode(&mixed, 0)), Some((mixed, 0)));
+        assert_eq!(decode(&encode(&[], 0)), Some((Vec::new(), 0)));
+    }
+
+    #[test]
+    fn rejects_invalid_sections() {
+        let data = encode(
+            &[(
+                1,
+                Clock {
+                    millis: 1000,
+                    decimals: 0,
+                },
+            )],
+            0,
+        );
+
+        assert_eq!(decode(&data[..data.len() - 1]), None);
+        assert_eq!(decode(&[data.as_slice(), &[0]].concat()), None);
+        assert_eq!(decode(&[8, 0, 0, 0]), None);
+        assert_eq!(decode(&[0, 0, 200, 1, 0, 0]), None);
+    }
+}
@@ -0,0 +1,209 @@
+//! The evaluation channel, which stores the `[%eval]` engine evaluation after each move
+//!
+//! The section is a varint count of evaluations followed by, for each one, the distance to the
+//! ply of the previous one, or to ply 0 for the first one, as a varint and a varint code. The low
+//! two bits of the code are 3 for a mate, with the zig-zag encoded number of moves in the rest of
+//! the code. Otherwise they are the number of decimals subtracted from 2 and the rest is the
+//! zig-zag encoded difference in centipawns from the previous evaluation in centipawns, or from 0
+//! for the first one, so the small changes between consecutive moves take a byte.
+
+use std::fmt;
+
+use crate::varint::{read_varint, write_varint, zigzag_decode, zigzag_encode};
+
+const MATE: u64 = 3;
+
+/// An engine evaluation from the point of view of white
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Eval {
+    /// An advantage in centipawns, written in pawns with `decimals` digits after the point, from 0
+    /// to 2
+    Centipawns { value: i32, decimals: u8 },
+    /// Mate in the given number of moves, which is negative when black mates
+    Mate(i32),
+}
+
+impl Eval {
+    /// Parses the value of an `[%eval]` command such as `0.34`, `-1.5` or `#-3`, returning `None`
+    /// unless the evaluation is written back as the same text
+    pub fn parse(text: &str) -> Option<Self> {
+        let eval = match text.strip_prefix('#') {
+            Some(mate) => Eval::Mate(mate.parse().ok()?),
+            None => {
+                let (pawns, fraction) = text.split_once('.').unwrap_or((text, ""));
+
+                if fraction.len() > 2 || !fraction.bytes().all(|byte| byte.is_ascii_digit()) {
+                    return None;
+                }
+
+                let sign = if pawns.starts_with('-') { -1 } else { 1 };
+                let centipawns = format!("{fraction:0<2}").parse::<i32>().ok()?;
+
+                Eval::Centipawns {
+                    value: pawns
+                        .parse::<i32>()
+                        .ok()?
+                        .checked_mul(100)?
+                        .checked_add(sign * centipawns)?,
+                    decimals: fraction.len() as u8,
+                }
+            }
+        };
+
+        (eval.to_string() == text).then_some(eval)
+    }
+}
+
+impl fmt::Display for Eval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Eval::Centipawns { value, decimals } => {
+                let sign = if value < 0 { "-" } else { "" };
+                let value = value.unsigned_abs();
+
+                write!(f, "{sign}{}", value / 100)?;
+
+                match decimals {
+                    0 => Ok(()),
+                    1 => write!(f, ".{}", value % 100 / 10),
+                    _ => write!(f, ".{:02}", value % 100),
+                }
+            }
+            Eval::Mate(moves) => write!(f, "#{moves}"),
+        }
+    }
+}
+
+/// Creates the contents of the evaluation section from the evaluations by ply in order
+pub(super) fn encode(evals: &[(usize, Eval)]) -> Vec<u8> {
+    let mut result = Vec::new();
+
+    write_varint(&mut result, evals.len() as u64);
+
+    let mut previous_ply = 0;
+    let mut previous = 0;
+
+    for (ply, eval) in evals {
+        write_varint(&mut result, (ply - previous_ply) as u64);
+        previous_ply = *ply;
+
+        let code = match *eval {
+            Eval::Centipawns { value, decimals } => {
+                let difference = value as i64 - previous;
+                previous = value as i64;
+
+                zigzag_encode(difference) << 2 | (2 - decimals.min(2) as u64)
+            }
+            Eval::Mate(moves) => zigzag_encode(moves as i64) << 2 | MATE,
+        };
+
+        write_varint(&mut result, code);
+    }
+
+    result
+}
+
+/// Reads the contents of an evaluation section, returning the evaluations by ply in order, or
+/// `None` if the section is invalid
+pub(super) fn decode(mut data: &[u8]) -> Option<Vec<(usize, Eval)>> {
+    let mut next = || {
+        let (value, length) = read_varint(data)?;
+        data = &data[length..];
+        Some(value)
+    };
+
+    let count = next()?;
+    let mut evals = Vec::new();
+    let mut ply = 0usize;
+    let mut previous = 0i64;
+
+    for index in 0..count {
+        let delta = usize::try_from(next()?).ok()?;
+
+        // plies are strictly increasing, except that the first one may be ply 0
+        if index > 0 && delta == 0 {
+            return None;
+        }
+
+        ply = ply.checked_add(delta)?;
+
+        let code = next()?;
+        let value = zigzag_decode(code >> 2);
+
+        let eval = match code & 3 {
+            MATE => Eval::Mate(i32::try_from(value).ok()?),
+            decimals => {
+                previous = previous.checked_add(value)?;
+
+                Eval::Centipawns {
+                    value: i32::try_from(previous).ok()?,
+                    decimals: 2 - decimals as u8,
+                }
+            }
+        };
+
+        evals.push((ply, eval));
+    }
+
+    data.is_empty().then_some(evals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, Eval};
+
+    #[test]
+    fn parse() {
+        let centipawns = |value, decimals| Eval::Centipawns { value, decimals };
+
+        for (text, eval) in [
+            ("0.34", centipawns(34, 2)),
+            ("-0.05", centipawns(-5, 2)),
+            ("-1.5", centipawns(-150, 1)),
+            ("12", centipawns(1200, 0)),
+            ("#3", Eval::Mate(3)),
+            ("#-3", Eval::Mate(-3)),
+        ] {
+            assert_eq!(Eval::parse(text), Some(eval), "{text}");
+            assert_eq!(eval.to_string(), text);
+        }
+
+        for text in [
+            "", "-0.00", "+0.3", "0.345", "1.", ".5", "0.-5", "#", "#+2", "0.3,20",
+        ] {
+            assert_eq!(Eval::parse(text), None, "{text}");
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let centipawns = |value| Eval::Centipawns { value, decimals: 2 };
+
+        let evals = vec![
+            (1, centipawns(20)),
+            (2, centipawns(34)),
+            (3, Eval::Mate(-4)),
+            (4, centipawns(-1200)),
+            (
+                6,
+                Eval::Centipawns {
+                    value: -1190,
+                    decimals: 1,
+                },
+            ),
+        ];
+
+        let data = encode(&evals);
+
+        assert_eq!(decode(&data), Some(evals));
+
+        // the count, then a byte for the ply and the code of each evaluation, except for the codes
+        // of the first evaluation and the jump from the mate
+        assert_eq!(data.len(), 1 + 5 * 2 + 2);
+
+        assert_eq!(decode(&encode(&[])), Some(Vec::new()));
+        assert_eq!(decode(&data[..data.len() - 1]), None);
+        assert_eq!(decode(&[data.as_slice(), &[0]].concat()), None);
+        assert_eq!(decode(&[2, 1, 0, 0, 0]), None);
+    }
+}
@@ -0,0 +1,435 @@
+//! A container for storing many encoded games in one file
+//!
+//! An archive starts with the magic bytes `ASCA` and a version byte. Every game follows as a varint
+//! length and the bytes of the game. After the last game is an index of the offset of every game
+//! as a little endian `u64`, and the archive ends with a footer holding the offset of the index and
+//! the number of games as little endian `u64`s followed by the magic bytes again. The footer lets a
+//! reader find game N with a single lookup, while the length prefixes let the games be read in order
+//! without the index.
+
+use std::{
+    error::Error,
+    fmt,
+    io::{self, Read, Seek, SeekFrom, Write},
+};
+
+use crate::{
+    outcome::Outcome,
+    reader::{DecodeError, Reader},
+    varint::{read_varint, write_varint},
+    writer::Writer,
+};
+
+const MAGIC: [u8; 4] = *b"ASCA";
+const VERSION: u8 = 1;
+
+const HEADER_LENGTH: u64 = MAGIC.len() as u64 + 1;
+const FOOTER_LENGTH: u64 = 16 + MAGIC.len() as u64;
+
+/// An error produced while reading an archive
+#[derive(Debug)]
+pub enum ArchiveError {
+    Io(io::Error),
+    /// The data does not start or end with the archive magic bytes
+    InvalidMagic,
+    /// The archive was written by a newer version of the format
+    UnsupportedVersion(u8),
+    /// The index or a length prefix points outside of the archive
+    Corrupt,
+    /// The requested game is past the end of the archive
+    OutOfRange(usize),
+    /// The game was read but could not be decoded
+    Decode(DecodeError),
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::Io(error) => write!(f, "{error}"),
+            ArchiveError::InvalidMagic => write!(f, "the data is not an archive"),
+            ArchiveError::UnsupportedVersion(version) => {
+                write!(f, "archive version {version} is not supported")
+            }
+            ArchiveError::Corrupt => write!(f, "the archive is corrupt"),
+            ArchiveError::OutOfRange(game) => write!(f, "the archive has no game {game}"),
+            ArchiveError::Decode(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl Error for ArchiveError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ArchiveError::Io(error) => Some(error),
+            ArchiveError::Decode(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ArchiveError {
+    fn from(error: io::Error) -> Self {
+        if error.kind() == io::ErrorKind::UnexpectedEof {
+            ArchiveError::Corrupt
+        } else {
+            ArchiveError::Io(error)
+        }
+    }
+}
+
+/// Writes games to an archive one at a time
+///
+/// Only the offsets of the games are kept in memory. The index is written by `finish`, so an
+/// archive that is never finished can not be opened.
+pub struct ArchiveWriter<W: Write> {
+    inner: W,
+    offsets: Vec<u64>,
+    position: u64,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    /// Starts an archive by writing its header
+    pub fn new(mut inner: W) -> io::Result<Self> {
+        inner.write_all(&MAGIC)?;
+        inner.write_all(&[VERSION])?;
+
+        Ok(Self {
+            inner,
+            offsets: Vec::new(),
+            position: HEADER_LENGTH,
+        })
+    }
+
+    /// Adds the data of an encoded game
+    pub fn add_game(&mut self, data: &[u8]) -> io::Result<()> {
+        let mut prefix = Vec::new();
+        write_varint(&mut prefix, data.len() as u64);
+
+        self.inner.write_all(&prefix)?;
+        self.inner.write_all(data)?;
+
+        self.offsets.push(self.position);
+        self.position += (prefix.len() + data.len()) as u64;
+
+        Ok(())
+    }
+
+    /// Finishes the game in a writer and adds it
+    pub fn add_writer(&mut self, writer: Writer, outcome: Option<Outcome>) -> io::Result<()> {
+        self.add_game(&writer.get_data(outcome))
+    }
+
+    /// Returns the number of games that have been added
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Writes the index and footer, returning the underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        let mut index = Vec::with_capacity(self.offsets.len() * 8 + FOOTER_LENGTH as usize);
+
+        for offset in &self.offsets {
+            index.extend_from_slice(&offset.to_le_bytes());
+        }
+
+        index.extend_from_slice(&self.position.to_le_bytes());
+        index.extend_from_slice(&(self.offsets.len() as u64).to_le_bytes());
+        index.extend_from_slice(&MAGIC);
+
+        self.inner.write_all(&index)?;
+        self.inner.flush()?;
+
+        Ok(self.inner)
+    }
+}
+
+/// Reads games from an archive, either in order as an iterator or by their index with `get_data`
+pub struct ArchiveReader<R: Read + Seek> {
+    inner: R,
+    index_offset: u64,
+    length: usize,
+    /// The game the iterator will read next
+    next: usize,
+    /// Whether `inner` is at the start of game `next`
+    in_place: bool,
+}
+
+impl<R: Read + Seek> ArchiveReader<R> {
+    /// Opens an archive by reading its header and footer
+    pub fn new(mut inner: R) -> Result<Self, ArchiveError> {
+        let mut header = [0; HEADER_LENGTH as usize];
+        inner.seek(SeekFrom::Start(0))?;
+        inner
+            .read_exact(&mut header)
+            .map_err(|error| match error.kind() {
+                io::ErrorKind::UnexpectedEof => ArchiveError::InvalidMagic,
+                _ => ArchiveError::Io(error),
+            })?;
+
+        if header[..MAGIC.len()] != MAGIC {
+            return Err(ArchiveError::InvalidMagic);
+        }
+
+        if header[MAGIC.len()] != VERSION {
+            return Err(ArchiveError::UnsupportedVersion(header[MAGIC.len()]));
+        }
+
+        let end = inner.seek(SeekFrom::End(0))?;
+
+        if end < HEADER_LENGTH + FOOTER_LENGTH {
+            return Err(ArchiveError::Corrupt);
+        }
+
+        let mut footer = [0; FOOTER_LENGTH as usize];
+        inner.seek(SeekFrom::Start(end - FOOTER_LENGTH))?;
+        inner.read_exact(&mut footer)?;
+
+        if footer[16..] != MAGIC {
+            return Err(ArchiveError::InvalidMagic);
+        }
+
+        let index_offset = u64::from_le_bytes(footer[..8].try_into().unwrap());
+        let length = u64::from_le_bytes(footer[8..16].try_into().unwrap());
+
+        if index_offset < HEADER_LENGTH
+            || length
+                .checked_mul(8)
+                .and_then(|index| index.checked_add(index_offset)?.checked_add(FOOTER_LENGTH))
+                != Some(end)
+        {
+            return Err(ArchiveError::Corrupt);
+        }
+
+        Ok(Self {
+            inner,
+            index_offset,
+            length: length as usize,
+            next: 0,
+            in_place: false,
+        })
+    }
+
+    /// Returns the number of games in the archive
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Reads the data of a game by its index, after which iteration continues from the game after it
+    pub fn get_data(&mut self, game: usize) -> Result<Vec<u8>, ArchiveError> {
+        if game >= self.length {
+            return Err(ArchiveError::OutOfRange(game));
+        }
+
+        let mut offset = [0; 8];
+        self.inner
+            .seek(SeekFrom::Start(self.index_offset + game as u64 * 8))?;
+        self.inner.read_exact(&mut offset)?;
+
+        let offset = u64::from_le_bytes(offset);
+
+        if !(HEADER_LENGTH..self.index_offset).contains(&offset) {
+            return Err(ArchiveError::Corrupt);
+        }
+
+        self.inner.seek(SeekFrom::Start(offset))?;
+        self.next = game;
+        self.in_place = true;
+
+        self.read_next()
+    }
+
+    /// Reads a game by its index and starts decoding it
+    pub fn get_reader(&mut self, game: usize) -> Result<Reader<'static>, ArchiveError> {
+        Reader::try_from_vec(self.get_data(game)?).map_err(ArchiveError::Decode)
+    }
+
+    /// Returns the underlying reader
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Reads the length prefixed game that `inner` is positioned at
+    fn read_next(&mut self) -> Result<Vec<u8>, ArchiveError> {
+        let mut prefix = Vec::new();
+
+        let length = loop {
+            let mut byte = [0];
+            self.inner.read_exact(&mut byte)?;
+            prefix.push(byte[0]);
+
+            if byte[0] & 0x80 == 0 {
+                break read_varint(&prefix).ok_or(ArchiveError::Corrupt)?.0;
+            }
+
+            if prefix.len() == 10 {
+                return Err(ArchiveError::Corrupt);
+            }
+        };
+
+        let position = self.inner.stream_position()?;
+
+        if length > self.index_offset.saturating_sub(position) {
+            self.in_place = false;
+            return Err(ArchiveError::Corrupt);
+        }
+
+        let mut data = vec![0; length as usize];
+        self.inner.read_exact(&mut data)?;
+
+        self.next += 1;
+
+        Ok(data)
+    }
+}
+
+impl<R: Read + Seek> Iterator for ArchiveReader<R> {
+    type Item = Result<Vec<u8>, ArchiveError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.length {
+            return None;
+        }
+
+        if !self.in_place {
+            return Some(self.get_data(self.next));
+        }
+
+        let result = self.read_next();
+
+        if result.is_err() {
+            // skip the game rather than trying to read it forever
+            self.next += 1;
+            self.in_place = false;
+        }
+
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use chess::{Board, ChessMove, Square};
+
+    use crate::{outcome::Outcome, writer::Writer};
+
+    use super::{ArchiveError, ArchiveReader, ArchiveWriter};
+
+    fn get_games() -> Vec<Vec<u8>> {
+        (0..300)
+            .map(|game| {
+                let mut writer = Writer::new();
+                let chess = Board::default();
+
+                let chess_move = if game % 2 == 0 {
+                    ChessMove::new(Square::E2, Square::E4, None)
+                } else {
+                    ChessMove::new(Square::G1, Square::F3, None)
+                };
+
+                writer.add_move(&chess_move, &chess);
+
+                writer.get_data(Some(Outcome::Draw))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let games = get_games();
+        let mut writer = ArchiveWriter::new(Vec::new()).unwrap();
+
+        for game in &games {
+            writer.add_game(game).unwrap();
+        }
+
+        assert_eq!(writer.len(), 300);
+
+        let archive = writer.finish().unwrap();
+        let mut reader = ArchiveReader::new(Cursor::new(archive)).unwrap();
+
+        assert_eq!(reader.len(), 300);
+        assert_eq!(reader.get_data(151).unwrap(), games[151]);
+        assert_eq!(
+            reader.next().unwrap().unwrap(),
+            games[152],
+            "iteration continues after a lookup"
+        );
+        assert_eq!(
+            reader.get_reader(0).unwrap().next().unwrap().0,
+            ChessMove::new(Square::E2, Square::E4, None)
+        );
+        assert!(matches!(
+            reader.get_data(300),
+            Err(ArchiveError::OutOfRange(300))
+        ));
+
+        let mut reader = ArchiveReader::new(Cursor::new(reader.into_inner().into_inner())).unwrap();
+
+        assert_eq!(
+            reader.by_ref().collect::<Result<Vec<_>, _>>().unwrap(),
+            games
+        );
+    }
+
+    #[test]
+    fn empty_archive() {
+        let archive = ArchiveWriter::new(Vec::new()).unwrap().finish().unwrap();
+        let mut reader = ArchiveReader::new(Cursor::new(archive)).unwrap();
+
+        assert!(reader.is_empty());
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_archives() {
+        let mut writer = ArchiveWriter::new(Vec::new()).unwrap();
+        writer.add_game(&get_games()[0]).unwrap();
+        let archive = writer.finish().unwrap();
+
+        assert!(matches!(
+            ArchiveReader::new(Cursor::new(&archive[..archive.len() - 1])),
+            Err(ArchiveError::InvalidMagic)
+        ));
+        assert!(matches!(
+            ArchiveReader::new(Cursor::new(&archive[1..])),
+            Err(ArchiveError::InvalidMagic)
+        ));
+
+        let mut newer = archive.clone();
+        newer[4] = 2;
+
+        assert!(matches!(
+            ArchiveReader::new(Cursor::new(newer)),
+            Err(ArchiveError::UnsupportedVersion(2))
+        ));
+
+        let mut truncated = archive.clone();
+        truncated.remove(6);
+
+        assert!(matches!(
+            ArchiveReader::new(Cursor::new(truncated)),
+            Err(ArchiveError::Corrupt)
+        ));
+
+        let mut bad_offset = archive.clone();
+        let index = bad_offset.len() - 28;
+        bad_offset[index] = 0xFF;
+
+        assert!(matches!(
+            ArchiveReader::new(Cursor::new(bad_offset))
+                .unwrap()
+                .get_data(0),
+            Err(ArchiveError::Corrupt)
+        ));
+    }
+}
@@ -1,44 +1,65 @@
-#[derive(Clone)]
+//! A packed stream of bits used for the overflow section
+//!
+//! Bits are stored least significant first in `u64` words and read back in the order they were
+//! written. When converted to bytes the stream is laid out from the end of the buffer: the first
+//! bit written is the lowest bit of the last byte, and the unused high bits of the first byte are
+//! zero.
+
+/// A growable bit stream with a read cursor
+#[derive(Clone, Default)]
 pub struct BitBuffer {
-    data: Vec<bool>,
+    words: Vec<u64>,
+    /// The number of bits that have been written
+    length: usize,
+    /// The number of bits that have been read
+    cursor: usize,
 }
 
 impl BitBuffer {
     pub fn new() -> Self {
-        Self { data: Vec::new() }
+        Self::default()
     }
 
-    pub fn add(&mut self, data: u8, length: u8) {
-        assert!(length <= 8);
+    /// Writes the lowest `length` bits of `data`, least significant first
+    pub fn add(&mut self, data: u64, length: u8) {
+        assert!(length <= 64);
 
-        for i in 0..length {
-            self.add_bit(((data >> i) & 1) == 1);
+        if length == 0 {
+            return;
         }
+
+        let data = if length < 64 {
+            data & ((1 << length) - 1)
+        } else {
+            data
+        };
+
+        let offset = self.length % 64;
+
+        if offset == 0 {
+            self.words.push(0);
+        }
+
+        *self.words.last_mut().unwrap() |= data << offset;
+
+        if offset + length as usize > 64 {
+            self.words.push(data >> (64 - offset));
+        }
+
+        self.length += length as usize;
     }
 
     pub fn add_bit(&mut self, data: bool) {
-        self.data.insert(0, data);
+        self.add(data as u64, 1);
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut byte_pos = 0;
-        let mut current_byte = 0u8;
-        let mut result = Vec::new();
-
-        for bit in self.data.iter().rev() {
-            current_byte |= (if *bit { 1 } else { 0 }) << byte_pos;
-            byte_pos += 1;
-
-            if byte_pos == 8 {
-                result.push(current_byte);
-                current_byte = 0u8;
-                byte_pos = 0;
-            }
-        }
-
-        if byte_pos != 0 {
-            result.push(current_byte);
-        }
+        let mut result: Vec<u8> = self
+            .words
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .take(self.length.div_ceil(8))
+            .collect();
 
         result.reverse();
 
@@ -46,29 +67,142 @@ impl BitBuffer {
     }
 
     pub fn from_bytes(data: &[u8]) -> Self {
-        let mut bit_buffer = Self::new();
+        let words = data
+            .rchunks(8)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .rev()
+                    .enumerate()
+                    .fold(0, |word, (index, byte)| {
+                        word | (*byte as u64) << (index * 8)
+                    })
+            })
+            .collect();
+
+        Self {
+            words,
+            length: data.len() * 8,
+            cursor: 0,
+        }
+    }
+
+    pub fn read_bit(&mut self) -> bool {
+        self.read(1) == 1
+    }
+
+    /// The number of bits that have not been read yet
+    pub fn len(&self) -> usize {
+        self.length - self.cursor
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 
-        for byte in data.iter().rev() {
-            for i in 0..8 {
-                bit_buffer.add_bit(((byte >> i) & 1) == 1);
-            }
+    /// Reads `length` bits, or returns `None` without consuming anything if there are not enough left
+    pub fn try_read(&mut self, length: u8) -> Option<u64> {
+        if self.len() < length as usize {
+            None
+        } else {
+            Some(self.read(length))
         }
+    }
+
+    /// Reads `length` bits in the order they were written, with the first bit as the least
+    /// significant
+    pub fn read(&mut self, length: u8) -> u64 {
+        assert!(length <= 64);
+        assert!(
+            self.len() >= length as usize,
+            "Tried to read past the end of the bit buffer"
+        );
+
+        if length == 0 {
+            return 0;
+        }
+
+        let word = self.cursor / 64;
+        let offset = self.cursor % 64;
+
+        let mut result = self.words[word] >> offset;
+
+        if offset + length as usize > 64 {
+            result |= self.words[word + 1] << (64 - offset);
+        }
+
+        if length < 64 {
+            result &= (1 << length) - 1;
+        }
+
+        self.cursor += length as usize;
 
-        bit_buffer
+        result
     }
+}
 
-    pub fn read_bit(&mut self) -> bool {
-        self.data.pop().expect("Tried to read an empty bit buffer")
+/// Reads a bit stream laid out by `BitBuffer::to_bytes` in place, without copying it
+#[derive(Clone, Copy)]
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    /// The number of bits that have been read
+    cursor: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self::with_cursor(data, 0)
     }
 
-    pub fn read(&mut self, length: u8) -> u8 {
-        assert!(length <= 8);
+    /// Creates a reader that has already read `cursor` bits
+    pub fn with_cursor(data: &'a [u8], cursor: usize) -> Self {
+        assert!(cursor <= data.len() * 8);
 
-        let mut result = 0u8;
+        Self { data, cursor }
+    }
+
+    /// The number of bits that have been read
+    pub fn get_cursor(&self) -> usize {
+        self.cursor
+    }
 
-        for i in 0..length {
-            let bit = self.read_bit();
-            result |= (if bit { 1 } else { 0 }) << i;
+    /// The number of bits that have not been read yet
+    pub fn len(&self) -> usize {
+        self.data.len() * 8 - self.cursor
+    }
+
+    /// Reads `length` bits, or returns `None` without consuming anything if there are not enough left
+    pub fn try_read(&mut self, length: u8) -> Option<u64> {
+        if self.len() < length as usize {
+            None
+        } else {
+            Some(self.read(length))
+        }
+    }
+
+    /// Reads `length` bits in the order they were written, with the first bit as the least
+    /// significant
+    pub fn read(&mut self, length: u8) -> u64 {
+        assert!(length <= 64);
+        assert!(
+            self.len() >= length as usize,
+            "Tried to read past the end of the bit reader"
+        );
+
+        let mut result = 0u64;
+        let mut read = 0;
+
+        while read < length as usize {
+            // the stream starts at the last byte
+            let byte = self.data[self.data.len() - 1 - self.cursor / 8];
+            let offset = self.cursor % 8;
+            let count = (8 - offset).min(length as usize - read);
+
+            let bits = (byte >> offset) as u64 & ((1 << count) - 1);
+            result |= bits << read;
+
+            read += count;
+            self.cursor += count;
         }
 
         result
@@ -77,13 +211,13 @@ impl BitBuffer {
 
 #[cfg(test)]
 mod tests {
-    use super::BitBuffer;
+    use super::{BitBuffer, BitReader};
 
     #[test]
     fn simple_read_write() {
         let mut bit_buffer = BitBuffer::new();
 
-        let test_val: u8 = 0b110;
+        let test_val: u64 = 0b110;
 
         bit_buffer.add(test_val, 3);
         assert_eq!(bit_buffer.read(3), test_val)
@@ -121,6 +255,75 @@ mod tests {
         assert_eq!(bit_buffer.read(5), 0b11001);
     }
 
+    #[test]
+    fn try_read_exhausted() {
+        let mut bit_buffer = BitBuffer::new();
+
+        bit_buffer.add(0b101, 3);
+
+        assert_eq!(bit_buffer.try_read(4), None);
+        assert_eq!(bit_buffer.try_read(3), Some(0b101));
+        assert_eq!(bit_buffer.len(), 0);
+    }
+
+    #[test]
+    fn wide_read_write() {
+        let mut bit_buffer = BitBuffer::new();
+
+        bit_buffer.add(0b101, 3);
+        bit_buffer.add(0xDEAD_BEEF_CAFE, 48);
+        bit_buffer.add(u64::MAX, 64);
+        bit_buffer.add(0x1234, 13);
+
+        let mut bit_buffer = BitBuffer::from_bytes(&bit_buffer.to_bytes());
+
+        assert_eq!(bit_buffer.read(3), 0b101);
+        assert_eq!(bit_buffer.read(48), 0xDEAD_BEEF_CAFE);
+        assert_eq!(bit_buffer.read(64), u64::MAX);
+        assert_eq!(bit_buffer.read(13), 0x1234 & 0x1FFF);
+        assert_eq!(bit_buffer.len(), 0);
+    }
+
+    #[test]
+    fn read_in_place() {
+        let mut bit_buffer = BitBuffer::new();
+
+        bit_buffer.add(0b101, 3);
+        bit_buffer.add(0xDEAD_BEEF_CAFE, 48);
+        bit_buffer.add(u64::MAX, 64);
+
+        let bytes = bit_buffer.to_bytes();
+        let mut bit_reader = BitReader::new(&bytes);
+
+        assert_eq!(bit_reader.read(3), 0b101);
+        assert_eq!(bit_reader.read(48), 0xDEAD_BEEF_CAFE);
+
+        let mut resumed = BitReader::with_cursor(&bytes, bit_reader.get_cursor());
+
+        assert_eq!(resumed.read(64), u64::MAX);
+        assert_eq!(resumed.len(), 5);
+        assert_eq!(resumed.try_read(6), None);
+        assert_eq!(resumed.try_read(5), Some(0));
+    }
+
+    #[test]
+    fn byte_layout() {
+        let mut bit_buffer = BitBuffer::new();
+
+        bit_buffer.add(0b110, 3);
+        bit_buffer.add(0b1101, 4);
+        bit_buffer.add(0b11, 2);
+
+        // the first bit written is the lowest bit of the last byte
+        assert_eq!(bit_buffer.to_bytes(), vec![0b1, 0b11101110]);
+
+        let mut bit_buffer = BitBuffer::from_bytes(&[0b1, 0b11101110]);
+
+        assert_eq!(bit_buffer.len(), 16);
+        assert_eq!(bit_buffer.read(9), 0b111101110);
+        assert_eq!(bit_buffer.read(7), 0);
+    }
+
     #[test]
     fn exam_test() {
         // Triggers the last if statement
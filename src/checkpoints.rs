@@ -0,0 +1,184 @@
+//! Snapshots of the reader taken every few moves of the main line, so that `Reader::seek` only
+//! replays the moves since the closest one instead of the whole game
+//!
+//! Checkpoints are stored in the `CHECKPOINTS_SECTION` of the header. The section is the interval
+//! in plies as a varint, followed by a varint count of checkpoints and, for each one:
+//!
+//! * the position, packed as described in `position`
+//! * the number of core bytes read, as a varint counted from the previous checkpoint
+//! * the number of overflow bits read, as a varint counted from the previous checkpoint
+//! * for games with Chess960 castling rights that the `chess` crate can not hold, a byte with the
+//!   rights that are left as packed by `Castling::to_bits`
+//!
+//! Checkpoint `n`, counting from 1, is the state of the reader right before the core byte of the
+//! main line move that leads to ply `n` times the interval, so reaching any ply from the closest
+//! checkpoint before it takes at most the interval in moves.
+
+use chess::Board;
+
+use crate::{
+    chess960::Castling,
+    position::{decode_position, encode_position},
+    varint::{read_varint, write_varint},
+};
+
+/// The state of a reader before one of the moves of the main line
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Checkpoint {
+    /// The number of moves of the main line before the checkpoint
+    pub ply: usize,
+    pub position: Board,
+    pub castling: Castling,
+    /// The number of core bytes before the move, including variation markers
+    pub core_cursor: usize,
+    /// The number of overflow bits before the move
+    pub overflow_cursor: usize,
+}
+
+/// Creates the contents of the checkpoint section from checkpoints taken every `interval` plies of
+/// a game that starts with the castling rights `start`
+pub(crate) fn encode(interval: usize, checkpoints: &[Checkpoint], start: &Castling) -> Vec<u8> {
+    let mut result = Vec::new();
+
+    write_varint(&mut result, interval as u64);
+    write_varint(&mut result, checkpoints.len() as u64);
+
+    let mut core_cursor = 0;
+    let mut overflow_cursor = 0;
+
+    for checkpoint in checkpoints {
+        result.extend(encode_position(&checkpoint.position));
+        write_varint(&mut result, (checkpoint.core_cursor - core_cursor) as u64);
+        write_varint(
+            &mut result,
+            (checkpoint.overflow_cursor - overflow_cursor) as u64,
+        );
+
+        if !start.is_empty() {
+            result.push(checkpoint.castling.to_bits());
+        }
+
+        core_cursor = checkpoint.core_cursor;
+        overflow_cursor = checkpoint.overflow_cursor;
+    }
+
+    result
+}
+
+/// Reads the contents of a checkpoint section of a game that starts with the castling rights
+/// `start`, returning the interval and the checkpoints in order, or `None` if the section is
+/// invalid
+pub(crate) fn decode(mut data: &[u8], start: &Castling) -> Option<(usize, Vec<Checkpoint>)> {
+    let next = |data: &mut &[u8]| {
+        let (value, length) = read_varint(data)?;
+        *data = &data[length..];
+        usize::try_from(value).ok()
+    };
+
+    let interval = next(&mut data).filter(|interval| *interval > 0)?;
+    let count = next(&mut data)?;
+
+    // every position takes at least ten bytes, which bounds the count before anything is allocated
+    let mut checkpoints = Vec::with_capacity(count.min(data.len() / 10));
+    let mut core_cursor = 0usize;
+    let mut overflow_cursor = 0usize;
+
+    for index in 1..=count {
+        let (position, length) = decode_position(data)?;
+        data = &data[length..];
+
+        core_cursor = core_cursor.checked_add(next(&mut data)?)?;
+        overflow_cursor = overflow_cursor.checked_add(next(&mut data)?)?;
+
+        let castling = if start.is_empty() {
+            *start
+        } else {
+            let (bits, rest) = data.split_first()?;
+            data = rest;
+            start.with_bits(*bits)?
+        };
+
+        checkpoints.push(Checkpoint {
+            ply: index.checked_mul(interval)? - 1,
+            position,
+            castling,
+            core_cursor,
+            overflow_cursor,
+        });
+    }
+
+    data.is_empty().then_some((interval, checkpoints))
+}
+
+#[cfg(test)]
+mod tests {
+    use chess::{Board, ChessMove, Color, Square};
+
+    use crate::chess960::{get_start_position, Castling};
+
+    use super::{decode, encode, Checkpoint};
+
+    #[test]
+    fn round_trip() {
+        let after_e4 = Board::default().make_move_new(ChessMove::new(Square::E2, Square::E4, None));
+        let none = Castling::default();
+
+        let checkpoints = vec![
+            Checkpoint {
+                ply: 1,
+                position: after_e4,
+                castling: none,
+                core_cursor: 1,
+                overflow_cursor: 0,
+            },
+            Checkpoint {
+                ply: 3,
+                position: Board::default(),
+                castling: none,
+                core_cursor: 5,
+                overflow_cursor: 3,
+            },
+        ];
+
+        let data = encode(2, &checkpoints, &none);
+
+        assert_eq!(decode(&data, &none), Some((2, checkpoints)));
+        assert_eq!(decode(&encode(8, &[], &none), &none), Some((8, Vec::new())));
+
+        assert_eq!(decode(&data[..data.len() - 1], &none), None);
+        assert_eq!(decode(&[data.as_slice(), &[0]].concat(), &none), None);
+        assert_eq!(decode(&[0, 0], &none), None);
+    }
+
+    #[test]
+    fn chess960_castling() {
+        let position = get_start_position(0).unwrap();
+        let start = Castling::from_start_index(0);
+
+        // white moves its king, which loses both of its rights
+        let mut castling = start;
+        castling.update(&position, ChessMove::new(Square::G1, Square::G2, None));
+
+        assert_eq!(castling.get_rook(Color::White, true), None);
+        assert_eq!(castling.get_rook(Color::Black, true), Some(Square::H8));
+
+        let checkpoints = vec![Checkpoint {
+            ply: 0,
+            position,
+            castling,
+            core_cursor: 0,
+            overflow_cursor: 0,
+        }];
+
+        let data = encode(1, &checkpoints, &start);
+
+        assert_eq!(decode(&data, &start), Some((1, checkpoints)));
+
+        // a game that starts without the rights of white can not get them back
+        let mut corrupt = data.clone();
+        *corrupt.last_mut().unwrap() = 0b1111;
+
+        assert_eq!(decode(&corrupt, &start).unwrap().1[0].castling, start);
+        assert_eq!(decode(&corrupt, &castling), None);
+    }
+}
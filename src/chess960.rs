@@ -0,0 +1,518 @@
+//! Chess960 start positions, their Scharnagl numbering and castling from them
+//!
+//! The `chess` crate can only castle with the king on the e-file and the rooks in the corners, so
+//! the start positions that are set up that way, like the standard one (number 518), castle with
+//! the castling rights of the `Board`. The castling rights of every other start position are kept
+//! by `Castling`, which plays castling written as the king moving onto the square of its own rook.
+
+use std::fmt;
+
+use chess::{
+    between, get_bishop_moves, get_king_moves, get_knight_moves, get_pawn_attacks, get_rook_moves,
+    BitBoard, Board, BoardBuilder, CastleRights, ChessMove, Color, File, Piece, Rank, Square,
+    ALL_COLORS, EMPTY,
+};
+
+/// The number of Chess960 start positions
+pub const START_POSITIONS: u16 = 960;
+
+/// The number of the standard start position
+pub const STANDARD_START: u16 = 518;
+
+/// The ways to place two knights on five squares, in Scharnagl order
+const KNIGHTS: [(usize, usize); 10] = [
+    (0, 1),
+    (0, 2),
+    (0, 3),
+    (0, 4),
+    (1, 2),
+    (1, 3),
+    (1, 4),
+    (2, 3),
+    (2, 4),
+    (3, 4),
+];
+
+/// Returns the back rank of a start position from its number, from the a-file to the h-file
+pub fn get_back_rank(index: u16) -> Option<[Piece; 8]> {
+    if index >= START_POSITIONS {
+        return None;
+    }
+
+    let mut rank: [Option<Piece>; 8] = [None; 8];
+    let mut index = index as usize;
+
+    // light squared bishop on b, d, f or h, then the dark squared one on a, c, e or g
+    rank[index % 4 * 2 + 1] = Some(Piece::Bishop);
+    index /= 4;
+    rank[index % 4 * 2] = Some(Piece::Bishop);
+    index /= 4;
+
+    let mut place = |piece, nth: usize| {
+        let file = (0..8)
+            .filter(|file| rank[*file].is_none())
+            .nth(nth)
+            .unwrap();
+        rank[file] = Some(piece);
+    };
+
+    place(Piece::Queen, index % 6);
+    index /= 6;
+
+    let (first, second) = KNIGHTS[index];
+
+    // placing the first knight shifts the empty squares after it down by one
+    place(Piece::Knight, first);
+    place(Piece::Knight, second - 1);
+
+    place(Piece::Rook, 0);
+    place(Piece::King, 0);
+    place(Piece::Rook, 0);
+
+    Some(rank.map(Option::unwrap))
+}
+
+/// Returns a start position from its number
+///
+/// Only positions with the king on the e-file have castling rights in the `Board`, the rights of
+/// the others are returned by `Castling::from_start_index`.
+pub fn get_start_position(index: u16) -> Option<Board> {
+    let back_rank = get_back_rank(index)?;
+    let mut builder = BoardBuilder::new();
+
+    // the rooks are always on either side of the king, so they are in the corners too
+    if back_rank[File::E.to_index()] == Piece::King
+        && back_rank[File::A.to_index()] == Piece::Rook
+        && back_rank[File::H.to_index()] == Piece::Rook
+    {
+        builder.castle_rights(Color::White, CastleRights::Both);
+        builder.castle_rights(Color::Black, CastleRights::Both);
+    }
+
+    for (file, piece) in back_rank.into_iter().enumerate() {
+        let file = File::from_index(file);
+
+        builder.piece(Square::make_square(Rank::First, file), piece, Color::White);
+        builder.piece(
+            Square::make_square(Rank::Second, file),
+            Piece::Pawn,
+            Color::White,
+        );
+        builder.piece(
+            Square::make_square(Rank::Seventh, file),
+            Piece::Pawn,
+            Color::Black,
+        );
+        builder.piece(Square::make_square(Rank::Eighth, file), piece, Color::Black);
+    }
+
+    Board::try_from(builder).ok()
+}
+
+/// Returns the number of a position if it is a Chess960 start position
+pub fn get_start_index(position: &Board) -> Option<u16> {
+    let back_rank: Vec<Piece> = (0..8)
+        .map(|file| position.piece_on(Square::make_square(Rank::First, File::from_index(file))))
+        .collect::<Option<_>>()?;
+
+    let find = |piece, files: &[usize]| {
+        files
+            .iter()
+            .position(|file| back_rank[*file] == piece)
+            .map(|index| (index, files[index]))
+    };
+
+    let (light, _) = find(Piece::Bishop, &[1, 3, 5, 7])?;
+    let (dark, _) = find(Piece::Bishop, &[0, 2, 4, 6])?;
+
+    let mut empty: Vec<usize> = (0..8)
+        .filter(|file| back_rank[*file] != Piece::Bishop)
+        .collect();
+
+    let (queen, file) = find(Piece::Queen, &empty)?;
+    empty.retain(|empty| *empty != file);
+
+    let knights: Vec<usize> = (0..empty.len())
+        .filter(|index| back_rank[empty[*index]] == Piece::Knight)
+        .collect();
+    let knights = KNIGHTS
+        .iter()
+        .position(|pair| knights[..] == [pair.0, pair.1])?;
+
+    let index = ((knights * 6 + queen) * 4 + dark) * 4 + light;
+
+    Some(index as u16).filter(|index| get_start_position(*index).as_ref() == Some(position))
+}
+
+/// Returns a FEN of a start position with its castling rights, which are written as Shredder-FEN
+/// if the `chess` crate can not castle from it
+pub fn get_start_fen(index: u16) -> Option<String> {
+    let position = get_start_position(index)?;
+    let castling = Castling::from_start_index(index);
+    let fen = position.to_string();
+
+    if castling.is_empty() {
+        return Some(fen);
+    }
+
+    Some(fen.replacen(" w - - ", &format!(" w {castling} - "), 1))
+}
+
+/// The castling rights of a game from a Chess960 start position that the `chess` crate can not
+/// castle from, which are the squares of the rooks that can still castle
+///
+/// Castling is written as the king moving onto the square of the rook it castles with, as UCI does
+/// for Chess960, and puts the king and the rook on the same squares as in standard chess. Games
+/// from any other position have no rights here, as the `Board` holds them. The rights can not be
+/// told from the position alone, since a start position may have lost them, so they are taken from
+/// the castling field of a FEN or from a game stored as a Chess960 start position.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Castling {
+    /// The rook on the h-file side of the king then the one on the a-file side, for white then
+    /// black
+    rooks: [[Option<Square>; 2]; 2],
+}
+
+impl Castling {
+    /// Returns all four rights of the start position with number `index`, which are only set if
+    /// the `chess` crate can not castle from it
+    pub fn from_start_index(index: u16) -> Self {
+        let Some(back_rank) = get_back_rank(index) else {
+            return Self::default();
+        };
+
+        if back_rank[File::E.to_index()] == Piece::King
+            && back_rank[File::A.to_index()] == Piece::Rook
+            && back_rank[File::H.to_index()] == Piece::Rook
+        {
+            return Self::default();
+        }
+
+        // the rooks are always on either side of the king
+        let rooks: Vec<File> = (0..8)
+            .filter(|file| back_rank[*file] == Piece::Rook)
+            .map(File::from_index)
+            .collect();
+
+        Self {
+            rooks: ALL_COLORS.map(|color| {
+                [rooks[1], rooks[0]]
+                    .map(|file| Some(Square::make_square(color.to_my_backrank(), file)))
+            }),
+        }
+    }
+
+    /// Whether there are no rights left, which is always the case in games that the `chess` crate
+    /// can castle in
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns the square of the rook that `color` can still castle with toward the h-file, or
+    /// toward the a-file if `kingside` is false
+    pub fn get_rook(&self, color: Color, kingside: bool) -> Option<Square> {
+        self.rooks[color.to_index()][!kingside as usize]
+    }
+
+    /// Returns the castling moves that are legal in `position`
+    pub fn get_moves(&self, position: &Board) -> Vec<ChessMove> {
+        let color = position.side_to_move();
+        let king = position.king_square(color);
+
+        self.rooks[color.to_index()]
+            .iter()
+            .flatten()
+            .map(|rook| ChessMove::new(king, *rook, None))
+            .filter(|chess_move| self.is_legal_castle(position, *chess_move))
+            .collect()
+    }
+
+    /// Returns whether a move is legal in `position`, counting castling with these rights
+    pub fn legal(&self, position: &Board, chess_move: ChessMove) -> bool {
+        position.legal(chess_move) || self.is_legal_castle(position, chess_move)
+    }
+
+    /// Removes the rights that a move loses, which are both rights of the side that moves its king
+    /// and the right of every rook that is moved or captured
+    pub fn update(&mut self, position: &Board, chess_move: ChessMove) {
+        let squares = [chess_move.get_source(), chess_move.get_dest()].map(Some);
+
+        if position.piece_on(chess_move.get_source()) == Some(Piece::King) {
+            self.rooks[position.side_to_move().to_index()] = [None; 2];
+        }
+
+        for rook in self.rooks.iter_mut().flatten() {
+            if squares.contains(rook) {
+                *rook = None;
+            }
+        }
+    }
+
+    /// Plays a legal move like `make_move` and removes the rights it loses
+    pub fn make_move(&mut self, position: &Board, chess_move: ChessMove) -> Board {
+        self.update(position, chess_move);
+        make_move(position, chess_move)
+    }
+
+    /// Packs the rights that are left into the low four bits, in the order they are stored
+    pub(crate) fn to_bits(self) -> u8 {
+        self.rooks
+            .iter()
+            .flatten()
+            .enumerate()
+            .map(|(index, rook)| (rook.is_some() as u8) << index)
+            .sum()
+    }
+
+    /// Keeps the rights whose bit is set by `to_bits`, returning `None` if a bit is set for a right
+    /// that is not held
+    pub(crate) fn with_bits(mut self, bits: u8) -> Option<Self> {
+        if bits >> 4 != 0 || bits & !self.to_bits() != 0 {
+            return None;
+        }
+
+        for (index, rook) in self.rooks.iter_mut().flatten().enumerate() {
+            if bits >> index & 1 == 0 {
+                *rook = None;
+            }
+        }
+
+        Some(self)
+    }
+
+    fn is_legal_castle(&self, position: &Board, chess_move: ChessMove) -> bool {
+        let color = position.side_to_move();
+        let (king, rook) = (chess_move.get_source(), chess_move.get_dest());
+
+        let Some(side) = self.rooks[color.to_index()]
+            .iter()
+            .position(|other| *other == Some(rook))
+        else {
+            return false;
+        };
+
+        let kingside = side == 0;
+
+        if chess_move.get_promotion().is_some()
+            || king != position.king_square(color)
+            || king.get_rank() != rook.get_rank()
+            || (rook.get_file() > king.get_file()) != kingside
+            || position.piece_on(rook) != Some(Piece::Rook)
+            || position.color_on(rook) != Some(color)
+            || *position.checkers() != EMPTY
+        {
+            return false;
+        }
+
+        let (king_to, rook_to) = get_castle_squares(color, kingside);
+        let king_path = get_span(king, king_to);
+
+        // the king and the rook may pass over each other, but nothing else
+        let occupied =
+            *position.combined() ^ BitBoard::from_square(king) ^ BitBoard::from_square(rook);
+
+        (king_path | get_span(rook, rook_to)) & occupied == EMPTY
+            && king_path
+                .into_iter()
+                .all(|square| !is_attacked(position, square, occupied))
+    }
+}
+
+impl fmt::Display for Castling {
+    /// Writes the rights as the castling field of a Shredder-FEN, such as `HAha`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "-");
+        }
+
+        for (color, rooks) in ALL_COLORS.iter().zip(self.rooks) {
+            for rook in rooks.into_iter().flatten() {
+                let file = (b'a' + rook.get_file().to_index() as u8) as char;
+
+                write!(
+                    f,
+                    "{}",
+                    match color {
+                        Color::White => file.to_ascii_uppercase(),
+                        Color::Black => file,
+                    }
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns whether a move is castling written as the king moving onto the square of its own rook
+pub fn is_castle(position: &Board, chess_move: ChessMove) -> bool {
+    position.piece_on(chess_move.get_source()) == Some(Piece::King)
+        && position.color_on(chess_move.get_dest()) == Some(position.side_to_move())
+}
+
+/// Plays a legal move, including castling written as the king moving onto its own rook
+pub fn make_move(position: &Board, chess_move: ChessMove) -> Board {
+    if !is_castle(position, chess_move) {
+        return position.make_move_new(chess_move);
+    }
+
+    let color = position.side_to_move();
+    let (king, rook) = (chess_move.get_source(), chess_move.get_dest());
+    let (king_to, rook_to) = get_castle_squares(color, rook.get_file() > king.get_file());
+
+    let mut builder = BoardBuilder::from(position);
+
+    builder
+        .clear_square(king)
+        .clear_square(rook)
+        .piece(king_to, Piece::King, color)
+        .piece(rook_to, Piece::Rook, color)
+        .side_to_move(!color)
+        .en_passant(None);
+
+    Board::try_from(builder).expect("Castling is only played when it is legal")
+}
+
+/// Returns where the king and the rook end up after castling
+fn get_castle_squares(color: Color, kingside: bool) -> (Square, Square) {
+    let rank = color.to_my_backrank();
+    let (king, rook) = if kingside {
+        (File::G, File::F)
+    } else {
+        (File::C, File::D)
+    };
+
+    (
+        Square::make_square(rank, king),
+        Square::make_square(rank, rook),
+    )
+}
+
+/// Returns the squares from `from` to `to` on a rank, including both
+fn get_span(from: Square, to: Square) -> BitBoard {
+    between(from, to) | BitBoard::from_square(from) | BitBoard::from_square(to)
+}
+
+/// Returns whether the side that is not to move attacks `square` when `occupied` is in the way
+fn is_attacked(position: &Board, square: Square, occupied: BitBoard) -> bool {
+    let color = position.side_to_move();
+    let pieces = |piece| *position.pieces(piece) & *position.color_combined(!color);
+    let queens = pieces(Piece::Queen);
+
+    get_rook_moves(square, occupied) & (pieces(Piece::Rook) | queens)
+        | get_bishop_moves(square, occupied) & (pieces(Piece::Bishop) | queens)
+        | get_knight_moves(square) & pieces(Piece::Knight)
+        | get_king_moves(square) & pieces(Piece::King)
+        | get_pawn_attacks(square, color, pieces(Piece::Pawn))
+        != EMPTY
+}
+
+#[cfg(test)]
+mod tests {
+    use chess::{Board, ChessMove, Color, Piece, Square};
+
+    use super::{
+        get_back_rank, get_start_fen, get_start_index, get_start_position, is_castle, Castling,
+        STANDARD_START, START_POSITIONS,
+    };
+
+    #[test]
+    fn known_positions() {
+        use Piece::*;
+
+        assert_eq!(
+            get_back_rank(0),
+            Some([Bishop, Bishop, Queen, Knight, Knight, Rook, King, Rook])
+        );
+        assert_eq!(
+            get_back_rank(STANDARD_START),
+            Some([Rook, Knight, Bishop, Queen, King, Bishop, Knight, Rook])
+        );
+        assert_eq!(
+            get_back_rank(959),
+            Some([Rook, King, Rook, Knight, Knight, Queen, Bishop, Bishop])
+        );
+        assert_eq!(get_back_rank(960), None);
+
+        assert_eq!(get_start_position(STANDARD_START), Some(Board::default()));
+        assert_eq!(
+            get_start_fen(0).as_deref(),
+            Some("bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w HFhf - 0 1")
+        );
+    }
+
+    #[test]
+    fn index_round_trip() {
+        for index in 0..START_POSITIONS {
+            let position = get_start_position(index).unwrap();
+
+            assert_eq!(get_start_index(&position), Some(index));
+        }
+
+        let moved = Board::default().make_move_new("e2e4".parse().unwrap());
+
+        assert_eq!(get_start_index(&moved), None);
+
+        let castling: Board = "rqnnkbbr/pppppppp/8/8/8/8/PPPPPPPP/RQNNKBBR w KQkq - 0 1"
+            .parse()
+            .unwrap();
+
+        // arrays with the king on the e-file keep the castling rights of the `chess` crate
+        assert_eq!(get_start_index(&castling), Some(414));
+        assert_eq!(get_start_position(414), Some(castling));
+        assert_eq!(
+            get_start_fen(414).as_deref(),
+            Some("rqnnkbbr/pppppppp/8/8/8/8/PPPPPPPP/RQNNKBBR w KQkq - 0 1")
+        );
+    }
+
+    #[test]
+    fn castling() {
+        let mut position = get_start_position(0).unwrap();
+        let mut castling = Castling::from_start_index(0);
+
+        assert_eq!(castling.to_string(), "HFhf");
+        assert_eq!(castling.get_rook(Color::White, false), Some(Square::F1));
+        assert!(castling.get_moves(&position).is_empty());
+        assert!(Castling::from_start_index(STANDARD_START).is_empty());
+        assert!(Castling::from_start_index(414).is_empty());
+
+        for uci in [
+            "e1d3", "e8d6", "d1e3", "d8e6", "c2c3", "c7c6", "c1c2", "c8c7",
+        ] {
+            position = castling.make_move(&position, uci.parse().unwrap());
+        }
+
+        // the king passes over its queenside rook on f1, the kingside rook can not reach f1
+        let queenside = ChessMove::new(Square::G1, Square::F1, None);
+
+        assert_eq!(castling.get_moves(&position), vec![queenside]);
+        assert!(!castling.legal(&position, ChessMove::new(Square::G1, Square::H1, None)));
+        assert!(is_castle(&position, queenside));
+
+        position = castling.make_move(&position, queenside);
+
+        assert_eq!(
+            position.to_string(),
+            "bb3rkr/ppqppppp/2pnn3/8/8/2PNN3/PPQPPPPP/BBKR3R b - - 0 1"
+        );
+        assert_eq!(castling.to_string(), "hf");
+
+        // moving the rook on f8 only loses the queenside right, and clears the way for the other one
+        position = castling.make_move(&position, "f8e8".parse().unwrap());
+        position = castling.make_move(&position, "d3c5".parse().unwrap());
+
+        let kingside = ChessMove::new(Square::G8, Square::H8, None);
+
+        assert_eq!(castling.to_string(), "h");
+        assert_eq!(castling.get_moves(&position), vec![kingside]);
+
+        position = castling.make_move(&position, kingside);
+
+        assert_eq!(
+            position.to_string(),
+            "bb2rrk1/ppqppppp/2pnn3/2N5/8/2P1N3/PPQPPPPP/BBKR3R w - - 0 1"
+        );
+        assert!(castling.is_empty());
+    }
+}
@@ -0,0 +1,418 @@
+//! An entropy coded "max compression" form of a game
+//!
+//! Instead of describing each move by its target square, the legal moves of every position are
+//! ranked from the most to the least likely with a deterministic heuristic and the rank of the
+//! played move is written with an adaptive range coder. Sensible moves are near the top of the
+//! ranking, so they take a few bits each, at the cost of generating and scoring every legal move
+//! on both ends.
+//!
+//! The game always starts with a header carrying the `ENTROPY` flag, followed by the number of
+//! plies as a varint, the same terminator byte a `Writer` ends the core stream with and the coded
+//! ranks up to the end of the buffer, or the checksum if there is one. A game without moves has no
+//! coded ranks. `Reader` decodes it like any other game, and rejects coded ranks that end before
+//! the last move or go on after it.
+//!
+//! A corrupted coded stream usually still decodes to legal moves, so pair this with a checksum for
+//! cold storage.
+
+mod coder;
+mod ranking;
+
+use chess::{Board, ChessMove};
+
+use crate::{
+    annotations::{Annotations, Clock, Eval},
+    chess960::Castling,
+    header::ENTROPY,
+    outcome::{Outcome, Termination},
+    reader::DecodeErrorKind,
+    varint::write_varint,
+    writer::{EncodeError, EncodeErrorKind, Extras},
+};
+
+use self::{
+    coder::{Decoder, Encoder, RankModel, IMPLIED_ZEROS, MAX_RANKS_PER_BYTE},
+    ranking::rank_moves,
+};
+
+/// Encodes a game in the entropy coded form, with the same interface as `Writer`
+#[derive(Clone)]
+pub struct EntropyWriter {
+    encoder: Encoder,
+    model: RankModel,
+    plies: usize,
+    /// The Chess960 castling rights of the position the next move is played from
+    castling: Castling,
+    extras: Extras,
+}
+
+impl Default for EntropyWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EntropyWriter {
+    /// Creates a new empty instance of the `EntropyWriter`
+    pub fn new() -> Self {
+        Self {
+            encoder: Encoder::new(),
+            model: RankModel::new(),
+            plies: 0,
+            castling: Castling::default(),
+            extras: Extras::new(Board::default()),
+        }
+    }
+
+    /// Creates a new empty instance of the `EntropyWriter` for a game that starts from `position`
+    ///
+    /// The position has no Chess960 castling rights, use `with_castling` for those
+    pub fn with_start_position(position: Board) -> Self {
+        Self {
+            extras: Extras::new(position),
+            ..Self::new()
+        }
+    }
+
+    /// Creates a new empty instance of the `EntropyWriter` for a game that starts from a Chess960
+    /// start position with the castling rights `castling`, which are only kept if they are all
+    /// four rights of the start position
+    pub fn with_castling(position: Board, castling: Castling) -> Self {
+        let mut writer = Self::with_start_position(position);
+
+        writer.castling = writer.extras.set_castling(castling);
+        writer
+    }
+
+    /// Sets why the game ended, which is stored in the header
+    pub fn set_termination(&mut self, termination: Termination) {
+        self.extras.termination = termination;
+    }
+
+    /// Sets the bytes stored in the metadata section of the header
+    pub fn set_metadata(&mut self, metadata: Vec<u8>) {
+        self.extras.metadata = Some(metadata);
+    }
+
+    /// Sets the tag pairs stored in the tag section of the header, such as `Event` or `White`
+    pub fn set_tags(&mut self, tags: Vec<(String, String)>) {
+        self.extras.tags = tags;
+    }
+
+    /// Adds a comment after the last move that was added, or before the first move if there are
+    /// none yet
+    pub fn add_comment(&mut self, comment: impl Into<String>) {
+        self.extras.annotations.add_comment(self.plies, comment);
+    }
+
+    /// Sets the time left on the clock of the side that made the last move that was added
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.extras.annotations.set_clock(self.plies, clock);
+    }
+
+    /// Sets the engine evaluation of the position after the last move that was added
+    pub fn set_eval(&mut self, eval: Eval) {
+        self.extras.annotations.set_eval(self.plies, eval);
+    }
+
+    /// Sets the increment of the time control in milliseconds, which makes the clocks smaller
+    pub fn set_clock_increment(&mut self, increment: u32) {
+        self.extras.annotations.set_clock_increment(increment);
+    }
+
+    /// Replaces the annotations of the game, which are keyed by ply
+    pub fn set_annotations(&mut self, annotations: Annotations) {
+        self.extras.annotations = annotations;
+    }
+
+    /// Adds a numeric annotation glyph to the last move that was added
+    pub fn add_nag(&mut self, nag: u8) {
+        self.extras.annotations.add_nag(self.plies, nag);
+    }
+
+    /// Sets whether the output ends with a CRC-32 of its contents
+    pub fn set_checksum(&mut self, checksum: bool) {
+        self.extras.checksum = checksum;
+    }
+
+    /// Adds a move to the output
+    ///
+    /// # Panics
+    ///
+    /// Panics if the move is illegal, use `try_add_move` to handle this
+    pub fn add_move(&mut self, chess_move: &ChessMove, position: &Board) {
+        self.try_add_move(chess_move, position)
+            .expect("Could not encode move");
+    }
+
+    /// Checks that the move is legal before adding it to the output. Nothing is added if an error
+    /// is returned.
+    pub fn try_add_move(
+        &mut self,
+        chess_move: &ChessMove,
+        position: &Board,
+    ) -> Result<(), EncodeError> {
+        let rank = rank_moves(position, &self.castling)
+            .iter()
+            .position(|legal| legal == chess_move)
+            .ok_or(EncodeError {
+                kind: EncodeErrorKind::IllegalMove(*chess_move),
+                ply: self.plies,
+            })?;
+
+        self.model.encode(&mut self.encoder, rank);
+        self.castling.update(position, *chess_move);
+        self.plies += 1;
+
+        Ok(())
+    }
+
+    /// Consumes self and creates a vector of bytes that represents the data
+    pub fn get_data(self, outcome: Option<Outcome>) -> Vec<u8> {
+        let mut result = self
+            .extras
+            .write_header(&self.extras.get_sections(), ENTROPY);
+
+        write_varint(&mut result, self.plies as u64);
+        result.push(outcome.unwrap_or_default().get_id());
+        // the decoder of a game without moves never reads, so nothing is flushed for it
+        if self.plies > 0 {
+            result.extend(self.encoder.finish());
+        }
+
+        self.extras.write_checksum(&mut result);
+
+        result
+    }
+}
+
+/// The state `Reader` keeps to decode the coded ranks of a game
+#[derive(Clone)]
+pub(crate) struct EntropyDecoder {
+    decoder: Decoder,
+    model: RankModel,
+    plies: usize,
+}
+
+impl EntropyDecoder {
+    /// Starts decoding the coded ranks of a game with `plies` moves, returning `TruncatedCore` if
+    /// there are too few of them to hold that many moves
+    pub fn new(data: &[u8], plies: usize) -> Result<Self, DecodeErrorKind> {
+        if plies > data.len().saturating_mul(MAX_RANKS_PER_BYTE) {
+            return Err(DecodeErrorKind::TruncatedCore);
+        }
+
+        Ok(Self {
+            decoder: Decoder::new(data),
+            model: RankModel::new(),
+            plies,
+        })
+    }
+
+    /// Starts decoding the same coded ranks again from the first move
+    pub fn restart(&mut self, data: &[u8]) {
+        self.decoder = Decoder::new(data);
+        self.model = RankModel::new();
+    }
+
+    /// The number of moves in the game
+    pub fn get_plies(&self) -> usize {
+        self.plies
+    }
+
+    /// The offset into the coded ranks of the byte being decoded
+    pub fn get_position(&self) -> usize {
+        self.decoder.get_position()
+    }
+
+    /// Whether the coded ranks have been read up to their end, which they must be once every move
+    /// has been decoded
+    pub fn is_exhausted(&self, data: &[u8]) -> bool {
+        if self.plies == 0 {
+            data.is_empty()
+        } else {
+            self.decoder.get_read() >= data.len() + IMPLIED_ZEROS
+        }
+    }
+
+    /// Decodes the next move from the coded ranks, which must be the same slice every time
+    pub fn decode_move(
+        &mut self,
+        data: &[u8],
+        position: &Board,
+        castling: &Castling,
+    ) -> Result<ChessMove, DecodeErrorKind> {
+        let rank = self.model.decode(&mut self.decoder, data);
+
+        if self.decoder.get_read() > data.len() + IMPLIED_ZEROS {
+            return Err(DecodeErrorKind::TruncatedCore);
+        }
+
+        rank_moves(position, castling)
+            .get(rank)
+            .copied()
+            .ok_or(DecodeErrorKind::RankOutOfRange(rank))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chess::{Board, ChessMove, Square};
+
+    use crate::{
+        outcome::{Outcome, Termination},
+        pgn::{read_game, write_game},
+        reader::{DecodeErrorKind, Reader},
+        varint::write_varint,
+        writer::{EncodeError, EncodeErrorKind},
+    };
+
+    use super::EntropyWriter;
+
+    const OPERA_GAME: &str = "[Event \"Opera Game\"]\n\n1. e4 e5 2. Nf3 d6 3. d4 Bg4 4. dxe5 \
+        Bxf3 5. Qxf3 dxe5 6. Bc4 Nf6 7. Qb3 Qe7 8. Nc3 c6 9. Bg5 b5 10. Nxb5 cxb5 11. Bxb5+ Nbd7 \
+        12. O-O-O Rd8 13. Rxd7 Rxd7 14. Rd1 Qe6 15. Bxd7+ Nxd7 16. Qb8+ Nxb8 17. Rd8# 1-0\n";
+
+    fn encode(data: &[u8], checksum: bool) -> Vec<u8> {
+        let mut reader = Reader::new(data);
+        let mut writer = EntropyWriter::with_start_position(*reader.get_start_position());
+        let mut position = *reader.get_start_position();
+
+        writer.set_checksum(checksum);
+        writer.set_termination(Termination::Checkmate);
+
+        for (chess_move, next) in reader.by_ref() {
+            writer.add_move(&chess_move, &position);
+            position = next;
+        }
+
+        writer.get_data(reader.get_outcome().map(|(outcome, _)| outcome))
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = read_game(OPERA_GAME).unwrap().unwrap().data;
+        let entropy = encode(&data, false);
+
+        let moves = |data: &[u8]| Reader::new(data).collect::<Vec<_>>();
+
+        assert_eq!(moves(&entropy), moves(&data));
+
+        let mut reader = Reader::new(&entropy);
+        reader.by_ref().count();
+
+        assert_eq!(
+            reader.get_outcome(),
+            Some((Outcome::WhiteWon, Termination::Checkmate))
+        );
+
+        // smaller even though the header, ply count and terminator take eleven bytes of it
+        assert!(
+            entropy.len() < data.len() * 4 / 5,
+            "{} bytes",
+            entropy.len()
+        );
+
+        let pgn = write_game(&[], &entropy).unwrap();
+
+        assert!(pgn.ends_with("17. Rd8# 1-0\n"), "{pgn}");
+    }
+
+    #[test]
+    fn custom_start_and_checksum() {
+        let position: Board = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1".parse().unwrap();
+        let mut writer = EntropyWriter::with_start_position(position);
+        writer.set_checksum(true);
+        writer.add_move(&ChessMove::new(Square::E2, Square::E4, None), &position);
+
+        let data = writer.get_data(Some(Outcome::Draw));
+        let mut reader = Reader::new(&data);
+
+        assert_eq!(reader.get_start_position(), &position);
+        assert_eq!(reader.by_ref().count(), 1);
+        assert_eq!(
+            reader.get_outcome(),
+            Some((Outcome::Draw, Termination::Unspecified))
+        );
+
+        let mut corrupt = data.clone();
+        let last = corrupt.len() - 5;
+        corrupt[last] ^= 1;
+
+        assert_eq!(
+            Reader::try_new(&corrupt).err().unwrap().kind,
+            DecodeErrorKind::ChecksumMismatch
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let mut writer = EntropyWriter::new();
+        let chess_move = ChessMove::new(Square::E2, Square::E5, None);
+
+        assert_eq!(
+            writer.try_add_move(&chess_move, &Board::default()),
+            Err(EncodeError {
+                kind: EncodeErrorKind::IllegalMove(chess_move),
+                ply: 0
+            })
+        );
+
+        // claims a move was played from a checkmate
+        let data = read_game(OPERA_GAME).unwrap().unwrap().data;
+        let mut entropy = encode(&data, false);
+        // the ply count follows the nine bytes of the header, two of them for the termination
+        assert_eq!(entropy[9], 33);
+        entropy[9] = 34;
+
+        let error = Reader::new(&entropy)
+            .try_iter()
+            .find_map(Result::err)
+            .unwrap();
+
+        assert_eq!(error.ply, 33);
+        assert!(matches!(error.kind, DecodeErrorKind::RankOutOfRange(_)));
+    }
+
+    #[test]
+    fn stream_length() {
+        let data = read_game(OPERA_GAME).unwrap().unwrap().data;
+        let entropy = encode(&data, false);
+
+        let error = |data: &[u8]| {
+            Reader::new(data)
+                .try_iter()
+                .find_map(Result::err)
+                .unwrap()
+                .kind
+        };
+
+        assert_eq!(
+            error(&entropy[..entropy.len() - 1]),
+            DecodeErrorKind::TruncatedCore
+        );
+        assert_eq!(
+            error(&[entropy.as_slice(), &[1]].concat()),
+            DecodeErrorKind::TrailingData
+        );
+
+        // a ply count that the coded ranks can not possibly hold is rejected up front
+        let mut huge = entropy[..9].to_vec();
+        write_varint(&mut huge, u64::MAX);
+        huge.extend(&entropy[10..]);
+
+        assert_eq!(
+            Reader::try_new(&huge).err().unwrap().kind,
+            DecodeErrorKind::TruncatedCore
+        );
+
+        // a game without moves has no coded ranks
+        let empty = EntropyWriter::new().get_data(None);
+
+        assert_eq!(Reader::new(&empty).count(), 0);
+        assert_eq!(
+            error(&[empty.as_slice(), &[0]].concat()),
+            DecodeErrorKind::TrailingData
+        );
+    }
+}
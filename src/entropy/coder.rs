@@ -0,0 +1,360 @@
+//! An adaptive binary range coder in the style of LZMA
+//!
+//! Every bit is coded with an 11 bit probability of it being zero, which moves towards the bits
+//! that are actually seen. The first byte the encoder produces is always zero, so it is left out,
+//! as are the `IMPLIED_ZEROS` zero bytes it ends with. The decoder reads exactly the bytes the
+//! encoder writes and those zeros, so one that runs further past the end is reading truncated data.
+
+const PROBABILITY_BITS: u32 = 11;
+/// Probabilities stay within these bounds while adapting, so they are clamped to them up front
+const MIN_PROBABILITY: u64 = 31;
+const MAX_PROBABILITY: u64 = (1 << PROBABILITY_BITS) - MIN_PROBABILITY;
+/// How quickly probabilities adapt, as a shift of the distance to the bit that was seen
+const ADAPT_SHIFT: u32 = 6;
+const TOP: u32 = 1 << 24;
+
+/// The most ranks that can be decoded from each byte of a coded stream. A rank takes at least four
+/// bits and none of them is more likely than `MAX_PROBABILITY`, so each costs at least 0.087 bits.
+pub const MAX_RANKS_PER_BYTE: usize = 92;
+
+/// The number of zero bytes every coded stream ends with, which are not written
+pub const IMPLIED_ZEROS: usize = 3;
+
+#[derive(Clone)]
+pub struct Encoder {
+    low: u64,
+    range: u32,
+    cache: u8,
+    cache_size: u64,
+    output: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self {
+            low: 0,
+            range: u32::MAX,
+            cache: 0,
+            cache_size: 1,
+            output: Vec::new(),
+        }
+    }
+
+    pub fn encode_bit(&mut self, probability: &mut u16, bit: bool) {
+        let bound = (self.range >> PROBABILITY_BITS) * *probability as u32;
+
+        if bit {
+            self.low += bound as u64;
+            self.range -= bound;
+            *probability -= *probability >> ADAPT_SHIFT;
+        } else {
+            self.range = bound;
+            *probability += ((1 << PROBABILITY_BITS) - *probability) >> ADAPT_SHIFT;
+        }
+
+        while self.range < TOP {
+            self.range <<= 8;
+            self.shift_low();
+        }
+    }
+
+    fn shift_low(&mut self) {
+        if self.low < 0xFF000000 || self.low >= 1 << 32 {
+            let carry = (self.low >> 32) as u8;
+            let mut byte = self.cache;
+
+            while self.cache_size > 0 {
+                self.output.push(byte.wrapping_add(carry));
+                byte = 0xFF;
+                self.cache_size -= 1;
+            }
+
+            self.cache = (self.low >> 24) as u8;
+        }
+
+        self.cache_size += 1;
+        self.low = (self.low & 0x00FFFFFF) << 8;
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        // any value in the final interval decodes the same, and it is at least `TOP` wide, so one
+        // of them is a multiple of `TOP`, whose last bytes are zero
+        self.low = self.low.next_multiple_of(TOP as u64);
+
+        for _ in 0..5 {
+            self.shift_low();
+        }
+
+        self.output.truncate(self.output.len() - IMPLIED_ZEROS);
+        self.output.split_off(1)
+    }
+}
+
+#[derive(Clone)]
+pub struct Decoder {
+    range: u32,
+    code: u32,
+    /// The offset of the next byte to read, which may be past the end of the data
+    position: usize,
+}
+
+impl Decoder {
+    pub fn new(data: &[u8]) -> Self {
+        let mut decoder = Self {
+            range: u32::MAX,
+            code: 0,
+            position: 0,
+        };
+
+        for _ in 0..4 {
+            decoder.code = decoder.code << 8 | decoder.next_byte(data) as u32;
+        }
+
+        decoder
+    }
+
+    /// The offset of the byte that is being decoded
+    pub fn get_position(&self) -> usize {
+        self.position.saturating_sub(4)
+    }
+
+    /// The number of bytes that have been read, counting the ones past the end of the data, which
+    /// is `IMPLIED_ZEROS` more than the length of the data once the last bit has been decoded
+    pub fn get_read(&self) -> usize {
+        self.position
+    }
+
+    fn next_byte(&mut self, data: &[u8]) -> u8 {
+        let byte = data.get(self.position).copied().unwrap_or(0);
+        self.position += 1;
+        byte
+    }
+
+    pub fn decode_bit(&mut self, data: &[u8], probability: &mut u16) -> bool {
+        let bound = (self.range >> PROBABILITY_BITS) * *probability as u32;
+
+        let bit = if self.code < bound {
+            self.range = bound;
+            *probability += ((1 << PROBABILITY_BITS) - *probability) >> ADAPT_SHIFT;
+            false
+        } else {
+            self.code -= bound;
+            self.range -= bound;
+            *probability -= *probability >> ADAPT_SHIFT;
+            true
+        };
+
+        while self.range < TOP {
+            self.range <<= 8;
+            self.code = self.code << 8 | self.next_byte(data) as u32;
+        }
+
+        bit
+    }
+}
+
+/// A tree of probabilities for coding numbers of a fixed number of bits, most significant first
+#[derive(Clone)]
+struct BitTree {
+    probabilities: Vec<u16>,
+    bits: u32,
+}
+
+/// Returns the probability of a zero bit given the weights of both outcomes
+fn get_probability(zero: u64, one: u64) -> u16 {
+    (zero << PROBABILITY_BITS)
+        .checked_div(zero + one)
+        .unwrap_or(1 << (PROBABILITY_BITS - 1))
+        .clamp(MIN_PROBABILITY, MAX_PROBABILITY) as u16
+}
+
+impl BitTree {
+    /// Creates a tree whose probabilities start out matching the weights of its values
+    fn with_weights(bits: u32, weights: &[u64]) -> Self {
+        let mut probabilities = vec![0; 1 << bits];
+
+        for (node, probability) in probabilities.iter_mut().enumerate().skip(1) {
+            let depth = node.ilog2();
+            let width = 1 << (bits - depth);
+            let start = (node - (1 << depth)) * width;
+
+            let zero = weights[start..start + width / 2].iter().sum();
+            let one = weights[start + width / 2..start + width].iter().sum();
+
+            *probability = get_probability(zero, one);
+        }
+
+        Self {
+            probabilities,
+            bits,
+        }
+    }
+
+    fn encode(&mut self, encoder: &mut Encoder, value: usize) {
+        let mut node = 1;
+
+        for shift in (0..self.bits).rev() {
+            let bit = (value >> shift) & 1 == 1;
+            encoder.encode_bit(&mut self.probabilities[node], bit);
+            node = node << 1 | bit as usize;
+        }
+    }
+
+    fn decode(&mut self, decoder: &mut Decoder, data: &[u8]) -> usize {
+        let mut node = 1;
+
+        for _ in 0..self.bits {
+            let bit = decoder.decode_bit(data, &mut self.probabilities[node]);
+            node = node << 1 | bit as usize;
+        }
+
+        node - (1 << self.bits)
+    }
+}
+
+/// The number of ranks each bucket holds. Moves are mostly near the top of the ranking, so the
+/// first buckets are small and cheap to code.
+const LOW_BITS: u32 = 3;
+const MID_BITS: u32 = 4;
+const HIGH_BITS: u32 = 8;
+
+const LOW_END: usize = 1 << LOW_BITS;
+const MID_END: usize = LOW_END + (1 << MID_BITS);
+
+/// The largest rank that can be coded
+pub const MAX_RANK: usize = MID_END + (1 << HIGH_BITS) - 1;
+
+/// How likely a move is to have a rank before anything is known about the game, proportional to
+/// `(rank + 2) ^ -1.5`. Games are short, so starting from a sensible guess saves more than a model
+/// that has to learn it could.
+fn get_prior(rank: usize) -> u64 {
+    (1 << 32) / ((rank as u64 + 2).pow(3)).isqrt()
+}
+
+/// An adaptive model for the rank of a move, which picks a bucket and then the rank within it
+#[derive(Clone)]
+pub struct RankModel {
+    choices: [u16; 2],
+    low: BitTree,
+    mid: BitTree,
+    high: BitTree,
+}
+
+impl RankModel {
+    pub fn new() -> Self {
+        let weights: Vec<u64> = (0..=MAX_RANK).map(get_prior).collect();
+        let (low, rest) = weights.split_at(LOW_END);
+        let (mid, high) = rest.split_at(MID_END - LOW_END);
+
+        let sum = |weights: &[u64]| weights.iter().sum::<u64>();
+
+        Self {
+            choices: [
+                get_probability(sum(low), sum(rest)),
+                get_probability(sum(mid), sum(high)),
+            ],
+            low: BitTree::with_weights(LOW_BITS, low),
+            mid: BitTree::with_weights(MID_BITS, mid),
+            high: BitTree::with_weights(HIGH_BITS, high),
+        }
+    }
+
+    pub fn encode(&mut self, encoder: &mut Encoder, rank: usize) {
+        assert!(rank <= MAX_RANK);
+
+        encoder.encode_bit(&mut self.choices[0], rank >= LOW_END);
+
+        if rank < LOW_END {
+            return self.low.encode(encoder, rank);
+        }
+
+        encoder.encode_bit(&mut self.choices[1], rank >= MID_END);
+
+        if rank < MID_END {
+            self.mid.encode(encoder, rank - LOW_END);
+        } else {
+            self.high.encode(encoder, rank - MID_END);
+        }
+    }
+
+    pub fn decode(&mut self, decoder: &mut Decoder, data: &[u8]) -> usize {
+        if !decoder.decode_bit(data, &mut self.choices[0]) {
+            return self.low.decode(decoder, data);
+        }
+
+        if !decoder.decode_bit(data, &mut self.choices[1]) {
+            self.mid.decode(decoder, data) + LOW_END
+        } else {
+            self.high.decode(decoder, data) + MID_END
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decoder, Encoder, RankModel, IMPLIED_ZEROS, MAX_RANK, MAX_RANKS_PER_BYTE};
+
+    #[test]
+    fn round_trip() {
+        let mut seed = 7u32;
+        let ranks: Vec<usize> = (0..5000)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+
+                // mostly small ranks with the occasional large one
+                match seed % 10 {
+                    0 => seed as usize % (MAX_RANK + 1),
+                    1..=3 => seed as usize % 24,
+                    _ => seed as usize % 3,
+                }
+            })
+            .collect();
+
+        let mut encoder = Encoder::new();
+        let mut model = RankModel::new();
+
+        for rank in &ranks {
+            model.encode(&mut encoder, *rank);
+        }
+
+        let data = encoder.finish();
+
+        let mut decoder = Decoder::new(&data);
+        let mut model = RankModel::new();
+
+        for rank in &ranks {
+            assert_eq!(model.decode(&mut decoder, &data), *rank);
+        }
+
+        // the skewed ranks take far less than a byte each
+        assert!(data.len() < ranks.len() * 5 / 8, "{} bytes", data.len());
+    }
+
+    #[test]
+    fn exact_length() {
+        for count in [1, 2, 100] {
+            let mut encoder = Encoder::new();
+            let mut model = RankModel::new();
+
+            for _ in 0..count {
+                model.encode(&mut encoder, 0);
+            }
+
+            let data = encoder.finish();
+
+            let mut decoder = Decoder::new(&data);
+            let mut model = RankModel::new();
+
+            for _ in 0..count {
+                assert_eq!(model.decode(&mut decoder, &data), 0);
+            }
+
+            // the decoder stops right after the implied zeros, and a rank takes at least a fraction
+            // of a bit
+            assert_eq!(decoder.get_read(), data.len() + IMPLIED_ZEROS);
+            assert!(count <= data.len() * MAX_RANKS_PER_BYTE);
+        }
+    }
+}
@@ -0,0 +1,290 @@
+//! A deterministic ordering of the legal moves in a position, most likely first
+//!
+//! Moves are scored with cheap static heuristics: the piece-square tables of Tomasz Michniewski's
+//! simplified evaluation function, the material a move captures, promotes to, saves or leaves
+//! hanging, checks and castling. The scores only use integer arithmetic and ties are broken by the squares of the move,
+//! so the encoder and decoder always agree on the order.
+
+use std::cmp::Reverse;
+
+use chess::{
+    get_bishop_moves, get_king_moves, get_knight_moves, get_pawn_attacks, get_rook_moves, BitBoard,
+    Board, ChessMove, Color, MoveGen, Piece, Square, EMPTY,
+};
+
+use crate::chess960::Castling;
+
+#[rustfmt::skip]
+const PAWN: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const KNIGHT: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+
+#[rustfmt::skip]
+const BISHOP: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const ROOK: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const QUEEN: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+     -5,  0,  5,  5,  5,  5,  0, -5,
+      0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const KING: [i32; 64] = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20,
+];
+
+const CHECK_BONUS: i32 = 150;
+const CASTLE_BONUS: i32 = 60;
+
+fn get_value(piece: Piece) -> i32 {
+    match piece {
+        Piece::Pawn => 100,
+        Piece::Knight => 320,
+        Piece::Bishop => 330,
+        Piece::Rook => 500,
+        Piece::Queen => 900,
+        Piece::King => 0,
+    }
+}
+
+/// Returns the piece-square table value of a piece, with the tables written from white's side
+fn get_square_value(piece: Piece, color: Color, square: Square) -> i32 {
+    let table = match piece {
+        Piece::Pawn => &PAWN,
+        Piece::Knight => &KNIGHT,
+        Piece::Bishop => &BISHOP,
+        Piece::Rook => &ROOK,
+        Piece::Queen => &QUEEN,
+        Piece::King => &KING,
+    };
+
+    let rank = square.get_rank().to_index();
+    let file = square.get_file().to_index();
+
+    match color {
+        Color::White => table[(7 - rank) * 8 + file],
+        Color::Black => table[rank * 8 + file],
+    }
+}
+
+/// Returns the value of the least valuable piece of `color` attacking `square`
+fn get_cheapest_attacker(
+    position: &Board,
+    square: Square,
+    color: Color,
+    occupied: BitBoard,
+) -> Option<i32> {
+    let pieces = occupied & position.color_combined(color);
+    let of = |piece| position.pieces(piece) & pieces;
+
+    // squares a piece on `square` reaches are the ones the same kind of piece attacks it from
+    let attackers = [
+        (
+            Piece::Pawn,
+            get_pawn_attacks(square, !color, of(Piece::Pawn)),
+        ),
+        (Piece::Knight, get_knight_moves(square) & of(Piece::Knight)),
+        (
+            Piece::Bishop,
+            get_bishop_moves(square, occupied) & of(Piece::Bishop),
+        ),
+        (
+            Piece::Rook,
+            get_rook_moves(square, occupied) & of(Piece::Rook),
+        ),
+        (
+            Piece::Queen,
+            (get_bishop_moves(square, occupied) | get_rook_moves(square, occupied))
+                & of(Piece::Queen),
+        ),
+        (Piece::King, get_king_moves(square) & of(Piece::King)),
+    ];
+
+    attackers
+        .into_iter()
+        .find(|(_, attackers)| *attackers != EMPTY)
+        .map(|(piece, _)| get_value(piece))
+}
+
+/// Roughly how much material is lost by leaving a piece worth `value` on `square`, which is what
+/// the cheapest attacker wins if the square is defended and the whole piece otherwise
+fn get_threat(
+    position: &Board,
+    square: Square,
+    value: i32,
+    color: Color,
+    occupied: BitBoard,
+) -> i32 {
+    match get_cheapest_attacker(position, square, !color, occupied) {
+        None => 0,
+        Some(_) if get_cheapest_attacker(position, square, color, occupied).is_none() => value,
+        Some(attacker) => (value - attacker).max(0),
+    }
+}
+
+fn get_score(chess_move: ChessMove, position: &Board) -> i32 {
+    let from = chess_move.get_source();
+    let to = chess_move.get_dest();
+    let color = position.side_to_move();
+
+    let piece = position
+        .piece_on(from)
+        .expect("A legal move starts on a piece");
+    let placed = chess_move.get_promotion().unwrap_or(piece);
+
+    let mut score = get_square_value(placed, color, to) - get_square_value(piece, color, from);
+
+    let victim = match position.piece_on(to) {
+        // a pawn moving diagonally onto an empty square captures en passant
+        None if piece == Piece::Pawn && from.get_file() != to.get_file() => Some(Piece::Pawn),
+        victim => victim,
+    };
+
+    if let Some(victim) = victim {
+        score += get_value(victim);
+    }
+
+    if let Some(promotion) = chess_move.get_promotion() {
+        score += get_value(promotion) - get_value(Piece::Pawn);
+    }
+
+    if piece == Piece::King
+        && from
+            .get_file()
+            .to_index()
+            .abs_diff(to.get_file().to_index())
+            == 2
+    {
+        score += CASTLE_BONUS;
+    }
+
+    let occupied = *position.combined();
+
+    // moving a threatened piece saves it, moving it onto a threatened square loses it
+    if piece != Piece::King {
+        let after = (occupied ^ BitBoard::from_square(from)) | BitBoard::from_square(to);
+
+        score += get_threat(position, from, get_value(piece), color, occupied);
+        score -= get_threat(position, to, get_value(placed), color, after);
+    }
+
+    if *position.make_move_new(chess_move).checkers() != EMPTY {
+        score += CHECK_BONUS;
+    }
+
+    score
+}
+
+/// Returns the legal moves of a position with the Chess960 castling rights `castling`, ordered from
+/// the most to the least likely to be played
+pub fn rank_moves(position: &Board, castling: &Castling) -> Vec<ChessMove> {
+    let mut moves: Vec<(i32, ChessMove)> = MoveGen::new_legal(position)
+        .map(|chess_move| (get_score(chess_move, position), chess_move))
+        .chain(
+            castling
+                .get_moves(position)
+                .into_iter()
+                .map(|chess_move| (CASTLE_BONUS, chess_move)),
+        )
+        .collect();
+
+    moves.sort_by_key(|(score, chess_move)| {
+        (
+            Reverse(*score),
+            chess_move.get_source().to_index(),
+            chess_move.get_dest().to_index(),
+            chess_move.get_promotion().map(|piece| piece.to_index()),
+        )
+    });
+
+    moves
+        .into_iter()
+        .map(|(_, chess_move)| chess_move)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chess::{Board, ChessMove};
+
+    use crate::chess960::Castling;
+
+    use super::rank_moves;
+
+    #[test]
+    fn ranking() {
+        let ranked = rank_moves(&Board::default(), &Castling::default());
+
+        assert_eq!(ranked.len(), 20);
+        assert!(ranked[..4].contains(&"e2e4".parse::<ChessMove>().unwrap()));
+
+        let rank = |chess_move: &str| {
+            let chess_move: ChessMove = chess_move.parse().unwrap();
+            ranked.iter().position(|ranked| *ranked == chess_move)
+        };
+
+        assert!(rank("g1f3") < rank("g1h3"));
+        assert!(rank("d2d4") < rank("f2f3"));
+
+        // taking the undefended queen with a pawn comes first
+        let position: Board = "4k3/8/8/3q4/4P3/8/8/4K2Q w - - 0 1".parse().unwrap();
+
+        assert_eq!(
+            rank_moves(&position, &Castling::default())[0],
+            "e4d5".parse().unwrap()
+        );
+    }
+}
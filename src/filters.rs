@@ -1,4 +1,8 @@
-use chess::{Board, Square};
+use chess::{Board, ChessMove, Piece, Square};
+
+use crate::PROMOTION_KEY;
+
+use self::{diagonal::Diagonal, knight::Knight, straight::Straight};
 
 pub mod diagonal;
 pub mod knight;
@@ -11,32 +15,100 @@ pub trait Filter {
         Self::get_raw_id() << 6
     }
 
-    fn get_overflow(to: &Square, from: &Square, position: &Board) -> Option<(u8, u8)> {
+    fn get_overflow_length() -> u8 {
+        2
+    }
+
+    /// Returns the overflow bits needed to tell `from` apart from the other candidates, or an error
+    /// if `from` is not one of the candidates at all
+    fn get_overflow(
+        to: &Square,
+        from: &Square,
+        position: &Board,
+        chess960: bool,
+    ) -> Result<Option<(u8, u8)>, ()> {
         // the number of the overflow bits
-        let square_data = Self::get_square_data(to, position);
+        let square_data = Self::get_square_data(to, position, chess960);
+
+        let index = square_data
+            .iter()
+            .position(|square| matches!(square, Some(square) if square == from))
+            .ok_or(())?;
 
         if square_data.iter().filter(|square| square.is_some()).count() == 1 {
-            None
+            Ok(None)
         } else {
-            Some((
-                square_data
-                    .iter()
-                    .position(|square| {
-                        if let Some(square) = square {
-                            square == from
-                        } else {
-                            false
-                        }
-                    })
-                    .expect("Could not find original square") as u8,
-                2,
-            ))
+            Ok(Some((index as u8, Self::get_overflow_length())))
         }
     }
 
-    fn get_square_data(to: &Square, position: &Board) -> Vec<Option<Square>>;
+    /// Returns the squares of the pieces that can move to `to`, one slot per direction. With
+    /// `chess960` the candidates follow the rules of games flagged `header::CHESS960`, which only
+    /// differ in how castling is handled.
+    fn get_square_data(to: &Square, position: &Board, chess960: bool) -> Vec<Option<Square>>;
+}
+
+/// Returns the candidate squares of the filter with the given raw id
+pub fn get_square_data(
+    raw_id: u8,
+    to: &Square,
+    position: &Board,
+    chess960: bool,
+) -> Vec<Option<Square>> {
+    match raw_id {
+        3 => Straight::get_square_data(to, position, chess960),
+        2 => Diagonal::get_square_data(to, position, chess960),
+        1 => Knight::get_square_data(to, position, chess960),
+        _ => unreachable!("A filter id of 0 is a terminator byte"),
+    }
+}
+
+/// Returns the candidates of a filter that can move to `to`, in the order of `get_square_data`.
+/// Compact overflow codes are an index into this list.
+pub fn get_candidates(raw_id: u8, to: &Square, position: &Board, chess960: bool) -> Vec<Square> {
+    get_square_data(raw_id, to, position, chess960)
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// Returns the promotion pieces that are legal for a pawn move, in the order of `PROMOTION_KEY`
+///
+/// In standard chess this is always all four, but the compact format does not rely on it.
+pub fn get_legal_promotions(from: &Square, to: &Square, position: &Board) -> Vec<Piece> {
+    PROMOTION_KEY
+        .into_iter()
+        .filter(|piece| position.legal(ChessMove::new(*from, *to, Some(*piece))))
+        .collect()
+}
+
+/// Returns the number of bits needed to tell `count` options apart
+pub fn get_code_length(count: usize) -> u8 {
+    (usize::BITS - count.saturating_sub(1).leading_zeros()) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use chess::{Board, Square};
+
+    use super::{get_candidates, get_code_length};
+
+    #[test]
+    fn code_lengths() {
+        assert_eq!(
+            [0, 1, 2, 3, 4, 5, 8, 9].map(get_code_length),
+            [0, 0, 1, 2, 2, 3, 3, 4]
+        );
+    }
+
+    #[test]
+    fn pinned_candidates_are_skipped() {
+        // the knight on d2 is pinned, so only the one on g1 can reach f3
+        let chess: Board = "4k3/8/8/b7/8/8/3N4/4K1N1 w - - 0 1".parse().unwrap();
 
-    fn get_overflow_mask() -> u8 {
-        0b00000011
+        assert_eq!(
+            get_candidates(1, &Square::F3, &chess, false),
+            vec![Square::G1]
+        );
     }
 }
@@ -9,7 +9,7 @@ impl Filter for Diagonal {
         2
     }
 
-    fn get_square_data(to: &Square, position: &Board) -> Vec<Option<Square>> {
+    fn get_square_data(to: &Square, position: &Board, _chess960: bool) -> Vec<Option<Square>> {
         let mut square_data: Vec<Option<Square>> = vec![None; 4];
 
         for square in (to.get_rank().to_index() + 1..8)
@@ -146,11 +146,11 @@ mod tests {
         let chess = Board::try_from(setup).unwrap();
 
         assert_eq!(
-            Diagonal::get_square_data(&Square::B3, &chess),
+            Diagonal::get_square_data(&Square::B3, &chess, false),
             vec![None, None, Some(Square::A2), None]
         );
         assert_eq!(
-            Diagonal::get_square_data(&Square::E4, &chess),
+            Diagonal::get_square_data(&Square::E4, &chess, false),
             vec![Some(Square::G6), Some(Square::H1), None, Some(Square::D5)]
         );
     }
@@ -164,7 +164,7 @@ mod tests {
             .make_move_new(ChessMove::new(Square::B7, Square::B5, None));
 
         assert_eq!(
-            Diagonal::get_square_data(&Square::B6, &chess),
+            Diagonal::get_square_data(&Square::B6, &chess, false),
             vec![None, None, Some(Square::A5), None]
         );
     }
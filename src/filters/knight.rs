@@ -9,7 +9,7 @@ impl Filter for Knight {
         1
     }
 
-    fn get_square_data(to: &Square, position: &Board) -> Vec<Option<Square>> {
+    fn get_square_data(to: &Square, position: &Board, _chess960: bool) -> Vec<Option<Square>> {
         let mut square_data: Vec<Option<Square>> = vec![None; 8];
 
         for (index, square) in [
@@ -45,31 +45,8 @@ impl Filter for Knight {
         square_data
     }
 
-    fn get_overflow(to: &Square, from: &Square, position: &Board) -> Option<(u8, u8)> {
-        // the number of the overflow bits
-        let square_data = Self::get_square_data(to, position);
-
-        if square_data.iter().filter(|square| square.is_some()).count() == 1 {
-            None
-        } else {
-            Some((
-                square_data
-                    .iter()
-                    .position(|square| {
-                        if let Some(square) = square {
-                            square == from
-                        } else {
-                            false
-                        }
-                    })
-                    .expect("Could not find original square") as u8,
-                3,
-            ))
-        }
-    }
-
-    fn get_overflow_mask() -> u8 {
-        0b00000111
+    fn get_overflow_length() -> u8 {
+        3
     }
 }
 
@@ -94,7 +71,7 @@ mod tests {
         let chess = Board::try_from(setup).unwrap();
 
         assert_eq!(
-            Knight::get_square_data(&Square::D4, &chess),
+            Knight::get_square_data(&Square::D4, &chess, false),
             vec![
                 Some(Square::F5),
                 Some(Square::F3),
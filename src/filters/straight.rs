@@ -2,6 +2,17 @@ use chess::{Board, ChessMove, Color, File, Piece, Rank, Square};
 
 use super::Filter;
 
+/// Moves along a rank or a file
+///
+/// Chess960 castling, written as the king moving onto its own rook, is never a candidate. It is
+/// the only move onto a piece of the side to move, so a core byte of this filter whose target holds
+/// one is read as castling instead, see `chess960::Castling`.
+///
+/// A king two files from the target is a candidate under one of two rules. Games without the
+/// `header::CHESS960` flag keep the original one, which lists the king whenever the side still has
+/// the castling right toward the g-file, and toward the c-file only when castling there is legal.
+/// Changing it would change the overflow of every game written before, so Chess960 games list
+/// the king only when the move is legal.
 pub struct Straight {}
 
 impl Filter for Straight {
@@ -9,7 +20,7 @@ impl Filter for Straight {
         3
     }
 
-    fn get_square_data(to: &Square, position: &Board) -> Vec<Option<Square>> {
+    fn get_square_data(to: &Square, position: &Board, chess960: bool) -> Vec<Option<Square>> {
         let mut square_data: Vec<Option<Square>> = vec![None; 4];
 
         for rank in to.get_rank().to_index() + 1..8 {
@@ -28,14 +39,14 @@ impl Filter for Straight {
 
         for file in to.get_file().to_index() + 1..8 {
             // right
-            if horizontal_checks(1, &mut square_data, to, position, file) {
+            if horizontal_checks(1, &mut square_data, to, position, file, chess960) {
                 break;
             }
         }
 
         for file in (0..to.get_file().to_index()).rev() {
             // left
-            if horizontal_checks(3, &mut square_data, to, position, file) {
+            if horizontal_checks(3, &mut square_data, to, position, file, chess960) {
                 break;
             }
         }
@@ -108,6 +119,7 @@ fn horizontal_checks(
     to: &Square,
     position: &Board,
     file: usize,
+    chess960: bool,
 ) -> bool {
     let file = File::from_index(file);
     let square = Square::make_square(to.get_rank(), file);
@@ -122,6 +134,19 @@ fn horizontal_checks(
         return false;
     }
 
+    if chess960 {
+        // a king two files away can only be castling, which `legal` checks like any other move
+        if (piece == Piece::Rook
+            || piece == Piece::Queen
+            || (piece == Piece::King && file.to_index().abs_diff(to.get_file().to_index()) <= 2))
+            && position.legal(ChessMove::new(square, *to, None))
+        {
+            square_data[index] = Some(square);
+        }
+
+        return true;
+    }
+
     if (piece == Piece::Rook
         || piece == Piece::Queen
         || (piece == Piece::King && file.to_index().abs_diff(to.get_file().to_index()) == 1))
@@ -160,7 +185,7 @@ mod tests {
         let chess = Board::default();
 
         assert_eq!(
-            Straight::get_square_data(&Square::A4, &chess),
+            Straight::get_square_data(&Square::A4, &chess, false),
             vec![None, None, Some(Square::A2), None]
         );
     }
@@ -180,7 +205,7 @@ mod tests {
             .make_move_new(ChessMove::new(Square::D7, Square::D6, None));
 
         assert_eq!(
-            Straight::get_square_data(&Square::C1, &chess),
+            Straight::get_square_data(&Square::C1, &chess, false),
             vec![None, Some(Square::E1), None, Some(Square::A1)]
         );
     }
@@ -198,11 +223,11 @@ mod tests {
         let chess = Board::try_from(setup).unwrap();
 
         assert_eq!(
-            Straight::get_square_data(&Square::D4, &chess),
+            Straight::get_square_data(&Square::D4, &chess, false),
             vec![Some(Square::D5), Some(Square::H4), Some(Square::D1), None]
         );
         assert_eq!(
-            Straight::get_square_data(&Square::A4, &chess),
+            Straight::get_square_data(&Square::A4, &chess, false),
             vec![None, None, Some(Square::A2), None]
         );
     }
@@ -0,0 +1,440 @@
+//! The optional header at the start of an encoded game
+//!
+//! A header is the magic bytes `>ASCN`, a version byte and the flags as a varint, followed by the
+//! sections the flags enable in the order below. The first magic byte would be a terminator without an outcome, so a
+//! game without a header can never start with it.
+//!
+//! * `CUSTOM_START` - the start position, packed as described in `position`
+//! * `CHESS960` - the number of a Chess960 start position as a little endian `u16`, used instead
+//!   of `CUSTOM_START` for those positions
+//! * `EXTENDED_OUTCOME` - a byte holding the id of the termination. The terminator byte only has
+//!   room for the outcome, so a game with a termination always has a header.
+//! * `METADATA` - a varint length followed by that many bytes of metadata
+//! * `SECTIONS` - a varint count followed by that many extension sections, each an id byte, a
+//!   varint length and that many bytes. Readers skip sections with ids they do not know, and
+//!   nothing in them is needed to decode the moves, so move-only decoding only reads their
+//!   lengths.
+//!
+//! With `COMPACT_OVERFLOW` an overflow index only counts the candidates that can legally make the
+//! move and is written with as few bits as it takes to tell them apart, so a move with two
+//! candidates takes one bit. Promotions are written the same way over the legal promotion pieces.
+//!
+//! With `ENTROPY` the game that follows is entropy coded, as described in `entropy`.
+//!
+//! With `CHECKSUM` the buffer ends with a little endian CRC-32 of everything before it, after the
+//! overflow section.
+//!
+//! The first seven flags fit in a single byte, so only games that need a later one pay for a second
+//! byte of flags.
+
+use std::ops::Range;
+
+use chess::Board;
+
+use crate::{
+    chess960::{get_start_index, get_start_position},
+    outcome::Termination,
+    position::{decode_position, encode_position},
+    reader::DecodeErrorKind,
+    varint::{read_varint, write_varint},
+};
+
+/// The bytes every buffer with a header starts with
+pub const MAGIC: [u8; 5] = *b">ASCN";
+/// The newest version of the format that can be read and the one that is written
+pub const VERSION: u8 = 1;
+
+/// The game starts from a custom position
+pub const CUSTOM_START: u16 = 1 << 0;
+/// The header holds a metadata section
+pub const METADATA: u16 = 1 << 1;
+/// The buffer ends with a checksum
+pub const CHECKSUM: u16 = 1 << 2;
+/// The game starts from a Chess960 start position
+pub const CHESS960: u16 = 1 << 3;
+/// The overflow codes are as short as possible
+pub const COMPACT_OVERFLOW: u16 = 1 << 4;
+/// The moves are entropy coded ranks instead of core bytes and overflow bits
+pub const ENTROPY: u16 = 1 << 5;
+/// The header holds extension sections
+pub const SECTIONS: u16 = 1 << 6;
+/// The termination is stored in its own byte
+pub const EXTENDED_OUTCOME: u16 = 1 << 7;
+
+const KNOWN_FLAGS: u16 = CUSTOM_START
+    | METADATA
+    | CHECKSUM
+    | CHESS960
+    | COMPACT_OVERFLOW
+    | ENTROPY
+    | SECTIONS
+    | EXTENDED_OUTCOME;
+
+/// The id of the section holding comments and numeric annotation glyphs, see `annotations`
+pub const ANNOTATIONS_SECTION: u8 = 1;
+/// The id of the section holding the clock times of moves, see `annotations::clock`
+pub const CLOCKS_SECTION: u8 = 2;
+/// The id of the section holding the engine evaluations of moves, see `annotations::eval`
+pub const EVALS_SECTION: u8 = 3;
+/// The id of the section holding the tag pairs of the game, see `tags`
+pub const TAGS_SECTION: u8 = 4;
+/// The id of the section holding snapshots for seeking through the game, see `checkpoints`
+pub const CHECKPOINTS_SECTION: u8 = 5;
+/// The id of the section holding the annotations of the moves inside variations, see
+/// `annotations`
+pub const VARIATION_ANNOTATIONS_SECTION: u8 = 6;
+
+/// The version and flags of a buffer that starts with a header
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Header {
+    pub version: u8,
+    pub flags: u16,
+}
+
+impl Header {
+    /// Returns whether every bit of `flag` is set
+    pub fn has_flag(&self, flag: u16) -> bool {
+        self.flags & flag == flag
+    }
+}
+
+/// Returns whether a buffer starts with a header, as opposed to being a legacy buffer without one
+pub fn has_header(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// The contents of a header read from the start of a buffer
+pub(crate) struct HeaderData {
+    pub header: Header,
+    pub start_position: Option<Board>,
+    pub chess960: Option<u16>,
+    pub termination: Termination,
+    /// Where the metadata is in the buffer
+    pub metadata: Option<Range<usize>>,
+    /// The ids of the extension sections and where they are in the buffer
+    pub sections: Vec<(u8, Range<usize>)>,
+    /// The length of the header in bytes
+    pub length: usize,
+}
+
+/// Reads the header at the start of `data`, which must start with the magic bytes
+///
+/// On failure the reason is returned along with the offset of the offending byte
+pub(crate) fn read_header(data: &[u8]) -> Result<HeaderData, (DecodeErrorKind, usize)> {
+    let mut offset = MAGIC.len();
+
+    let version = *data
+        .get(offset)
+        .ok_or((DecodeErrorKind::InvalidHeader, data.len()))?;
+
+    if version == 0 || version > VERSION {
+        return Err((DecodeErrorKind::UnsupportedVersion(version), offset));
+    }
+
+    offset += 1;
+
+    let (flags, length) = data
+        .get(offset..)
+        .and_then(read_varint)
+        .and_then(|(flags, length)| Some((u16::try_from(flags).ok()?, length)))
+        .ok_or((DecodeErrorKind::InvalidHeader, offset))?;
+
+    if flags & !KNOWN_FLAGS != 0 {
+        return Err((DecodeErrorKind::UnknownFlags(flags & !KNOWN_FLAGS), offset));
+    }
+
+    let header = Header { version, flags };
+
+    if header.has_flag(CUSTOM_START | CHESS960) {
+        return Err((DecodeErrorKind::InvalidHeader, offset));
+    }
+
+    offset += length;
+
+    let mut start_position = if header.has_flag(CUSTOM_START) {
+        let (position, length) = decode_position(&data[offset..])
+            .ok_or((DecodeErrorKind::InvalidStartPosition, offset))?;
+
+        offset += length;
+        Some(position)
+    } else {
+        None
+    };
+
+    let chess960 = if header.has_flag(CHESS960) {
+        let index = data
+            .get(offset..offset + 2)
+            .map(|index| u16::from_le_bytes([index[0], index[1]]))
+            .ok_or((DecodeErrorKind::InvalidHeader, offset))?;
+
+        start_position =
+            Some(get_start_position(index).ok_or((DecodeErrorKind::InvalidStartPosition, offset))?);
+
+        offset += 2;
+        Some(index)
+    } else {
+        None
+    };
+
+    let termination = if header.has_flag(EXTENDED_OUTCOME) {
+        let termination = data
+            .get(offset)
+            .and_then(|id| Termination::try_from(*id).ok())
+            .ok_or((DecodeErrorKind::InvalidHeader, offset))?;
+
+        offset += 1;
+        termination
+    } else {
+        Termination::Unspecified
+    };
+
+    let metadata = if header.has_flag(METADATA) {
+        let range = read_block(data, offset)?;
+
+        offset = range.end;
+        Some(range)
+    } else {
+        None
+    };
+
+    let mut sections = Vec::new();
+
+    if header.has_flag(SECTIONS) {
+        let (count, length) = data
+            .get(offset..)
+            .and_then(read_varint)
+            .ok_or((DecodeErrorKind::InvalidHeader, offset))?;
+
+        offset += length;
+
+        for _ in 0..count {
+            let id = *data
+                .get(offset)
+                .ok_or((DecodeErrorKind::InvalidHeader, offset))?;
+
+            if sections.iter().any(|(other, _)| *other == id) {
+                return Err((DecodeErrorKind::InvalidHeader, offset));
+            }
+
+            let range = read_block(data, offset + 1)?;
+
+            offset = range.end;
+            sections.push((id, range));
+        }
+    }
+
+    Ok(HeaderData {
+        header,
+        start_position,
+        chess960,
+        termination,
+        metadata,
+        sections,
+        length: offset,
+    })
+}
+
+/// Reads a varint length at `offset` and returns where the bytes that follow it are
+fn read_block(data: &[u8], offset: usize) -> Result<Range<usize>, (DecodeErrorKind, usize)> {
+    let (length, prefix) = data
+        .get(offset..)
+        .and_then(read_varint)
+        .ok_or((DecodeErrorKind::InvalidHeader, offset))?;
+
+    let start = offset + prefix;
+    let end = start
+        .checked_add(length as usize)
+        .filter(|end| *end <= data.len())
+        .ok_or((DecodeErrorKind::InvalidHeader, offset))?;
+
+    Ok(start..end)
+}
+
+/// Creates a header with the flags for the sections that are given along with `flags`, which are
+/// the flags for features outside of the header
+///
+/// A start position that is a Chess960 start position is stored by its number, and a termination
+/// other than `Termination::Unspecified` in its own byte. Sections are written in the order they
+/// are given.
+pub(crate) fn write_header(
+    start_position: Option<&Board>,
+    termination: &Termination,
+    metadata: Option<&[u8]>,
+    sections: &[(u8, Vec<u8>)],
+    mut flags: u16,
+) -> Vec<u8> {
+    let chess960 = start_position.and_then(get_start_index);
+
+    match (start_position, chess960) {
+        (Some(_), Some(_)) => flags |= CHESS960,
+        (Some(_), None) => flags |= CUSTOM_START,
+        (None, _) => {}
+    }
+
+    if *termination != Termination::Unspecified {
+        flags |= EXTENDED_OUTCOME;
+    }
+
+    if metadata.is_some() {
+        flags |= METADATA;
+    }
+
+    if !sections.is_empty() {
+        flags |= SECTIONS;
+    }
+
+    let mut result = MAGIC.to_vec();
+    result.push(VERSION);
+    write_varint(&mut result, flags.into());
+
+    match (start_position, chess960) {
+        (_, Some(index)) => result.extend(index.to_le_bytes()),
+        (Some(position), None) => result.extend(encode_position(position)),
+        (None, None) => {}
+    }
+
+    if *termination != Termination::Unspecified {
+        result.push(termination.get_id());
+    }
+
+    if let Some(metadata) = metadata {
+        write_varint(&mut result, metadata.len() as u64);
+        result.extend_from_slice(metadata);
+    }
+
+    if !sections.is_empty() {
+        write_varint(&mut result, sections.len() as u64);
+
+        for (id, section) in sections {
+            result.push(*id);
+            write_varint(&mut result, section.len() as u64);
+            result.extend_from_slice(section);
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use chess::Board;
+
+    use crate::{
+        chess960::get_start_position,
+        outcome::{Outcome, Termination},
+        reader::DecodeErrorKind,
+    };
+
+    use super::{
+        read_header, write_header, Header, CHECKSUM, CUSTOM_START, EXTENDED_OUTCOME, MAGIC,
+        METADATA, SECTIONS,
+    };
+
+    #[test]
+    fn magic_is_not_a_terminator() {
+        assert!(Outcome::try_from(MAGIC[0]).is_err());
+        assert_ne!(MAGIC[0], crate::START_POSITION_MARKER);
+    }
+
+    #[test]
+    fn round_trip() {
+        let position: Board = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1".parse().unwrap();
+        let data = write_header(
+            Some(&position),
+            &Termination::Resignation,
+            Some(b"meta"),
+            &[(1, b"one".to_vec()), (9, Vec::new())],
+            CHECKSUM,
+        );
+
+        let header = read_header(&data).unwrap();
+
+        assert_eq!(
+            header.header,
+            Header {
+                version: 1,
+                flags: CUSTOM_START | METADATA | CHECKSUM | SECTIONS | EXTENDED_OUTCOME
+            }
+        );
+        assert_eq!(header.start_position, Some(position));
+        assert_eq!(header.chess960, None);
+        assert_eq!(header.termination, Termination::Resignation);
+        assert_eq!(&data[header.metadata.unwrap()], b"meta");
+        assert_eq!(header.sections.len(), 2);
+        assert_eq!(&data[header.sections[0].1.clone()], b"one");
+        assert_eq!(header.sections[1].0, 9);
+        assert!(header.sections[1].1.is_empty());
+        assert_eq!(header.length, data.len());
+    }
+
+    #[test]
+    fn chess960_start_position() {
+        let position = get_start_position(0).unwrap();
+        let data = write_header(Some(&position), &Termination::Unspecified, None, &[], 0);
+
+        assert_eq!(data, b">ASCN\x01\x08\x00\x00");
+
+        let header = read_header(&data).unwrap();
+
+        assert_eq!(header.start_position, Some(position));
+        assert_eq!(header.chess960, Some(0));
+        assert_eq!(header.termination, Termination::Unspecified);
+    }
+
+    #[test]
+    fn extended_outcome() {
+        let data = write_header(None, &Termination::Timeout, None, &[], 0);
+
+        // the flag takes a second byte of flags
+        assert_eq!(data, b">ASCN\x01\x80\x01\x03");
+        assert_eq!(
+            read_header(&data).unwrap().termination,
+            Termination::Timeout
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_headers() {
+        let error = |data: &[u8]| read_header(data).err().unwrap();
+
+        assert_eq!(error(&MAGIC), (DecodeErrorKind::InvalidHeader, 5));
+        assert_eq!(
+            error(b">ASCN\x02\x00"),
+            (DecodeErrorKind::UnsupportedVersion(2), 5)
+        );
+        assert_eq!(error(b">ASCN\x01\x40"), (DecodeErrorKind::InvalidHeader, 7));
+        assert_eq!(
+            error(b">ASCN\x01\x02\x05abc"),
+            (DecodeErrorKind::InvalidHeader, 7)
+        );
+        assert_eq!(
+            error(b">ASCN\x01\x08\xC0\x03"),
+            (DecodeErrorKind::InvalidStartPosition, 7)
+        );
+        assert_eq!(error(b">ASCN\x01\x09"), (DecodeErrorKind::InvalidHeader, 6));
+
+        // flags that are not known yet, flags that do not fit in 16 bits and an unknown termination
+        assert_eq!(
+            error(b">ASCN\x01\x80\x02"),
+            (DecodeErrorKind::UnknownFlags(1 << 8), 6)
+        );
+        assert_eq!(
+            error(b">ASCN\x01\xFF\xFF\x7F"),
+            (DecodeErrorKind::InvalidHeader, 6)
+        );
+        assert_eq!(
+            error(b">ASCN\x01\x80\x01\x0A"),
+            (DecodeErrorKind::InvalidHeader, 8)
+        );
+
+        // a truncated section and two sections with the same id
+        assert_eq!(
+            error(b">ASCN\x01\x40\x01\x01\x03ab"),
+            (DecodeErrorKind::InvalidHeader, 9)
+        );
+        assert_eq!(
+            error(b">ASCN\x01\x40\x02\x01\x00\x01\x00"),
+            (DecodeErrorKind::InvalidHeader, 10)
+        );
+    }
+}
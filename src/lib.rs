@@ -1,9 +1,40 @@
 use chess::Piece;
 
+pub mod annotations;
+pub mod archive;
 mod bitbuffer;
+mod checkpoints;
+mod checksum;
+pub mod chess960;
+pub mod entropy;
 mod filters;
+pub mod header;
 pub mod outcome;
+pub mod pgn;
+mod position;
 pub mod reader;
+#[cfg(feature = "serde")]
+pub mod serde;
+pub mod stats;
+mod tags;
+pub mod text;
+mod varint;
 pub mod writer;
 
+/// Only public so that the benchmarks can compare it with the buffer it replaced
+#[doc(hidden)]
+pub use bitbuffer::BitBuffer;
+
+/// Marks a buffer that starts with a custom position. As a terminator byte it would not hold an
+/// outcome, so it can never be the first byte of a game from the default position.
+const START_POSITION_MARKER: u8 = 0b00111111;
+
+/// Marks the start of a variation in the core stream, which replaces the last move before it. As a
+/// terminator byte it would not hold an outcome, so older readers reject it.
+const VARIATION_START: u8 = 0b00111000;
+
+/// Marks the end of the innermost variation in the core stream, after which the line it branched
+/// from continues
+const VARIATION_END: u8 = 0b00111001;
+
 const PROMOTION_KEY: [Piece; 4] = [Piece::Queen, Piece::Bishop, Piece::Rook, Piece::Knight];
@@ -1 +1,624 @@
-fn main() {}
+use std::{
+    env,
+    error::Error,
+    fs,
+    io::{self, Cursor, Read, Write},
+    process::ExitCode,
+};
+
+use chess::Board;
+
+use ascn_rs::{
+    archive::{ArchiveReader, ArchiveWriter},
+    entropy::EntropyWriter,
+    header::{
+        ANNOTATIONS_SECTION, CHECKPOINTS_SECTION, CHECKSUM, CLOCKS_SECTION, COMPACT_OVERFLOW,
+        ENTROPY, EVALS_SECTION, TAGS_SECTION, VARIATION_ANNOTATIONS_SECTION,
+    },
+    pgn::{read_games, to_san, write_game},
+    reader::{Node, Reader},
+    stats::{read_stats, EncodingStats},
+    text::{from_text, to_text},
+    writer::Writer,
+};
+
+const USAGE: &str = "Usage: ascn <COMMAND> [OPTIONS] [FILE]
+
+Reads from FILE or stdin if it is not given and writes to stdout.
+
+Commands:
+  encode    Convert a PGN game into ASCN
+              --archive   Convert every game into an archive
+              --compact   Use the shortest overflow codes
+              --checksum  End every game with a checksum
+              --entropy   Entropy code the moves, which is smaller but slower
+              --checkpoints=K
+                          Store a checkpoint every K plies for seeking
+  decode    Convert an ASCN game into PGN
+              --uci       Write the moves in UCI notation instead
+              --archive   Convert every game of an archive
+              --game=N    Only convert game N of an archive
+  inspect   Show what every byte of an ASCN game means
+  stats     Count how the moves of an ASCN game were encoded
+              --archive   Add up the counts of every game of an archive
+  verify    Check that an ASCN game decodes and encodes back to the same bytes
+
+Every command takes --text to write or read the URL-safe text form of a game or archive instead of
+its bytes.";
+
+/// The options every command takes, where the ones ending in `=` take a value
+const OPTIONS: [(&str, &[&str]); 5] = [
+    (
+        "encode",
+        &[
+            "--archive",
+            "--compact",
+            "--checksum",
+            "--entropy",
+            "--checkpoints=",
+            "--text",
+        ],
+    ),
+    ("decode", &["--uci", "--archive", "--game=", "--text"]),
+    ("inspect", &["--text"]),
+    ("stats", &["--archive", "--text"]),
+    ("verify", &["--text"]),
+];
+
+type CliResult = Result<(), Box<dyn Error>>;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let Some((command, args)) = args.split_first() else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+
+    if ["help", "--help", "-h"].contains(&command.as_str()) {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+
+    let (flags, files): (Vec<&String>, Vec<&String>) = args
+        .iter()
+        .partition(|arg| arg.starts_with('-') && arg.len() > 1);
+
+    if let Err(error) = check_args(command, &flags, &files) {
+        eprintln!("ascn: {error}\n\n{USAGE}");
+        return ExitCode::FAILURE;
+    }
+
+    let has_flag = |name: &str| flags.iter().any(|flag| *flag == name);
+    let get_flag = |name: &str| {
+        flags
+            .iter()
+            .find_map(|flag| flag.strip_prefix(name)?.strip_prefix('='))
+    };
+
+    let text = has_flag("--text");
+    let mut out = io::stdout().lock();
+
+    let result = read_input(files.first().map(|file| file.as_str())).and_then(|mut input| {
+        let options = EncodeOptions {
+            compact: has_flag("--compact"),
+            checksum: has_flag("--checksum"),
+            entropy: has_flag("--entropy"),
+            checkpoints: get_flag("--checkpoints")
+                .map(|interval| {
+                    interval
+                        .parse()
+                        .map_err(|_| format!("{interval:?} is not a number of plies"))
+                })
+                .transpose()?,
+        };
+
+        if text && command != "encode" {
+            input = from_text(std::str::from_utf8(&input)?.trim())?;
+        }
+
+        match command.as_str() {
+            "encode" if has_flag("--archive") => encode_archive(&input, options, text, &mut out),
+            "encode" => encode(&input, options, text, &mut out),
+            "decode" if has_flag("--archive") || get_flag("--game").is_some() => {
+                decode_archive(&input, has_flag("--uci"), get_flag("--game"), &mut out)
+            }
+            "decode" => decode(&input, has_flag("--uci"), &mut out),
+            "inspect" => inspect(&input, &mut out),
+            "stats" => stats(&input, has_flag("--archive"), &mut out),
+            "verify" => verify(&input, &mut out),
+            _ => unreachable!("The command has been checked"),
+        }
+    });
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        // the reading end of a pipeline has stopped listening, which is not our problem
+        Err(error) if matches!(error.downcast_ref::<io::Error>(), Some(error) if error.kind() == io::ErrorKind::BrokenPipe) => {
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("ascn: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Checks that the command exists and that it takes every option it is given and at most one file
+fn check_args(command: &str, flags: &[&String], files: &[&String]) -> Result<(), String> {
+    let (_, options) = OPTIONS
+        .iter()
+        .find(|(name, _)| *name == command)
+        .ok_or_else(|| format!("unknown command {command:?}"))?;
+
+    for flag in flags {
+        let known = options.iter().any(|option| match option.strip_suffix('=') {
+            Some(name) => flag
+                .strip_prefix(name)
+                .is_some_and(|value| value.starts_with('=')),
+            None => flag == option,
+        });
+
+        if !known {
+            return Err(format!("unknown option {flag:?} for {command}"));
+        }
+    }
+
+    if let Some(file) = files.get(1) {
+        return Err(format!(
+            "unexpected argument {file:?}, only one file can be read"
+        ));
+    }
+
+    Ok(())
+}
+
+fn read_input(file: Option<&str>) -> Result<Vec<u8>, Box<dyn Error>> {
+    match file {
+        Some(file) => Ok(fs::read(file)?),
+        None => {
+            let mut input = Vec::new();
+            io::stdin().read_to_end(&mut input)?;
+            Ok(input)
+        }
+    }
+}
+
+fn write_data(data: &[u8], text: bool, out: &mut impl Write) -> CliResult {
+    if text {
+        writeln!(out, "{}", to_text(data))?;
+    } else {
+        out.write_all(data)?;
+    }
+
+    Ok(())
+}
+
+fn encode(input: &[u8], options: EncodeOptions, text: bool, out: &mut impl Write) -> CliResult {
+    let mut games = read_games(std::str::from_utf8(input)?);
+
+    let game = games.next().ok_or("the input does not contain a game")??;
+
+    if games.next().is_some() {
+        return Err("the input contains more than one game".into());
+    }
+
+    write_data(&apply_options(game.data, options)?, text, out)
+}
+
+fn encode_archive(
+    input: &[u8],
+    options: EncodeOptions,
+    text: bool,
+    out: &mut impl Write,
+) -> CliResult {
+    let mut writer = ArchiveWriter::new(Vec::new())?;
+
+    for game in read_games(std::str::from_utf8(input)?) {
+        writer.add_game(&apply_options(game?.data, options)?)?;
+    }
+
+    write_data(&writer.finish()?, text, out)
+}
+
+fn decode_archive(input: &[u8], uci: bool, game: Option<&str>, out: &mut impl Write) -> CliResult {
+    let mut reader = ArchiveReader::new(Cursor::new(input))?;
+
+    if let Some(game) = game {
+        let game = game
+            .parse()
+            .map_err(|_| format!("{game:?} is not a game number"))?;
+
+        return decode(&reader.get_data(game)?, uci, out);
+    }
+
+    for (index, game) in reader.enumerate() {
+        if index > 0 && !uci {
+            writeln!(out)?;
+        }
+
+        decode(&game?, uci, out)?;
+    }
+
+    Ok(())
+}
+
+fn apply_options(data: Vec<u8>, options: EncodeOptions) -> Result<Vec<u8>, Box<dyn Error>> {
+    if options.is_default() {
+        Ok(data)
+    } else {
+        Ok(reencode(&data, options, false)?.0)
+    }
+}
+
+fn decode(input: &[u8], uci: bool, out: &mut impl Write) -> CliResult {
+    if uci {
+        let mut reader = Reader::try_new(input)?;
+        let mut moves = Vec::new();
+
+        while let Some((chess_move, _)) = reader.try_next()? {
+            moves.push(chess_move.to_string());
+        }
+
+        moves.push(
+            reader
+                .get_outcome()
+                .unwrap_or_default()
+                .0
+                .as_str()
+                .to_string(),
+        );
+
+        writeln!(out, "{}", moves.join(" "))?;
+    } else {
+        write!(out, "{}", write_game(&[], input)?)?;
+    }
+
+    Ok(())
+}
+
+fn inspect(input: &[u8], out: &mut impl Write) -> CliResult {
+    let mut reader = Reader::try_new(input)?;
+    let mut chess = *reader.get_start_position();
+
+    if reader.get_core_offset() != 0 {
+        let range = format!("0-{}", reader.get_core_offset() - 1);
+
+        match reader.get_header() {
+            Some(header) => {
+                writeln!(
+                    out,
+                    "{range:>6}  header version {} with flags {:08b}",
+                    header.version, header.flags
+                )?;
+
+                if chess != Board::default() {
+                    writeln!(out, "{:>6}  start position {chess}", "")?;
+                }
+
+                if let Some(metadata) = reader.get_metadata() {
+                    writeln!(out, "{:>6}  {} bytes of metadata", "", metadata.len())?;
+                }
+
+                if let Some(section) = reader.get_section(TAGS_SECTION) {
+                    writeln!(
+                        out,
+                        "{:>6}  {} tags in {} bytes",
+                        "",
+                        reader.get_tags()?.len(),
+                        section.len()
+                    )?;
+                }
+
+                if let Some(section) = reader.get_section(CHECKPOINTS_SECTION) {
+                    writeln!(
+                        out,
+                        "{:>6}  checkpoints every {} plies in {} bytes",
+                        "",
+                        reader.get_checkpoint_interval().unwrap_or_default(),
+                        section.len()
+                    )?;
+                }
+
+                let annotations = reader.get_annotations()?;
+
+                if let Some(section) = reader.get_section(ANNOTATIONS_SECTION) {
+                    let count = annotations
+                        .iter()
+                        .filter(|(_, annotation)| {
+                            !annotation.comments.is_empty() || !annotation.nags.is_empty()
+                        })
+                        .count();
+
+                    writeln!(
+                        out,
+                        "{:>6}  {count} commented plies in {} bytes",
+                        "",
+                        section.len()
+                    )?;
+                }
+
+                if let Some(section) = reader.get_section(CLOCKS_SECTION) {
+                    let count = annotations
+                        .iter()
+                        .filter(|(_, annotation)| annotation.clock.is_some())
+                        .count();
+
+                    writeln!(out, "{:>6}  {count} clocks in {} bytes", "", section.len())?;
+                }
+
+                if let Some(section) = reader.get_section(EVALS_SECTION) {
+                    let count = annotations
+                        .iter()
+                        .filter(|(_, annotation)| annotation.eval.is_some())
+                        .count();
+
+                    writeln!(
+                        out,
+                        "{:>6}  {count} evaluations in {} bytes",
+                        "",
+                        section.len()
+                    )?;
+                }
+
+                if let Some(section) = reader.get_section(VARIATION_ANNOTATIONS_SECTION) {
+                    writeln!(
+                        out,
+                        "{:>6}  {} annotated variation nodes in {} bytes",
+                        "",
+                        annotations.iter_nodes().count(),
+                        section.len()
+                    )?;
+                }
+            }
+            None => writeln!(out, "{range:>6}  start position {chess}")?,
+        }
+    }
+
+    let entropy = reader
+        .get_header()
+        .is_some_and(|header| header.has_flag(ENTROPY));
+
+    if entropy {
+        let mut moves = Vec::new();
+
+        while let Some((chess_move, board)) = reader.try_next()? {
+            moves.push(to_san(&chess_move, &chess));
+            chess = board;
+        }
+
+        writeln!(
+            out,
+            "{:>6}  {} entropy coded moves: {}",
+            "",
+            moves.len(),
+            moves.join(" ")
+        )?;
+    } else {
+        writeln!(out, "offset  byte      filter     to  overflow  move")?;
+
+        loop {
+            let offset = reader.get_core_offset();
+            let byte = input[offset];
+            let overflow_left = reader.get_overflow_left();
+            let chess = *reader.get_position();
+
+            let chess_move = match reader.try_next_node()? {
+                None => break,
+                Some(Node::Move(chess_move, _)) => chess_move,
+                Some(Node::StartVariation) => {
+                    writeln!(out, "{offset:>6}  {byte:08b}  start of variation")?;
+                    continue;
+                }
+                Some(Node::EndVariation) => {
+                    writeln!(out, "{offset:>6}  {byte:08b}  end of variation")?;
+                    continue;
+                }
+            };
+
+            writeln!(
+                out,
+                "{offset:>6}  {byte:08b}  {:<9}  {}  {:>8}  {}",
+                match byte >> 6 {
+                    3 => "Straight",
+                    2 => "Diagonal",
+                    _ => "Knight",
+                },
+                chess_move.get_dest(),
+                overflow_left - reader.get_overflow_left(),
+                to_san(&chess_move, &chess),
+            )?;
+        }
+    }
+
+    let offset = reader.get_core_offset();
+
+    let (outcome, termination) = reader.get_outcome().unwrap_or_default();
+
+    writeln!(
+        out,
+        "{offset:>6}  {:08b}  terminator {outcome} {termination}",
+        input[offset],
+    )?;
+
+    let end = if reader
+        .get_header()
+        .is_some_and(|header| header.has_flag(CHECKSUM))
+    {
+        input.len() - 4
+    } else {
+        input.len()
+    };
+
+    if offset + 1 < end {
+        writeln!(
+            out,
+            "{:>6}  {} of {} bytes",
+            format!("{}-{}", offset + 1, end - 1),
+            if entropy {
+                "coded moves"
+            } else {
+                "overflow section"
+            },
+            end - offset - 1,
+        )?;
+    }
+
+    if end < input.len() {
+        writeln!(
+            out,
+            "{:>6}  checksum {:08x}",
+            format!("{end}-{}", input.len() - 1),
+            u32::from_le_bytes(input[end..].try_into()?),
+        )?;
+    }
+
+    Ok(())
+}
+
+fn stats(input: &[u8], archive: bool, out: &mut impl Write) -> CliResult {
+    if !archive {
+        writeln!(out, "{}", read_stats(input)?)?;
+        return Ok(());
+    }
+
+    let mut total = EncodingStats::default();
+    let mut worst: Option<(usize, f64)> = None;
+
+    for (index, game) in ArchiveReader::new(Cursor::new(input))?.enumerate() {
+        let stats = read_stats(&game?)?;
+
+        if let Some(bits) = stats.get_bits_per_ply() {
+            if worst.is_none_or(|(_, worst)| bits > worst) {
+                worst = Some((index, bits));
+            }
+        }
+
+        total += stats;
+    }
+
+    writeln!(out, "{total}")?;
+
+    if let Some((index, bits)) = worst {
+        writeln!(out, "worst game      {index} with {bits:.2} bits per ply")?;
+    }
+
+    Ok(())
+}
+
+fn verify(input: &[u8], out: &mut impl Write) -> CliResult {
+    let (data, plies) = reencode(input, EncodeOptions::default(), true)?;
+
+    if data != input {
+        return Err("the game does not encode back to the same bytes".into());
+    }
+
+    writeln!(out, "ok: {plies} plies in {} bytes", input.len())?;
+
+    Ok(())
+}
+
+/// The optional features `encode` can turn on
+#[derive(Clone, Copy, Default)]
+struct EncodeOptions {
+    compact: bool,
+    checksum: bool,
+    entropy: bool,
+    /// The number of plies between checkpoints, which replaces the interval the game had
+    checkpoints: Option<usize>,
+}
+
+impl EncodeOptions {
+    fn is_default(&self) -> bool {
+        !self.compact && !self.checksum && !self.entropy && self.checkpoints.is_none()
+    }
+}
+
+/// Decodes a game and encodes it again with the features it already had along with `options`,
+/// returning the new data and the number of moves, including those of variations
+fn reencode(
+    input: &[u8],
+    options: EncodeOptions,
+    verify: bool,
+) -> Result<(Vec<u8>, usize), Box<dyn Error>> {
+    let mut reader = Reader::try_new(input)?;
+    let mut chess = *reader.get_start_position();
+    let mut plies = 0;
+
+    let has_flag = |flag| {
+        reader
+            .get_header()
+            .is_some_and(|header| header.has_flag(flag))
+    };
+
+    if options.entropy || has_flag(ENTROPY) {
+        if options.checkpoints.is_some() {
+            return Err("entropy coded games can not store checkpoints".into());
+        }
+
+        let mut writer = EntropyWriter::with_start_position(chess);
+
+        writer.set_checksum(options.checksum || has_flag(CHECKSUM));
+
+        if let Some(metadata) = reader.get_metadata() {
+            writer.set_metadata(metadata.to_vec());
+        }
+
+        writer.set_tags(reader.get_tags()?);
+        writer.set_annotations(reader.get_annotations()?);
+
+        while let Some(node) = reader.try_next_node()? {
+            let Node::Move(chess_move, board) = node else {
+                return Err("entropy coded games can not store variations".into());
+            };
+
+            writer.try_add_move(&chess_move, &chess)?;
+            chess = board;
+            plies += 1;
+        }
+
+        let (outcome, termination) = reader.get_outcome().unwrap_or_default();
+        writer.set_termination(termination);
+
+        return Ok((writer.get_data(Some(outcome)), plies));
+    }
+
+    let mut writer = Writer::with_start_position(chess);
+
+    writer.set_verify(verify);
+    writer.set_header(reader.get_header().is_some());
+    writer.set_checksum(options.checksum || has_flag(CHECKSUM));
+    writer.set_compact_overflow(options.compact || has_flag(COMPACT_OVERFLOW));
+    writer.set_checkpoint_interval(
+        options
+            .checkpoints
+            .or(reader.get_checkpoint_interval())
+            .unwrap_or(0),
+    );
+
+    if let Some(metadata) = reader.get_metadata() {
+        writer.set_metadata(metadata.to_vec());
+    }
+
+    writer.set_tags(reader.get_tags()?);
+    writer.set_annotations(reader.get_annotations()?);
+
+    loop {
+        let chess = *reader.get_position();
+
+        match reader.try_next_node()? {
+            None => break,
+            Some(Node::Move(chess_move, _)) => {
+                writer.try_add_move(&chess_move, &chess)?;
+                plies += 1;
+            }
+            Some(Node::StartVariation) => writer.try_start_variation()?,
+            Some(Node::EndVariation) => writer.try_end_variation()?,
+        }
+    }
+
+    let (outcome, termination) = reader.get_outcome().unwrap_or_default();
+    writer.set_termination(termination);
+
+    Ok((writer.get_data(Some(outcome)), plies))
+}
@@ -1,11 +1,55 @@
-#[derive(Clone, Debug, PartialEq)]
+use std::{error::Error, fmt, str::FromStr};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
 pub enum Outcome {
     WhiteWon,
     BlackWon,
     Draw,
+    #[default]
     Unknown,
 }
 
+/// Why a game ended
+///
+/// The spare values of the terminator byte mark variations, a custom start position and the
+/// header, so there is no room left for a termination next to the outcome. It is stored in its own
+/// header byte behind `header::EXTENDED_OUTCOME` instead, which has room for 256 ids. New
+/// terminations go at the end of `ALL` so that the ids already written keep their meaning.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum Termination {
+    #[default]
+    Unspecified,
+    Checkmate,
+    Resignation,
+    Timeout,
+    Stalemate,
+    ThreefoldRepetition,
+    FiftyMoveRule,
+    InsufficientMaterial,
+    Agreement,
+    Abandonment,
+}
+
+/// An error produced when converting an id or a string into an `Outcome` or a `Termination`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OutcomeError {
+    /// The id does not belong to any variant
+    InvalidId(u8),
+    /// The string is not a recognised result token
+    InvalidString(String),
+}
+
+impl fmt::Display for OutcomeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutcomeError::InvalidId(id) => write!(f, "{id} is not a valid outcome id"),
+            OutcomeError::InvalidString(str) => write!(f, "{str:?} is not a valid result"),
+        }
+    }
+}
+
+impl Error for OutcomeError {}
+
 impl Outcome {
     pub fn get_id(&self) -> u8 {
         match self {
@@ -16,17 +60,8 @@ impl Outcome {
         }
     }
 
-    pub fn from_id(id: u8) -> Self {
-        match id {
-            1 => Outcome::WhiteWon,
-            2 => Outcome::BlackWon,
-            3 => Outcome::Draw,
-            0 => Outcome::Unknown,
-            _ => panic!("Unknown ID"), // TODO: Error handling
-        }
-    }
-
-    pub fn to_string(&self) -> &'static str {
+    /// The canonical PGN result token for the outcome
+    pub fn as_str(&self) -> &'static str {
         match self {
             Outcome::WhiteWon => "1-0",
             Outcome::BlackWon => "0-1",
@@ -35,19 +70,211 @@ impl Outcome {
         }
     }
 
+    /// Parses a result the way it is often written in the wild
+    ///
+    /// Whitespace is ignored, `:`, `–` and `—` are accepted as separators and draws may be written
+    /// as `1/2`, `½-½`, `½`, `0.5-0.5` or `=`. An empty string or `?` is an unknown outcome.
+    pub fn parse_lenient(str: &str) -> Result<Self, OutcomeError> {
+        let normalized: String = str
+            .chars()
+            .filter(|char| !char.is_whitespace())
+            .map(|char| match char {
+                ':' | '–' | '—' => '-',
+                char => char,
+            })
+            .collect();
+
+        match normalized.as_str() {
+            "1-0" => Ok(Outcome::WhiteWon),
+            "0-1" => Ok(Outcome::BlackWon),
+            "1/2-1/2" | "1/2" | "½-½" | "½" | "0.5-0.5" | "=" => Ok(Outcome::Draw),
+            "*" | "?" | "" => Ok(Outcome::Unknown),
+            _ => Err(OutcomeError::InvalidString(str.to_string())),
+        }
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the id does not belong to an outcome
+    #[deprecated(note = "use `Outcome::try_from` instead")]
+    pub fn from_id(id: u8) -> Self {
+        Self::try_from(id).expect("Unknown ID")
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the string is not one of the four canonical result tokens
+    #[deprecated(
+        since = "0.4.0",
+        note = "use `str::parse` or `Outcome::parse_lenient` instead"
+    )]
     pub fn from_string(str: &str) -> Self {
+        str.parse().expect("Unknown outcome")
+    }
+
+    /// The canonical PGN result token for the outcome, which shadows `Display::to_string`
+    #[deprecated(note = "use `Outcome::as_str` or `Display` instead")]
+    #[allow(clippy::inherent_to_string_shadow_display)]
+    pub fn to_string(&self) -> &'static str {
+        self.as_str()
+    }
+}
+
+impl Termination {
+    pub const ALL: [Termination; 10] = [
+        Termination::Unspecified,
+        Termination::Checkmate,
+        Termination::Resignation,
+        Termination::Timeout,
+        Termination::Stalemate,
+        Termination::ThreefoldRepetition,
+        Termination::FiftyMoveRule,
+        Termination::InsufficientMaterial,
+        Termination::Agreement,
+        Termination::Abandonment,
+    ];
+
+    /// The id of the termination, which is the byte stored in the header
+    pub fn get_id(&self) -> u8 {
+        Self::ALL
+            .iter()
+            .position(|termination| termination == self)
+            .expect("Every termination is listed") as u8
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Termination::Unspecified => "unspecified",
+            Termination::Checkmate => "checkmate",
+            Termination::Resignation => "resignation",
+            Termination::Timeout => "timeout",
+            Termination::Stalemate => "stalemate",
+            Termination::ThreefoldRepetition => "threefold repetition",
+            Termination::FiftyMoveRule => "fifty-move rule",
+            Termination::InsufficientMaterial => "insufficient material",
+            Termination::Agreement => "agreement",
+            Termination::Abandonment => "abandonment",
+        }
+    }
+}
+
+impl TryFrom<u8> for Termination {
+    type Error = OutcomeError;
+
+    fn try_from(id: u8) -> Result<Self, Self::Error> {
+        Self::ALL
+            .get(id as usize)
+            .copied()
+            .ok_or(OutcomeError::InvalidId(id))
+    }
+}
+
+impl FromStr for Termination {
+    type Err = OutcomeError;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .find(|termination| termination.as_str() == str)
+            .copied()
+            .ok_or_else(|| OutcomeError::InvalidString(str.to_string()))
+    }
+}
+
+impl fmt::Display for Termination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl TryFrom<u8> for Outcome {
+    type Error = OutcomeError;
+
+    fn try_from(id: u8) -> Result<Self, Self::Error> {
+        match id {
+            1 => Ok(Outcome::WhiteWon),
+            2 => Ok(Outcome::BlackWon),
+            3 => Ok(Outcome::Draw),
+            0 => Ok(Outcome::Unknown),
+            _ => Err(OutcomeError::InvalidId(id)),
+        }
+    }
+}
+
+impl FromStr for Outcome {
+    type Err = OutcomeError;
+
+    /// Parses one of the four canonical PGN result tokens, see `parse_lenient` for anything else
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
         match str {
-            "1-0" => Outcome::WhiteWon,
-            "0-1" => Outcome::BlackWon,
-            "1/2-1/2" => Outcome::Draw,
-            "*" => Outcome::Unknown,
-            _ => panic!("Unknown outcome"), // TODO: Error handling
+            "1-0" => Ok(Outcome::WhiteWon),
+            "0-1" => Ok(Outcome::BlackWon),
+            "1/2-1/2" => Ok(Outcome::Draw),
+            "*" => Ok(Outcome::Unknown),
+            _ => Err(OutcomeError::InvalidString(str.to_string())),
         }
     }
 }
 
-impl Default for Outcome {
-    fn default() -> Self {
-        Self::Unknown
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Outcome, OutcomeError, Termination};
+
+    #[test]
+    fn id_round_trip() {
+        for outcome in [
+            Outcome::WhiteWon,
+            Outcome::BlackWon,
+            Outcome::Draw,
+            Outcome::Unknown,
+        ] {
+            assert_eq!(Outcome::try_from(outcome.get_id()), Ok(outcome));
+            assert_eq!(format!("{outcome}").parse(), Ok(outcome));
+        }
+
+        assert_eq!(Outcome::try_from(4), Err(OutcomeError::InvalidId(4)));
+    }
+
+    #[test]
+    fn termination_round_trip() {
+        for termination in Termination::ALL {
+            assert_eq!(Termination::try_from(termination.get_id()), Ok(termination));
+            assert_eq!(termination.to_string().parse(), Ok(termination));
+        }
+
+        assert_eq!(Termination::try_from(10), Err(OutcomeError::InvalidId(10)));
+    }
+
+    #[test]
+    fn strict_parsing() {
+        assert!("1/2".parse::<Outcome>().is_err());
+        assert!(" 1-0".parse::<Outcome>().is_err());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn deprecated_wrappers() {
+        assert_eq!(Outcome::from_id(2), Outcome::BlackWon);
+        assert_eq!(Outcome::from_string("1/2-1/2"), Outcome::Draw);
+        assert_eq!(Outcome::WhiteWon.to_string(), "1-0");
+    }
+
+    #[test]
+    fn lenient_parsing() {
+        assert_eq!(Outcome::parse_lenient(" 1 - 0 "), Ok(Outcome::WhiteWon));
+        assert_eq!(Outcome::parse_lenient("0–1"), Ok(Outcome::BlackWon));
+        assert_eq!(Outcome::parse_lenient("1/2"), Ok(Outcome::Draw));
+        assert_eq!(Outcome::parse_lenient("½-½"), Ok(Outcome::Draw));
+        assert_eq!(Outcome::parse_lenient(""), Ok(Outcome::Unknown));
+        assert_eq!(
+            Outcome::parse_lenient("2-0"),
+            Err(OutcomeError::InvalidString("2-0".to_string()))
+        );
     }
 }
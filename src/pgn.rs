@@ -0,0 +1,782 @@
+use std::{error::Error, fmt, iter::Peekable, str::FromStr};
+
+use chess::{Board, BoardStatus, Color, File};
+
+use crate::{
+    annotations::{Clock, Eval},
+    chess960::{get_start_index, Castling},
+    outcome::{Outcome, Termination},
+    writer::{EncodeError, Writer},
+};
+
+use self::{
+    export::is_derived,
+    san::is_castling,
+    tokens::{Token, Tokenizer},
+};
+
+mod export;
+pub mod san;
+mod tokens;
+
+pub use self::{
+    export::{write_game, write_movetext},
+    san::{parse_san, parse_san_with_castling, to_san, SanError},
+};
+
+/// The reason a PGN game could not be converted
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PgnErrorKind {
+    /// Text that is neither a tag pair nor valid movetext
+    InvalidToken(String),
+    /// A brace comment that is never closed
+    UnterminatedComment,
+    /// A variation that is closed without being opened or never closed
+    UnbalancedVariation,
+    /// The `FEN` tag does not hold a valid position
+    InvalidFen(String),
+    /// The `Result` tag can not be parsed and there is no termination marker
+    InvalidResult(String),
+    /// A move could not be resolved, along with the ply it was played at
+    San(usize, SanError),
+    /// The game castles with rights that can not be represented at the given ply, which happens
+    /// when a position that is not a Chess960 start position, or a Chess960 start position with
+    /// only some of its rights, has castling rights the `chess` crate can not hold
+    UnsupportedCastling(usize),
+    /// A move could not be encoded
+    Encode(EncodeError),
+}
+
+/// An error produced while converting a single game of a PGN file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PgnError {
+    pub kind: PgnErrorKind,
+    /// The index of the game in the PGN text
+    pub game: usize,
+    /// The 1-based line the offending text is on
+    pub line: usize,
+}
+
+impl fmt::Display for PgnErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PgnErrorKind::InvalidToken(text) => write!(f, "could not understand {text:?}"),
+            PgnErrorKind::UnterminatedComment => write!(f, "a comment is never closed"),
+            PgnErrorKind::UnbalancedVariation => write!(f, "the variations are unbalanced"),
+            PgnErrorKind::InvalidFen(fen) => write!(f, "{fen:?} is not a valid FEN"),
+            PgnErrorKind::InvalidResult(result) => write!(f, "{result:?} is not a valid result"),
+            PgnErrorKind::San(ply, error) => write!(f, "{error} at ply {ply}"),
+            PgnErrorKind::UnsupportedCastling(ply) => write!(
+                f,
+                "castling at ply {ply} is only supported from the standard squares or with all \
+                 the rights of a Chess960 start position"
+            ),
+            PgnErrorKind::Encode(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (game {}, line {})", self.kind, self.game, self.line)
+    }
+}
+
+impl Error for PgnError {}
+
+/// A game converted from PGN
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct PgnGame {
+    /// The tag pairs of the game in the order they were written
+    pub tags: Vec<(String, String)>,
+    pub outcome: Outcome,
+    pub termination: Termination,
+    /// The encoded game
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde::game"))]
+    pub data: Vec<u8>,
+}
+
+impl PgnGame {
+    /// Returns the value of the first tag with the given name
+    pub fn get_tag(&self, name: &str) -> Option<&str> {
+        get_tag(&self.tags, name)
+    }
+}
+
+fn get_tag<'a>(tags: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    tags.iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/// Converts every game in PGN text, yielding each game or the reason it could not be converted
+///
+/// A game that fails to convert does not stop the following games from being read.
+pub fn read_games(pgn: &str) -> Games<'_> {
+    Games {
+        tokens: Tokenizer::new(pgn).peekable(),
+        lines: Tokenizer::new(pgn),
+        game: 0,
+    }
+}
+
+/// Converts the first game in PGN text
+pub fn read_game(pgn: &str) -> Option<Result<PgnGame, PgnError>> {
+    read_games(pgn).next()
+}
+
+/// An iterator over the games of PGN text, created by `read_games`
+pub struct Games<'a> {
+    tokens: Peekable<Tokenizer<'a>>,
+    lines: Tokenizer<'a>,
+    game: usize,
+}
+
+impl Iterator for Games<'_> {
+    type Item = Result<PgnGame, PgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut tags = Vec::new();
+        let mut movetext = Vec::new();
+        let mut result = None;
+
+        let start = self.tokens.peek()?.0;
+
+        while let Some((_, Token::Tag(..))) = self.tokens.peek() {
+            if let Some((_, Token::Tag(name, value))) = self.tokens.next() {
+                tags.push((name.to_string(), value));
+            }
+        }
+
+        // the movetext ends at a termination marker outside of any variation or at the tags of
+        // the next game
+        let mut depth = 0usize;
+
+        while let Some((offset, token)) = self
+            .tokens
+            .next_if(|(_, token)| !matches!(token, Token::Tag(..)))
+        {
+            match token {
+                Token::StartVariation => depth += 1,
+                Token::EndVariation => depth = depth.saturating_sub(1),
+                Token::Result(outcome) if depth == 0 => {
+                    result = Some(outcome);
+                    break;
+                }
+                _ => {}
+            }
+
+            movetext.push((offset, token));
+        }
+
+        let game = self.game;
+        self.game += 1;
+
+        Some(
+            convert_game(tags, &movetext, result).map_err(|(offset, kind)| PgnError {
+                kind,
+                game,
+                line: self.lines.line(offset.unwrap_or(start)),
+            }),
+        )
+    }
+}
+
+type ConvertError = (Option<usize>, PgnErrorKind);
+
+fn convert_game(
+    tags: Vec<(String, String)>,
+    movetext: &[(usize, Token)],
+    result: Option<Outcome>,
+) -> Result<PgnGame, ConvertError> {
+    let (start_position, mut castling, dropped_castling) = match get_tag(&tags, "FEN") {
+        Some(fen) => {
+            parse_fen(fen).ok_or_else(|| (None, PgnErrorKind::InvalidFen(fen.to_string())))?
+        }
+        None => (Board::default(), Castling::default(), false),
+    };
+
+    let mut writer = Writer::with_castling(start_position, castling);
+    let mut chess = start_position;
+
+    if let Some(increment) = get_tag(&tags, "TimeControl").and_then(get_increment) {
+        writer.set_clock_increment(increment);
+    }
+
+    let chess960 = writer.is_chess960();
+    // castling may only be written as the king capturing its own rook in Chess960 games
+    let chess960_notation = chess960
+        || get_tag(&tags, "Variant")
+            .is_some_and(|variant| variant.eq_ignore_ascii_case("chess960"));
+
+    writer.set_tags(
+        tags.iter()
+            .filter(|(name, value)| !is_derived(name, value, chess960))
+            .cloned()
+            .collect(),
+    );
+
+    // the position and castling rights before the last move of the current line, which a variation
+    // starts from
+    let mut previous = None;
+    let mut ply = 0;
+    // the position, previous position and ply of every line that a variation branched from
+    let mut variations = Vec::new();
+
+    for (offset, token) in movetext {
+        let error = |kind| (Some(*offset), kind);
+
+        match token {
+            Token::StartVariation => {
+                writer
+                    .try_start_variation()
+                    .map_err(|encode| error(PgnErrorKind::Encode(encode)))?;
+
+                let before = previous.expect("The writer checks that there is a move to replace");
+
+                variations.push(((chess, castling), previous, ply));
+                (chess, castling) = before;
+                previous = None;
+                ply -= 1;
+            }
+            Token::EndVariation => {
+                ((chess, castling), previous, ply) = variations
+                    .pop()
+                    .ok_or_else(|| error(PgnErrorKind::UnbalancedVariation))?;
+
+                writer
+                    .try_end_variation()
+                    .map_err(|encode| error(PgnErrorKind::Encode(encode)))?;
+            }
+            Token::San(san) => {
+                let chess_move = if chess960_notation {
+                    parse_san_with_castling(san, &chess, &castling)
+                } else {
+                    parse_san(san, &chess)
+                };
+                let chess_move = chess_move.map_err(|error_kind| {
+                    if dropped_castling && is_castling(san, &chess) {
+                        error(PgnErrorKind::UnsupportedCastling(ply))
+                    } else {
+                        error(PgnErrorKind::San(ply, error_kind))
+                    }
+                })?;
+
+                writer
+                    .try_add_move(&chess_move, &chess)
+                    .map_err(|encode| error(PgnErrorKind::Encode(encode)))?;
+
+                previous = Some((chess, castling));
+                chess = castling.make_move(&chess, chess_move);
+                ply += 1;
+            }
+            Token::Comment(comment) => {
+                let mut comment = comment.trim().to_string();
+                let mut has_command = false;
+
+                // commands are only taken out of the comment if they would be exported the same
+                if let Some((clock, rest)) = take_command(&comment, "clk", Clock::parse) {
+                    writer.set_clock(clock);
+                    comment = rest;
+                    has_command = true;
+                }
+
+                if let Some((eval, rest)) = take_command(&comment, "eval", Eval::parse) {
+                    writer.set_eval(eval);
+                    comment = rest;
+                    has_command = true;
+                }
+
+                if !has_command || !comment.is_empty() {
+                    writer.add_comment(comment);
+                }
+            }
+            Token::Nag(nag) => writer.add_nag(*nag),
+            Token::UnterminatedComment => return Err(error(PgnErrorKind::UnterminatedComment)),
+            Token::Invalid(text) => {
+                return Err(error(PgnErrorKind::InvalidToken(text.to_string())))
+            }
+            _ => {}
+        }
+    }
+
+    if !variations.is_empty() {
+        return Err((None, PgnErrorKind::UnbalancedVariation));
+    }
+
+    let outcome = match (result, get_tag(&tags, "Result")) {
+        (Some(outcome), _) => outcome,
+        (None, Some(result)) => Outcome::parse_lenient(result)
+            .map_err(|_| (None, PgnErrorKind::InvalidResult(result.to_string())))?,
+        (None, None) => Outcome::Unknown,
+    };
+
+    let termination = get_termination(&chess, &castling, get_tag(&tags, "Termination"));
+
+    writer.set_termination(termination);
+
+    Ok(PgnGame {
+        data: writer.get_data(Some(outcome)),
+        tags,
+        outcome,
+        termination,
+    })
+}
+
+/// Removes the first `[%name value]` command from a comment if `parse` accepts its value,
+/// returning the parsed value and the rest of the comment
+fn take_command<T>(
+    comment: &str,
+    name: &str,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Option<(T, String)> {
+    let start = comment.find(&format!("[%{name} "))?;
+    let value_start = start + name.len() + 3;
+    let end = value_start + comment[value_start..].find(']')?;
+
+    let rest = format!(
+        "{} {}",
+        comment[..start].trim_end(),
+        comment[end + 1..].trim_start()
+    );
+
+    Some((parse(&comment[value_start..end])?, rest.trim().to_string()))
+}
+
+/// Reads the increment in milliseconds from a `TimeControl` tag such as `180+2`
+fn get_increment(time_control: &str) -> Option<u32> {
+    let (_, increment) = time_control.split_once('+')?;
+
+    increment.parse::<u32>().ok()?.checked_mul(1000)
+}
+
+/// Parses a FEN, X-FEN or Shredder-FEN
+///
+/// The `chess` crate can only castle with the king on the e-file and the rooks in the corners, so
+/// castling rights anywhere else are only kept when they are all four rights of a Chess960 start
+/// position, which `Castling` holds. Those rights and whether any were dropped are returned with
+/// the position.
+pub(crate) fn parse_fen(fen: &str) -> Option<(Board, Castling, bool)> {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    let castling = *fields.get(2)?;
+
+    let with_castling = |castling: &str| {
+        let mut fields = fields.clone();
+        fields[2] = castling;
+        Board::from_str(&fields.join(" ")).ok()
+    };
+
+    let position = with_castling("-")?;
+    let chess960 = get_start_index(&position)
+        .map(Castling::from_start_index)
+        .unwrap_or_default();
+
+    // every right as its color, whether it is toward the h-file and the file Shredder-FEN names
+    let rights: Vec<(Color, bool, Option<File>)> = castling
+        .chars()
+        .filter(|char| *char != '-')
+        .map(|char| {
+            let color = if char.is_ascii_uppercase() {
+                Color::White
+            } else {
+                Color::Black
+            };
+
+            match char.to_ascii_lowercase() {
+                'k' => Some((color, true, None)),
+                'q' => Some((color, false, None)),
+                file @ 'a'..='h' => {
+                    let file = File::from_index(file as usize - 'a' as usize);
+
+                    Some((
+                        color,
+                        file > position.king_square(color).get_file(),
+                        Some(file),
+                    ))
+                }
+                _ => None,
+            }
+        })
+        .collect::<Option<_>>()?;
+
+    let mut standard: Vec<char> = rights
+        .iter()
+        .map(|(color, kingside, _)| match (color, kingside) {
+            (Color::White, true) => 'K',
+            (Color::White, false) => 'Q',
+            (Color::Black, true) => 'k',
+            (Color::Black, false) => 'q',
+        })
+        .collect();
+
+    standard.sort();
+
+    if standard.is_empty() {
+        return Some((position, Castling::default(), false));
+    }
+
+    if let Some(position) = with_castling(&standard.iter().collect::<String>()) {
+        return Some((position, Castling::default(), false));
+    }
+
+    let kept = !chess960.is_empty()
+        && standard == ['K', 'Q', 'k', 'q']
+        && rights.iter().all(|(color, kingside, file)| {
+            file.is_none_or(|file| {
+                chess960
+                    .get_rook(*color, *kingside)
+                    .map(|rook| rook.get_file())
+                    == Some(file)
+            })
+        });
+
+    if kept {
+        Some((position, chess960, false))
+    } else {
+        Some((position, Castling::default(), true))
+    }
+}
+
+/// Works out why a game ended from its final position and its `Termination` tag
+fn get_termination(position: &Board, castling: &Castling, tag: Option<&str>) -> Termination {
+    match position.status() {
+        BoardStatus::Checkmate => return Termination::Checkmate,
+        // the `chess` crate does not know about Chess960 castling, which may be the only move left
+        BoardStatus::Stalemate if castling.get_moves(position).is_empty() => {
+            return Termination::Stalemate
+        }
+        _ => {}
+    }
+
+    match tag.map(|tag| tag.to_ascii_lowercase()).as_deref() {
+        Some("time forfeit") => Termination::Timeout,
+        Some("abandoned") => Termination::Abandonment,
+        Some(tag) => tag.parse().unwrap_or_default(),
+        None => Termination::Unspecified,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chess::{Board, BoardStatus, ChessMove, MoveGen, Piece, Square};
+
+    use crate::{
+        annotations::Annotation,
+        chess960::{self, get_start_position, Castling, START_POSITIONS},
+        outcome::{Outcome, Termination},
+        reader::Reader,
+        writer::Writer,
+    };
+
+    use super::{read_game, read_games, san::SanError, write_game, PgnError, PgnErrorKind};
+
+    const GAMES: &str = r#"[Event "Scholar's mate"]
+[Result "1-0"]
+
+1. e4 e5 2. Bc4 Nc6 3. Qh5 Nf6?? (3... g6 4. Qf3) 4. Qxf7# 1-0
+
+[Event "Broken"]
+
+1. e4 e5 2. Ke3 *
+
+[Event "Setup"]
+[SetUp "1"]
+[FEN "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"]
+[Result "1/2"]
+
+1. e4 {a comment} Kd7
+"#;
+
+    #[test]
+    fn reads_multiple_games() {
+        let games: Vec<_> = read_games(GAMES).collect();
+
+        assert_eq!(games.len(), 3);
+
+        let game = games[0].as_ref().unwrap();
+
+        assert_eq!(game.get_tag("Event"), Some("Scholar's mate"));
+        assert_eq!(game.outcome, Outcome::WhiteWon);
+        assert_eq!(game.termination, Termination::Checkmate);
+
+        let mut reader = Reader::new(&game.data);
+
+        // the NAG of the mainline is kept, but not the moves of the variation
+        assert_eq!(
+            reader.get_annotations().unwrap().iter().collect::<Vec<_>>(),
+            [(
+                &6,
+                &Annotation {
+                    nags: vec![4],
+                    ..Default::default()
+                }
+            )]
+        );
+        assert_eq!(reader.by_ref().count(), 7);
+        assert_eq!(
+            reader.get_outcome(),
+            Some((Outcome::WhiteWon, Termination::Checkmate))
+        );
+
+        assert_eq!(
+            games[1],
+            Err(PgnError {
+                kind: PgnErrorKind::San(2, SanError::Illegal("Ke3".to_string())),
+                game: 1,
+                line: 8
+            })
+        );
+
+        let game = games[2].as_ref().unwrap();
+        let reader = Reader::new(&game.data);
+        let annotations = reader.get_annotations().unwrap();
+
+        assert_eq!(annotations.get(1).unwrap().comments, ["a comment"]);
+
+        assert_eq!(game.outcome, Outcome::Draw);
+        assert_eq!(
+            reader.get_start_position(),
+            &"4k3/8/8/8/8/8/4P3/4K3 w - - 0 1".parse::<Board>().unwrap()
+        );
+        assert_eq!(
+            reader.map(|(chess_move, _)| chess_move).collect::<Vec<_>>(),
+            vec![
+                ChessMove::new(Square::E2, Square::E4, None),
+                ChessMove::new(Square::E8, Square::D7, None)
+            ]
+        );
+    }
+
+    #[test]
+    fn chess960_games() {
+        let shredder = "[Variant \"Chess960\"]\n[SetUp \"1\"]\n\
+            [FEN \"bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w HFhf - 0 1\"]\n\n\
+            1. e4 e5 2. Nd3 Nd6 *";
+
+        let game = read_game(shredder).unwrap().unwrap();
+        let reader = Reader::new(&game.data);
+
+        assert_eq!(reader.get_chess960_index(), Some(0));
+        assert_eq!(game.data.len(), 9 + 5);
+
+        let exported = write_game(&game.tags, &game.data).unwrap();
+
+        assert!(exported.contains(
+            "[Variant \"Chess960\"]\n[SetUp \"1\"]\n\
+            [FEN \"bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w HFhf - 0 1\"]\n"
+        ));
+        assert_eq!(exported.matches("Variant").count(), 1);
+        assert_eq!(read_game(&exported).unwrap().unwrap().data, game.data);
+
+        // the rook on f1 is in the way of the king's rook, which castles onto f1
+        let blocked = "[FEN \"bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1\"]\n\n\
+            1. g3 g6 2. O-O *";
+
+        assert_eq!(
+            read_game(blocked).unwrap().unwrap_err().kind,
+            PgnErrorKind::San(2, SanError::Illegal("O-O".to_string()))
+        );
+
+        // only some of the rights of a Chess960 start position can not be represented
+        let partial = "[FEN \"bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w Hh - 0 1\"]\n\n\
+            1. Nd3 Nd6 2. Ne3 Ne6 3. c3 c6 4. Qc2 Qc7 5. O-O-O *";
+
+        assert_eq!(
+            read_game(partial).unwrap().unwrap_err().kind,
+            PgnErrorKind::UnsupportedCastling(8)
+        );
+    }
+
+    #[test]
+    fn castling_rights_from_fen() {
+        // a Chess960 start position that has lost its rights is not stored as one
+        let no_rights = "[FEN \"bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w - - 0 1\"]\n\n\
+            1. Nd3 Nd6 2. Ne3 Ne6 3. c3 c6 4. Qc2 Qc7 *";
+
+        let game = read_game(no_rights).unwrap().unwrap();
+        let mut reader = Reader::new(&game.data);
+
+        assert_eq!(reader.get_chess960_index(), None);
+        assert!(reader.get_castling().is_empty());
+        assert_eq!(reader.by_ref().count(), 8);
+        assert!(write_game(&game.tags, &game.data)
+            .unwrap()
+            .contains("[FEN \"bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w - - 0 1\"]"));
+
+        // so it can not castle in either notation
+        for castle in ["O-O-O", "Kxf1", "Kf1"] {
+            let pgn = format!("{}5. {castle} *", &no_rights[..no_rights.len() - 1]);
+
+            assert_eq!(
+                read_game(&pgn).unwrap().unwrap_err().kind,
+                PgnErrorKind::San(8, SanError::Illegal(castle.to_string())),
+                "{castle}"
+            );
+        }
+
+        // the king capturing its own rook is only castling in games flagged as Chess960
+        let standard = "1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5 4. Kxh1 *";
+
+        assert_eq!(
+            read_game(standard).unwrap().unwrap_err().kind,
+            PgnErrorKind::San(6, SanError::Illegal("Kxh1".to_string()))
+        );
+
+        let game = read_game(&format!("[Variant \"Chess960\"]\n\n{standard}"))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            Reader::new(&game.data).last().unwrap().0,
+            ChessMove::new(Square::E1, Square::G1, None)
+        );
+    }
+
+    #[test]
+    fn chess960_castling() {
+        // white castles queenside with the king staying on c1, black castles kingside with the king
+        // moving onto the square of its rook
+        let x_fen = "[Variant \"Chess960\"]\n[SetUp \"1\"]\n\
+            [FEN \"brknqnrb/pppppppp/8/8/8/8/PPPPPPPP/BRKNQNRB w KQkq - 0 1\"]\n\n\
+            1. Nde3 Nc6 2. O-O-O Ng6 3. Ng3 e5 4. f4 Qe7 5. f5 O-O *";
+
+        // both kings move to the c-file, each passing over its own rook on f1 or f8
+        let shredder = "[Variant \"Chess960\"]\n[SetUp \"1\"]\n\
+            [FEN \"bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w FHfh - 0 1\"]\n\n\
+            1. Nd3 Nd6 2. Ne3 Ne6 3. c3 c6 4. Qc2 Qc7 5. O-O-O O-O-O *";
+
+        for (pgn, index, castles, after) in [
+            (
+                x_fen,
+                723,
+                [(3, "c1b1"), (10, "c8g8")],
+                "br3rkb/ppppqppp/2n3n1/4pP2/8/4N1N1/PPPPP1PP/B1KRQ1RB w - - 0 1",
+            ),
+            (
+                shredder,
+                0,
+                [(9, "g1f1"), (10, "g8f8")],
+                "bbkr3r/ppqppppp/2pnn3/8/8/2PNN3/PPQPPPPP/BBKR3R w - - 0 1",
+            ),
+        ] {
+            let game = read_game(pgn).unwrap().unwrap();
+            let mut reader = Reader::new(&game.data);
+
+            assert_eq!(reader.get_chess960_index(), Some(index));
+
+            let moves: Vec<_> = reader.by_ref().collect();
+
+            for (ply, uci) in castles {
+                assert_eq!(moves[ply - 1].0, uci.parse().unwrap(), "{pgn}");
+            }
+
+            assert_eq!(moves.last().unwrap().1.to_string(), after, "{pgn}");
+            assert!(reader.get_castling().is_empty());
+
+            // the castles are exported the way they were written
+            let exported = write_game(&game.tags, &game.data).unwrap();
+
+            assert!(
+                exported.contains(&pgn[pgn.find("\n\n").unwrap() + 2..]),
+                "{exported}"
+            );
+            assert_eq!(read_game(&exported).unwrap().unwrap().data, game.data);
+        }
+    }
+
+    #[test]
+    fn chess960_round_trip() {
+        // the games from start positions that the `chess` crate can not castle from, and how many
+        // of them castled
+        let mut games = 0;
+        let mut castled = 0;
+
+        for index in 0..START_POSITIONS {
+            let mut chess = get_start_position(index).unwrap();
+            let mut castling = Castling::from_start_index(index);
+            let mut castles = 0;
+
+            if !castling.is_empty() {
+                games += 1;
+            }
+            let mut writer = Writer::with_castling(chess, castling);
+
+            writer.set_verify(true);
+
+            for ply in 0..32 {
+                let mut moves = castling.get_moves(&chess);
+
+                // castle as soon as possible, and otherwise clear the back rank for it
+                if moves.is_empty() {
+                    moves = MoveGen::new_legal(&chess)
+                        .filter(|chess_move| {
+                            let back_rank = chess.side_to_move().to_my_backrank();
+                            let source = chess_move.get_source();
+
+                            source.get_rank() == back_rank
+                                && chess_move.get_dest().get_rank() != back_rank
+                                && !matches!(
+                                    chess.piece_on(source),
+                                    Some(Piece::King | Piece::Rook)
+                                )
+                        })
+                        .collect();
+                } else {
+                    castles += 1;
+                }
+
+                if moves.is_empty() {
+                    moves = MoveGen::new_legal(&chess).collect();
+                }
+
+                // the game is written without an outcome, so it must not end on the board
+                moves.retain(|chess_move| {
+                    chess960::make_move(&chess, *chess_move).status() == BoardStatus::Ongoing
+                });
+
+                let Some(chess_move) = moves.get((index as usize + ply) % moves.len().max(1))
+                else {
+                    break;
+                };
+
+                writer.add_move(chess_move, &chess);
+                chess = castling.make_move(&chess, *chess_move);
+            }
+
+            let data = writer.get_data(None);
+            let pgn = write_game(&[], &data).unwrap();
+
+            assert_eq!(read_game(&pgn).unwrap().unwrap().data, data, "{pgn}");
+
+            if castles > 0 {
+                castled += 1;
+            }
+        }
+
+        assert!(
+            castled * 10 > games * 9,
+            "only {castled} of {games} games castled"
+        );
+    }
+
+    #[test]
+    fn reports_invalid_games() {
+        assert_eq!(
+            read_game("1. e4 (1. d4").unwrap().unwrap_err().kind,
+            PgnErrorKind::UnbalancedVariation
+        );
+        assert_eq!(
+            read_game("1. e4 {unterminated").unwrap().unwrap_err().kind,
+            PgnErrorKind::UnterminatedComment
+        );
+        assert_eq!(
+            read_game("[FEN \"nonsense\"]\n\n*")
+                .unwrap()
+                .unwrap_err()
+                .kind,
+            PgnErrorKind::InvalidFen("nonsense".to_string())
+        );
+        assert!(read_game("").is_none());
+    }
+}
@@ -0,0 +1,523 @@
+use chess::{Board, Color};
+
+use crate::{
+    annotations::Annotation,
+    chess960::get_start_fen,
+    reader::{DecodeError, Node, Reader},
+};
+
+use super::{get_tag, san::to_san};
+
+/// The column that movetext lines are wrapped at
+const LINE_WIDTH: usize = 80;
+
+/// The tags every exported game starts with, in order, along with their value if it is unknown
+const SEVEN_TAG_ROSTER: [(&str, &str); 6] = [
+    ("Event", "?"),
+    ("Site", "?"),
+    ("Date", "????.??.??"),
+    ("Round", "?"),
+    ("White", "?"),
+    ("Black", "?"),
+];
+
+/// Returns whether `write_game` writes a tag the same way from the game itself, in which case it
+/// does not need to be stored with the game
+pub(super) fn is_derived(name: &str, value: &str, chess960: bool) -> bool {
+    SEVEN_TAG_ROSTER
+        .iter()
+        .any(|(roster, default)| *roster == name && *default == value)
+        || ["Result", "SetUp", "FEN"].contains(&name)
+        || (chess960 && name == "Variant")
+}
+
+/// Joins movetext tokens with spaces, starting a new line before a token that would overflow it
+struct Movetext {
+    text: String,
+    line_length: usize,
+    /// The last token, which is held back so that the end of a variation can be attached to it
+    last: String,
+    /// The start of the variations that come before the next token
+    prefix: String,
+}
+
+impl Movetext {
+    fn new() -> Self {
+        Self {
+            text: String::new(),
+            line_length: 0,
+            last: String::new(),
+            prefix: String::new(),
+        }
+    }
+
+    fn push(&mut self, token: &str) {
+        self.flush();
+        self.last = std::mem::take(&mut self.prefix) + token;
+    }
+
+    /// Starts a variation, whose parenthesis is attached to the next token
+    fn start_variation(&mut self) {
+        self.prefix.push('(');
+    }
+
+    /// Ends a variation, whose parenthesis is attached to the last token
+    fn end_variation(&mut self) {
+        if !self.prefix.is_empty() {
+            self.push("");
+        }
+
+        if self.last.is_empty() {
+            self.push(")");
+        } else {
+            self.last.push(')');
+        }
+    }
+
+    /// Writes the last token, starting a new line before it if it would overflow the current one
+    fn flush(&mut self) {
+        let token = std::mem::take(&mut self.last);
+
+        if token.is_empty() {
+            return;
+        }
+
+        if self.line_length > 0 {
+            if self.line_length + 1 + token.len() > LINE_WIDTH {
+                self.text.push('\n');
+                self.line_length = 0;
+            } else {
+                self.text.push(' ');
+                self.line_length += 1;
+            }
+        }
+
+        self.text.push_str(&token);
+        self.line_length += token.len();
+    }
+
+    /// Ends the current line after the last token
+    fn break_line(&mut self) {
+        self.flush();
+        self.text.push('\n');
+        self.line_length = 0;
+    }
+
+    fn into_text(mut self) -> String {
+        self.flush();
+        self.text
+    }
+
+    /// Adds the NAGs and comments of a ply, returning whether there were any comments
+    ///
+    /// The evaluation and the clock are written as `[%eval]` and `[%clk]` commands in a comment of
+    /// their own before the others.
+    /// Comments are brace comments unless they hold a closing brace, in which case each of their
+    /// lines becomes a rest of line comment.
+    fn push_annotation(&mut self, annotation: &Annotation) -> bool {
+        for nag in &annotation.nags {
+            self.push(&format!("${nag}"));
+        }
+
+        let commands: Vec<String> = [
+            annotation.eval.map(|eval| format!("[%eval {eval}]")),
+            annotation.clock.map(|clock| format!("[%clk {clock}]")),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        if !commands.is_empty() {
+            self.push(&format!("{{{}}}", commands.join(" ")));
+        }
+
+        for comment in &annotation.comments {
+            if !comment.contains('}') {
+                self.push(&format!("{{{comment}}}"));
+                continue;
+            }
+
+            for line in comment.lines().filter(|line| !line.trim().is_empty()) {
+                self.push(&format!(";{line}"));
+                self.break_line();
+            }
+        }
+
+        !annotation.comments.is_empty() || !commands.is_empty()
+    }
+}
+
+/// Renders the moves of an encoded game as PGN movetext with move numbers, wrapped at 80 columns
+/// and ending with the result token. Comments, NAGs, clocks and evaluations are written after the
+/// moves they follow and variations in parentheses after the moves they replace.
+pub fn write_movetext(data: &[u8]) -> Result<String, DecodeError> {
+    Ok(render(data)?.0)
+}
+
+/// Renders the movetext of a game, returning it along with the reader that has been run to the end
+/// of the game
+fn render(data: &[u8]) -> Result<(String, Reader<'_>), DecodeError> {
+    let mut reader = Reader::try_new(data)?;
+    let mut annotations = reader.get_annotations()?;
+    let mut movetext = Movetext::new();
+
+    // the number of plies before the next move counted from a move by white, so that a game
+    // starting with black to move starts at 1
+    let mut plies = (reader.get_start_position().side_to_move() == Color::Black) as usize;
+    let mut variations = Vec::new();
+
+    if let Some(annotation) = annotations.take(0) {
+        movetext.push_annotation(&annotation);
+    }
+
+    // a move by black only needs its number at the start, after a comment or around a variation
+    let mut needs_number = true;
+
+    loop {
+        let chess = *reader.get_position();
+
+        let Some(node) = reader.try_next_node()? else {
+            break;
+        };
+
+        let is_move = matches!(node, Node::Move(..));
+
+        match node {
+            Node::Move(chess_move, _) => {
+                let san = to_san(&chess_move, &chess);
+                let move_number = plies / 2 + 1;
+
+                if chess.side_to_move() == Color::White {
+                    movetext.push(&format!("{move_number}. {san}"));
+                } else {
+                    if needs_number {
+                        movetext.push(&format!("{move_number}..."));
+                    }
+
+                    movetext.push(&san);
+                }
+
+                plies += 1;
+                needs_number = false;
+            }
+            Node::StartVariation => {
+                variations.push(plies);
+                plies -= 1;
+                movetext.start_variation();
+                needs_number = true;
+            }
+            Node::EndVariation => {
+                plies = variations.pop().expect("The reader checks the variations");
+                movetext.end_variation();
+                needs_number = true;
+            }
+        }
+
+        // inside a variation every node may be annotated, on the main line only the moves
+        let annotation = if reader.get_depth() > 0 {
+            annotations.take_node(reader.get_node_count() - 1)
+        } else if is_move {
+            annotations.take(reader.get_ply())
+        } else {
+            None
+        };
+
+        if let Some(annotation) = annotation {
+            needs_number |= movetext.push_annotation(&annotation);
+        }
+    }
+
+    movetext.push(reader.get_outcome().unwrap_or_default().0.as_str());
+
+    Ok((movetext.into_text(), reader))
+}
+
+/// Renders an encoded game as a complete PGN game, with the seven tag roster first followed by
+/// the rest of `tags` and then the tags stored in the game that are not in `tags`
+///
+/// The `Result` tag is taken from the game itself, and the `SetUp` and `FEN` tags are added when
+/// the game does not start from the default position. Games from a Chess960 start position also
+/// get a `Variant` tag, and their castling rights are written as Shredder-FEN when the `chess`
+/// crate can not castle from the start position.
+pub fn write_game(tags: &[(String, String)], data: &[u8]) -> Result<String, DecodeError> {
+    let (movetext, reader) = render(data)?;
+
+    let stored = reader.get_tags()?;
+    let tags: Vec<(String, String)> = tags
+        .iter()
+        .chain(
+            stored
+                .iter()
+                .filter(|(name, _)| get_tag(tags, name).is_none()),
+        )
+        .cloned()
+        .collect();
+    let tags = tags.as_slice();
+
+    let mut result = String::new();
+
+    let mut push_tag = |name: &str, value: &str| {
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+        result.push_str(&format!("[{name} \"{value}\"]\n"));
+    };
+
+    for (name, default) in SEVEN_TAG_ROSTER {
+        push_tag(name, get_tag(tags, name).unwrap_or(default));
+    }
+
+    push_tag(
+        "Result",
+        reader.get_outcome().unwrap_or_default().0.as_str(),
+    );
+
+    let chess960 = reader.get_chess960_index();
+
+    if let Some(fen) = chess960.and_then(get_start_fen) {
+        push_tag("Variant", "Chess960");
+        push_tag("SetUp", "1");
+        push_tag("FEN", &fen);
+    } else if reader.get_start_position() != &Board::default() {
+        push_tag("SetUp", "1");
+        push_tag("FEN", &reader.get_start_position().to_string());
+    }
+
+    // tags that have already been written from the game itself
+    let is_written = |name: &str| {
+        SEVEN_TAG_ROSTER.iter().any(|(roster, _)| *roster == name)
+            || ["Result", "SetUp", "FEN"].contains(&name)
+            || (chess960.is_some() && name == "Variant")
+    };
+
+    for (name, value) in tags {
+        if !is_written(name) {
+            push_tag(name, value);
+        }
+    }
+
+    result.push('\n');
+    result.push_str(&movetext);
+    result.push('\n');
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use chess::{Board, ChessMove, Square};
+
+    use crate::{pgn::read_game, reader::Reader, writer::Writer};
+
+    use super::{write_game, write_movetext};
+
+    #[test]
+    fn round_trip() {
+        let pgn = "[Event \"Test \\\"quoted\\\"\"]\n[Site \"?\"]\n[Date \"????.??.??\"]\n\
+            [Round \"?\"]\n[White \"?\"]\n[Black \"?\"]\n[Result \"1-0\"]\n[Opening \"Italian\"]\n\n\
+            1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5 4. c3 Nf6 5. d4 exd4 6. cxd4 Bb4+ 7. Bd2 Bxd2+\n\
+            8. Nbxd2 d5 9. exd5 Nxd5 10. Qb3 Nce7 11. O-O O-O 12. Rfe1 c6 13. a4 Qb6 14. Qa3\n\
+            1-0\n";
+
+        let game = read_game(pgn).unwrap().unwrap();
+
+        assert_eq!(write_game(&game.tags, &game.data), Ok(pgn.to_string()));
+    }
+
+    #[test]
+    fn stored_tags() {
+        let pgn = "[Event \"Casual Game\"]\n[Site \"?\"]\n[Date \"1858.??.??\"]\n[Round \"?\"]\n\
+            [White \"Morphy\"]\n[Black \"Duke Karl\"]\n[Result \"1-0\"]\n[WhiteElo \"2690\"]\n\
+            [ECO \"C41\"]\n\n1. e4 e5 1-0\n";
+
+        let game = read_game(pgn).unwrap().unwrap();
+
+        // the placeholders and the result are written from the game itself
+        assert_eq!(
+            Reader::new(&game.data).get_tags().unwrap(),
+            [
+                ("Event", "Casual Game"),
+                ("Date", "1858.??.??"),
+                ("White", "Morphy"),
+                ("Black", "Duke Karl"),
+                ("WhiteElo", "2690"),
+                ("ECO", "C41"),
+            ]
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+        );
+        assert_eq!(write_game(&[], &game.data), Ok(pgn.to_string()));
+
+        // tags that are given replace the ones stored in the game
+        let tags = [("White".to_string(), "Paul Morphy".to_string())];
+
+        assert!(write_game(&tags, &game.data)
+            .unwrap()
+            .contains("[White \"Paul Morphy\"]\n[Black \"Duke Karl\"]\n"));
+    }
+
+    #[test]
+    fn black_to_move() {
+        let game = read_game("[FEN \"4k3/4p3/8/8/8/8/8/4K3 b - - 0 1\"]\n\n1... e5 2. Kd2 Kd7#\n*");
+        let game = game.unwrap().unwrap();
+
+        assert_eq!(
+            write_movetext(&game.data),
+            Ok("1... e5 2. Kd2 Kd7 *".to_string())
+        );
+        assert!(write_game(&game.tags, &game.data)
+            .unwrap()
+            .contains("[SetUp \"1\"]\n[FEN \"4k3/4p3/8/8/8/8/8/4K3 b - - 0 1\"]\n"));
+    }
+
+    #[test]
+    fn annotations() {
+        let movetext = "{Opening} 1. e4 $1 {The best} {move} 1... e5 2. Nf3 $2 $18 Nc6 ;a } here\n\
+            3. Bb5 *";
+
+        let game = read_game(movetext).unwrap().unwrap();
+
+        assert_eq!(write_movetext(&game.data), Ok(movetext.to_string()));
+
+        // suffix annotations are stored as NAGs, inside variations as well
+        let game = read_game("1. e4! (1. d4 {mainline?}) e5?! *")
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            write_movetext(&game.data),
+            Ok("1. e4 $1 (1. d4 {mainline?}) 1... e5 $6 *".to_string())
+        );
+
+        let movetext =
+            "1. e4 e5 ({Or} 1... c5 $5 {Sicilian} 2. Nf3 (2. c3 {Alapin}) 2... d6 {Open})
+\
+            2. Nf3 *";
+
+        let game = read_game(movetext).unwrap().unwrap();
+
+        assert_eq!(write_movetext(&game.data), Ok(movetext.to_string()));
+
+        // a rest of line comment can not hold a line break, so every line gets its own
+        let mut writer = Writer::new();
+        writer.add_move(
+            &ChessMove::new(Square::E2, Square::E4, None),
+            &Board::default(),
+        );
+        writer.add_comment("a } b\n\nc");
+
+        let movetext = write_movetext(&writer.get_data(None)).unwrap();
+
+        assert_eq!(movetext, "1. e4 ;a } b\n;c\n*");
+
+        // which reads back as two comments
+        let game = read_game(&movetext).unwrap().unwrap();
+
+        assert_eq!(
+            write_movetext(&game.data),
+            Ok("1. e4 ;a } b\n{c} *".to_string())
+        );
+    }
+
+    #[test]
+    fn clocks() {
+        let pgn = "[TimeControl \"60+1\"]\n\n\
+            1. e4 { [%clk 0:01:00] } 1... e5 { [%clk 0:01:00] } 2. Nf3 {[%clk 0:00:58.5] Quick}\n\
+            2... Nc6 {[%clk 00:00:59]} 3. Bc4 *";
+
+        let game = read_game(pgn).unwrap().unwrap();
+        let annotations = Reader::new(&game.data).get_annotations().unwrap();
+
+        assert_eq!(annotations.get_clock_increment(), 1000);
+        assert_eq!(annotations.get_move_time(3), Some(2500));
+
+        // the clock that would not be written the same way is kept as a comment
+        assert_eq!(annotations.get(4).unwrap().clock, None);
+        assert_eq!(
+            write_movetext(&game.data),
+            Ok(
+                "1. e4 {[%clk 0:01:00]} 1... e5 {[%clk 0:01:00]} 2. Nf3 {[%clk 0:00:58.5]}\n\
+                {Quick} 2... Nc6 {[%clk 00:00:59]} 3. Bc4 *"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn evals() {
+        let movetext = "1. e4 {[%eval 0.17] [%clk 0:03:00]} 1... e5 {[%eval 0.2]} 2. Qh5 \
+            {[%eval -1.05] Bad} 2... Nc6 {[%eval 0.3,20]} 3. Bc4 {[%eval #-4]} *";
+
+        let game = read_game(movetext).unwrap().unwrap();
+        let mut reader = Reader::new(&game.data);
+        let mut evals = Vec::new();
+
+        while let Some((_, _, annotation)) = reader.try_next_annotated().unwrap() {
+            evals.push(annotation.eval.map(|eval| eval.to_string()));
+        }
+
+        assert_eq!(
+            evals,
+            [Some("0.17"), Some("0.2"), Some("-1.05"), None, Some("#-4")]
+                .map(|eval| eval.map(str::to_string))
+        );
+        assert_eq!(
+            write_movetext(&game.data),
+            Ok("1. e4 {[%eval 0.17] [%clk 0:03:00]} 1... e5 {[%eval 0.2]} 2. Qh5 {[%eval -1.05]}\n\
+                {Bad} 2... Nc6 {[%eval 0.3,20]} 3. Bc4 {[%eval #-4]} *"
+                .to_string())
+        );
+    }
+
+    #[test]
+    fn variations() {
+        let movetext =
+            "1. e4 e5 (1... c5 2. Nf3 (2. c3 d5) 2... d6) (1... e6) 2. Nf3 {Main} 2... Nc6\n\
+            (2... d6 3. d4) 3. Bb5 *";
+
+        let game = read_game(movetext).unwrap().unwrap();
+
+        assert_eq!(write_movetext(&game.data), Ok(movetext.to_string()));
+
+        // the main line is read without the variations
+        assert_eq!(
+            Reader::new(&game.data)
+                .map(|(chess_move, _)| chess_move.to_string())
+                .collect::<Vec<_>>(),
+            ["e2e4", "e7e5", "g1f3", "b8c6", "f1b5"]
+        );
+    }
+
+    #[test]
+    fn annotated_variations() {
+        let pgn = "[Event \"Study\"]\n\n\
+            1. e4 {[%clk 0:03:00]} 1... e5 (1... c5 $1 {[%eval 0.3] [%clk 0:02:59]}\n\
+            {Sicilian} 2. Nf3 (2. c3 {[%eval 0.1]}) 2... d6 {Open}) 2. Nf3 {[%clk 0:02:58]}\n\
+            1-0";
+
+        let game = read_game(pgn).unwrap().unwrap();
+        let exported = write_game(&game.tags, &game.data).unwrap();
+
+        assert!(exported.ends_with(&format!("\n\n{}\n", pgn.split_once("\n\n").unwrap().1)));
+        assert_eq!(read_game(&exported).unwrap().unwrap().data, game.data);
+
+        // the annotations of the variations are found by the node they follow
+        let mut reader = Reader::new(&game.data);
+        let annotations = reader.get_annotations().unwrap();
+        let mut comments = Vec::new();
+
+        while reader.try_next_node().unwrap().is_some() {
+            if let Some(annotation) = annotations.get_node(reader.get_node_count() - 1) {
+                comments.push(annotation.clone());
+            }
+        }
+
+        assert_eq!(comments.len(), 3);
+        assert_eq!(comments[0].comments, ["Sicilian"]);
+        assert_eq!(comments[0].nags, [1]);
+        assert_eq!(comments[0].clock.unwrap().to_string(), "0:02:59");
+        assert_eq!(comments[1].eval.unwrap().to_string(), "0.1");
+        assert_eq!(comments[2].comments, ["Open"]);
+        assert_eq!(
+            annotations.get(1).unwrap().clock.unwrap().to_string(),
+            "0:03:00"
+        );
+    }
+}
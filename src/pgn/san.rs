@@ -0,0 +1,418 @@
+use std::{error::Error, fmt, str::FromStr};
+
+use chess::{Board, BoardStatus, ChessMove, File, MoveGen, Piece, Rank, Square, EMPTY};
+
+use crate::chess960::{is_castle, make_move, Castling};
+
+/// The reason a move in standard algebraic notation could not be resolved
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SanError {
+    /// The text is not valid SAN
+    Invalid(String),
+    /// No legal move matches the SAN
+    Illegal(String),
+    /// More than one legal move matches the SAN
+    Ambiguous(String),
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SanError::Invalid(san) => write!(f, "{san:?} is not a valid move"),
+            SanError::Illegal(san) => write!(f, "{san:?} is not a legal move"),
+            SanError::Ambiguous(san) => write!(f, "{san:?} is ambiguous"),
+        }
+    }
+}
+
+impl Error for SanError {}
+
+fn get_letter(piece: Piece) -> char {
+    match piece {
+        Piece::Pawn => 'P',
+        Piece::Knight => 'N',
+        Piece::Bishop => 'B',
+        Piece::Rook => 'R',
+        Piece::Queen => 'Q',
+        Piece::King => 'K',
+    }
+}
+
+fn get_piece(letter: char) -> Option<Piece> {
+    match letter {
+        'P' => Some(Piece::Pawn),
+        'N' => Some(Piece::Knight),
+        'B' => Some(Piece::Bishop),
+        'R' => Some(Piece::Rook),
+        'Q' => Some(Piece::Queen),
+        'K' => Some(Piece::King),
+        _ => None,
+    }
+}
+
+/// Resolves a move in standard algebraic notation against the position it is played in
+///
+/// Check, mate and suffix annotations are ignored, castling may be written with `O` or `0` and
+/// promotions with or without `=`.
+pub fn parse_san(san: &str, position: &Board) -> Result<ChessMove, SanError> {
+    parse(san, position, &Castling::default(), false)
+}
+
+/// Resolves a move of a Chess960 game in standard algebraic notation like `parse_san`, castling
+/// with the Chess960 castling rights `castling` if the `Board` can not
+///
+/// Castling may also be written as the king capturing its own rook, which is a king move onto a
+/// friendly piece and so illegal in `parse_san`.
+pub fn parse_san_with_castling(
+    san: &str,
+    position: &Board,
+    castling: &Castling,
+) -> Result<ChessMove, SanError> {
+    parse(san, position, castling, true)
+}
+
+fn parse(
+    san: &str,
+    position: &Board,
+    castling: &Castling,
+    chess960: bool,
+) -> Result<ChessMove, SanError> {
+    let invalid = || SanError::Invalid(san.to_string());
+
+    let text = san.trim_end_matches(['+', '#', '!', '?']);
+
+    let kingside = match text {
+        "O-O" | "0-0" => Some(true),
+        "O-O-O" | "0-0-0" => Some(false),
+        _ => None,
+    };
+
+    if let Some(kingside) = kingside {
+        return castle(san, position, castling, kingside);
+    }
+
+    let mut chars = text.chars().peekable();
+
+    let piece = chars.next_if(|char| char.is_ascii_uppercase());
+    let piece = match piece {
+        Some(letter) => get_piece(letter).ok_or_else(invalid)?,
+        None => Piece::Pawn,
+    };
+
+    let rest: String = chars.collect();
+    let rest = rest.as_str();
+
+    let (rest, promotion) = match rest.char_indices().last() {
+        Some((index, letter)) if letter.is_ascii_uppercase() => (
+            rest[..index].trim_end_matches('='),
+            Some(get_piece(letter).ok_or_else(invalid)?),
+        ),
+        _ => (rest, None),
+    };
+
+    if rest.len() < 2 || !rest.is_ascii() {
+        return Err(invalid());
+    }
+
+    let dest = Square::from_str(&rest[rest.len() - 2..]).map_err(|_| invalid())?;
+
+    // castling written as the king capturing its own rook, as in Chess960
+    if chess960
+        && piece == Piece::King
+        && position.piece_on(dest) == Some(Piece::Rook)
+        && position.color_on(dest) == Some(position.side_to_move())
+    {
+        let king = position.king_square(position.side_to_move());
+        let chess_move = castle(san, position, castling, dest.get_file() > king.get_file())?;
+
+        // the rook that castles toward that side may not be the one that was named
+        if is_castle(position, chess_move) && chess_move.get_dest() != dest {
+            return Err(SanError::Illegal(san.to_string()));
+        }
+
+        return Ok(chess_move);
+    }
+
+    let mut starting_file = None;
+    let mut starting_rank = None;
+
+    for char in rest[..rest.len() - 2].trim_end_matches(['x', ':']).chars() {
+        match char {
+            'a'..='h' if starting_file.is_none() && starting_rank.is_none() => {
+                starting_file = Some(File::from_index(char as usize - 'a' as usize))
+            }
+            '1'..='8' if starting_rank.is_none() => {
+                starting_rank = Some(Rank::from_index(char as usize - '1' as usize))
+            }
+            _ => return Err(invalid()),
+        }
+    }
+
+    let mut candidates = MoveGen::new_legal(position).filter(|chess_move| {
+        chess_move.get_dest() == dest
+            && position.piece_on(chess_move.get_source()) == Some(piece)
+            && chess_move.get_promotion() == promotion
+            && starting_file.is_none_or(|file| chess_move.get_source().get_file() == file)
+            && starting_rank.is_none_or(|rank| chess_move.get_source().get_rank() == rank)
+    });
+
+    match (candidates.next(), candidates.next()) {
+        (Some(chess_move), None) => Ok(chess_move),
+        (None, _) => Err(SanError::Illegal(san.to_string())),
+        (Some(_), Some(_)) => Err(SanError::Ambiguous(san.to_string())),
+    }
+}
+
+/// Resolves castling toward the h-file, or toward the a-file if `kingside` is false, which moves the
+/// king onto its rook if it castles with `castling` and two files otherwise
+fn castle(
+    san: &str,
+    position: &Board,
+    castling: &Castling,
+    kingside: bool,
+) -> Result<ChessMove, SanError> {
+    let color = position.side_to_move();
+    let king = position.king_square(color);
+    let dest = castling.get_rook(color, kingside).unwrap_or_else(|| {
+        Square::make_square(king.get_rank(), if kingside { File::G } else { File::C })
+    });
+    let chess_move = ChessMove::new(king, dest, None);
+
+    if castling.legal(position, chess_move) {
+        Ok(chess_move)
+    } else {
+        Err(SanError::Illegal(san.to_string()))
+    }
+}
+
+/// Returns whether a move in standard algebraic notation is castling, either written with `O` or
+/// `0` or as the king capturing its own rook
+pub(crate) fn is_castling(san: &str, position: &Board) -> bool {
+    if san.starts_with("O-O") || san.starts_with("0-0") {
+        return true;
+    }
+
+    let text = san.trim_end_matches(['+', '#', '!', '?']);
+
+    text.starts_with('K')
+        && text.len() >= 3
+        && text.is_ascii()
+        && Square::from_str(&text[text.len() - 2..]).is_ok_and(|square| {
+            position.piece_on(square) == Some(Piece::Rook)
+                && position.color_on(square) == Some(position.side_to_move())
+        })
+}
+
+/// Renders a legal move in standard algebraic notation with the least disambiguation needed and
+/// a check or mate marker
+///
+/// Chess960 castling, written as the king moving onto its own rook, is rendered as `O-O` or `O-O-O`.
+pub fn to_san(chess_move: &ChessMove, position: &Board) -> String {
+    let source = chess_move.get_source();
+    let dest = chess_move.get_dest();
+    let piece = position
+        .piece_on(source)
+        .expect("There is no piece on the source square");
+
+    let mut result = String::new();
+
+    if piece == Piece::King
+        && (source
+            .get_file()
+            .to_index()
+            .abs_diff(dest.get_file().to_index())
+            == 2
+            || is_castle(position, *chess_move))
+    {
+        result.push_str(if dest.get_file() > source.get_file() {
+            "O-O"
+        } else {
+            "O-O-O"
+        });
+    } else {
+        let is_capture = if piece == Piece::Pawn {
+            source.get_file() != dest.get_file()
+        } else {
+            position.piece_on(dest).is_some()
+        };
+
+        if piece == Piece::Pawn {
+            if is_capture {
+                result.push(file_char(source.get_file()));
+            }
+        } else {
+            result.push(get_letter(piece));
+
+            let others: Vec<Square> = MoveGen::new_legal(position)
+                .filter(|other| {
+                    other.get_dest() == dest
+                        && other.get_source() != source
+                        && position.piece_on(other.get_source()) == Some(piece)
+                })
+                .map(|other| other.get_source())
+                .collect();
+
+            if !others.is_empty() {
+                if others
+                    .iter()
+                    .all(|other| other.get_file() != source.get_file())
+                {
+                    result.push(file_char(source.get_file()));
+                } else if others
+                    .iter()
+                    .all(|other| other.get_rank() != source.get_rank())
+                {
+                    result.push(rank_char(source.get_rank()));
+                } else {
+                    result.push(file_char(source.get_file()));
+                    result.push(rank_char(source.get_rank()));
+                }
+            }
+        }
+
+        if is_capture {
+            result.push('x');
+        }
+
+        result.push(file_char(dest.get_file()));
+        result.push(rank_char(dest.get_rank()));
+
+        if let Some(promotion) = chess_move.get_promotion() {
+            result.push('=');
+            result.push(get_letter(promotion));
+        }
+    }
+
+    let after = make_move(position, *chess_move);
+
+    if after.status() == BoardStatus::Checkmate {
+        result.push('#');
+    } else if *after.checkers() != EMPTY {
+        result.push('+');
+    }
+
+    result
+}
+
+fn file_char(file: File) -> char {
+    (b'a' + file.to_index() as u8) as char
+}
+
+fn rank_char(rank: Rank) -> char {
+    (b'1' + rank.to_index() as u8) as char
+}
+
+#[cfg(test)]
+mod tests {
+    use chess::{Board, ChessMove, Piece, Square};
+
+    use crate::chess960::Castling;
+
+    use super::{parse_san, parse_san_with_castling, to_san, SanError};
+
+    #[test]
+    fn parses_moves() {
+        let chess: Board = "r3k2r/1P6/8/8/8/2N3N1/8/R3K2R w KQkq - 0 1"
+            .parse()
+            .unwrap();
+
+        for (san, chess_move) in [
+            ("O-O", ChessMove::new(Square::E1, Square::G1, None)),
+            ("0-0-0+", ChessMove::new(Square::E1, Square::C1, None)),
+            ("Nce4", ChessMove::new(Square::C3, Square::E4, None)),
+            ("Ng3xe4!", ChessMove::new(Square::G3, Square::E4, None)),
+            (
+                "bxa8=Q",
+                ChessMove::new(Square::B7, Square::A8, Some(Piece::Queen)),
+            ),
+            (
+                "b8N#",
+                ChessMove::new(Square::B7, Square::B8, Some(Piece::Knight)),
+            ),
+            ("Rhf1", ChessMove::new(Square::H1, Square::F1, None)),
+        ] {
+            assert_eq!(parse_san(san, &chess), Ok(chess_move), "{san}");
+        }
+
+        // the king capturing its own rook is only castling in Chess960 games
+        for (san, chess_move) in [
+            ("Kxh1", ChessMove::new(Square::E1, Square::G1, None)),
+            ("Ka1", ChessMove::new(Square::E1, Square::C1, None)),
+        ] {
+            assert_eq!(
+                parse_san_with_castling(san, &chess, &Castling::default()),
+                Ok(chess_move)
+            );
+            assert_eq!(
+                parse_san(san, &chess),
+                Err(SanError::Illegal(san.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn renders_moves() {
+        let chess: Board = "r3k2r/1P6/8/3pP3/8/2N3N1/8/R3K2R w KQkq d6 0 1"
+            .parse()
+            .unwrap();
+
+        for (chess_move, san) in [
+            (ChessMove::new(Square::E1, Square::G1, None), "O-O"),
+            (ChessMove::new(Square::E1, Square::C1, None), "O-O-O"),
+            (ChessMove::new(Square::C3, Square::E4, None), "Nce4"),
+            (ChessMove::new(Square::C3, Square::D5, None), "Nxd5"),
+            (ChessMove::new(Square::E5, Square::D6, None), "exd6"),
+            (ChessMove::new(Square::E5, Square::E6, None), "e6"),
+            (ChessMove::new(Square::A1, Square::A8, None), "Rxa8+"),
+            (
+                ChessMove::new(Square::B7, Square::A8, Some(Piece::Queen)),
+                "bxa8=Q+",
+            ),
+            (
+                ChessMove::new(Square::B7, Square::B8, Some(Piece::Knight)),
+                "b8=N",
+            ),
+        ] {
+            assert_eq!(to_san(&chess_move, &chess), san);
+            assert_eq!(parse_san(san, &chess), Ok(chess_move));
+        }
+
+        let chess: Board = "k7/8/1K6/8/8/8/8/7R w - - 0 1".parse().unwrap();
+
+        assert_eq!(
+            to_san(&ChessMove::new(Square::H1, Square::H8, None), &chess),
+            "Rh8#"
+        );
+
+        let chess: Board = "7Q/8/k7/8/8/8/1Q5Q/K7 w - - 0 1".parse().unwrap();
+
+        assert_eq!(
+            to_san(&ChessMove::new(Square::H2, Square::E5, None), &chess),
+            "Qh2e5"
+        );
+    }
+
+    #[test]
+    fn rejects_moves() {
+        let chess: Board = "r3k2r/1P6/8/8/8/2N3N1/8/R3K2R w KQkq - 0 1"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            parse_san("Ne4", &chess),
+            Err(SanError::Ambiguous("Ne4".to_string()))
+        );
+        assert_eq!(
+            parse_san("b8", &chess),
+            Err(SanError::Illegal("b8".to_string()))
+        );
+        assert_eq!(
+            parse_san("Xe4", &chess),
+            Err(SanError::Invalid("Xe4".to_string()))
+        );
+        assert_eq!(
+            parse_san("", &chess),
+            Err(SanError::Invalid("".to_string()))
+        );
+    }
+}
@@ -0,0 +1,263 @@
+use crate::outcome::Outcome;
+
+/// A single lexical element of PGN text
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token<'a> {
+    /// A tag pair with its value unescaped
+    Tag(&'a str, String),
+    /// A move in standard algebraic notation without any suffix annotations
+    San(&'a str),
+    /// The text of a brace or rest of line comment
+    Comment(&'a str),
+    /// A numeric annotation glyph, suffix annotations such as `!?` are converted to these
+    Nag(u8),
+    StartVariation,
+    EndVariation,
+    /// A game termination marker
+    Result(Outcome),
+    /// A brace comment that is never closed
+    UnterminatedComment,
+    /// Text that can not be understood
+    Invalid(&'a str),
+}
+
+/// Splits PGN text into tokens along with their byte offsets, skipping move numbers and escaped
+/// lines
+pub struct Tokenizer<'a> {
+    text: &'a str,
+    position: usize,
+    pending: Option<(usize, Token<'a>)>,
+}
+
+impl<'a> Tokenizer<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            position: 0,
+            pending: None,
+        }
+    }
+
+    /// Returns the 1-based line that a byte offset is on
+    pub fn line(&self, offset: usize) -> usize {
+        self.text[..offset].matches('\n').count() + 1
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.position..]
+    }
+
+    fn skip_line(&mut self) -> &'a str {
+        let rest = self.rest();
+        let end = rest.find('\n').unwrap_or(rest.len());
+
+        self.position += end;
+
+        &rest[..end]
+    }
+
+    fn read_tag(&mut self) -> Option<Token<'a>> {
+        let rest = self.rest();
+        let mut chars = rest.char_indices().skip(1).peekable();
+
+        while chars.next_if(|(_, char)| char.is_whitespace()).is_some() {}
+
+        let name_start = chars.peek()?.0;
+
+        while chars
+            .next_if(|(_, char)| char.is_alphanumeric() || *char == '_')
+            .is_some()
+        {}
+
+        let name = &rest[name_start..chars.peek()?.0];
+
+        while chars.next_if(|(_, char)| char.is_whitespace()).is_some() {}
+
+        if name.is_empty() || chars.next()?.1 != '"' {
+            return None;
+        }
+
+        let mut value = String::new();
+
+        loop {
+            match chars.next()?.1 {
+                '"' => break,
+                '\\' => value.push(chars.next()?.1),
+                char => value.push(char),
+            }
+        }
+
+        while chars.next_if(|(_, char)| char.is_whitespace()).is_some() {}
+
+        let (end, char) = chars.next()?;
+
+        if char != ']' {
+            return None;
+        }
+
+        self.position += end + 1;
+
+        Some(Token::Tag(name, value))
+    }
+
+    fn read_symbol(&mut self) -> Token<'a> {
+        let rest = self.rest();
+        let length = rest
+            .find(|char: char| char.is_whitespace() || "[]{}();$".contains(char))
+            .unwrap_or(rest.len())
+            .max(1);
+        let symbol = &rest[..length];
+
+        match symbol {
+            "1-0" => return self.consume(length, Token::Result(Outcome::WhiteWon)),
+            "0-1" => return self.consume(length, Token::Result(Outcome::BlackWon)),
+            "1/2-1/2" => return self.consume(length, Token::Result(Outcome::Draw)),
+            "*" => return self.consume(length, Token::Result(Outcome::Unknown)),
+            _ => {}
+        }
+
+        let annotation_start = symbol.find(['!', '?']).unwrap_or(length);
+
+        let nag = match &symbol[annotation_start..] {
+            "" => None,
+            "!" => Some(1),
+            "?" => Some(2),
+            "!!" => Some(3),
+            "??" => Some(4),
+            "!?" => Some(5),
+            "?!" => Some(6),
+            _ => return self.consume(length, Token::Invalid(symbol)),
+        };
+
+        if annotation_start == 0 {
+            return self.consume(length, Token::Nag(nag.unwrap()));
+        }
+
+        if let Some(nag) = nag {
+            self.pending = Some((self.position + annotation_start, Token::Nag(nag)));
+        }
+
+        self.consume(length, Token::San(&symbol[..annotation_start]))
+    }
+
+    fn consume(&mut self, length: usize, token: Token<'a>) -> Token<'a> {
+        self.position += length;
+
+        token
+    }
+
+    fn next_token(&mut self) -> Option<(usize, Token<'a>)> {
+        if let Some(pending) = self.pending.take() {
+            return Some(pending);
+        }
+
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+
+            self.position += rest.len() - trimmed.len();
+
+            let at_line_start = self.position == 0 || self.text[..self.position].ends_with('\n');
+
+            // move numbers such as `12.`, `12...` or `...`, which may have a move right after them
+            let digits = trimmed
+                .find(|char: char| !char.is_ascii_digit())
+                .unwrap_or(trimmed.len());
+            let dots = trimmed[digits..]
+                .find(|char: char| char != '.')
+                .unwrap_or(trimmed.len() - digits);
+
+            match trimmed.chars().next()? {
+                '%' if at_line_start => {
+                    self.skip_line();
+                }
+                _ if dots > 0 => self.position += digits + dots,
+                _ => break,
+            }
+        }
+
+        let offset = self.position;
+        let rest = self.rest();
+
+        let token = match rest.chars().next()? {
+            '[' => match self.read_tag() {
+                Some(tag) => tag,
+                None => Token::Invalid(self.skip_line()),
+            },
+            '{' => match rest.find('}') {
+                Some(end) => self.consume(end + 1, Token::Comment(&rest[1..end])),
+                None => self.consume(rest.len(), Token::UnterminatedComment),
+            },
+            ';' => Token::Comment(&self.skip_line()[1..]),
+            '(' => self.consume(1, Token::StartVariation),
+            ')' => self.consume(1, Token::EndVariation),
+            '$' => {
+                let digits = rest[1..]
+                    .find(|char: char| !char.is_ascii_digit())
+                    .unwrap_or(rest.len() - 1);
+
+                match rest[1..=digits].parse() {
+                    Ok(nag) => self.consume(digits + 1, Token::Nag(nag)),
+                    Err(_) => self.consume(digits + 1, Token::Invalid(&rest[..=digits])),
+                }
+            }
+            _ => self.read_symbol(),
+        };
+
+        Some((offset, token))
+    }
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = (usize, Token<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_token()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::outcome::Outcome;
+
+    use super::{Token::*, Tokenizer};
+
+    #[test]
+    fn tokenizes_game() {
+        let tokens: Vec<_> = Tokenizer::new(
+            "[Event \"Te\\\"st\"]\n% escaped line\n1.e4 {good} e5!? 2. Nf3 $1 (2. Nc3 ; line\n) 0-1",
+        )
+        .map(|(_, token)| token)
+        .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Tag("Event", "Te\"st".to_string()),
+                San("e4"),
+                Comment("good"),
+                San("e5"),
+                Nag(5),
+                San("Nf3"),
+                Nag(1),
+                StartVariation,
+                San("Nc3"),
+                Comment(" line"),
+                EndVariation,
+                Result(Outcome::BlackWon),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_input() {
+        let tokens: Vec<_> = Tokenizer::new("[Event Test]\n1. e4 {open")
+            .map(|(_, token)| token)
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![Invalid("[Event Test]"), San("e4"), UnterminatedComment]
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use chess::{BitBoard, Board, BoardBuilder, CastleRights, Color, File, ALL_PIECES};
+
+/// Packs a position into an occupancy bitboard, a nibble per piece and the remaining state
+///
+/// The layout is 8 bytes of occupancy (little endian, A1 first), one nibble per occupied square
+/// in square order (the low three bits are the piece and the high bit is set for black), a byte
+/// holding the side to move in bit 0 and the white and black castle rights in bits 1-2 and 3-4,
+/// and finally the en passant file plus one or zero if there is none.
+pub fn encode_position(position: &Board) -> Vec<u8> {
+    let occupied = position.combined();
+
+    let mut result = occupied.0.to_le_bytes().to_vec();
+
+    let nibbles: Vec<u8> = occupied
+        .into_iter()
+        .map(|square| {
+            let piece = position.piece_on(square).unwrap().to_index() as u8;
+
+            if position.color_on(square) == Some(Color::Black) {
+                piece | 0b1000
+            } else {
+                piece
+            }
+        })
+        .collect();
+
+    result.extend(
+        nibbles
+            .chunks(2)
+            .map(|pair| pair[0] | pair.get(1).copied().unwrap_or_default() << 4),
+    );
+
+    result.push(
+        (position.side_to_move() == Color::Black) as u8
+            | (position.castle_rights(Color::White).to_index() as u8) << 1
+            | (position.castle_rights(Color::Black).to_index() as u8) << 3,
+    );
+
+    result.push(
+        position
+            .en_passant()
+            .map(|square| square.get_file().to_index() as u8 + 1)
+            .unwrap_or_default(),
+    );
+
+    result
+}
+
+/// Unpacks a position written by `encode_position`, returning it and the number of bytes read
+///
+/// Returns `None` if the data is truncated or does not describe a valid position
+pub fn decode_position(data: &[u8]) -> Option<(Board, usize)> {
+    let occupied = BitBoard(u64::from_le_bytes(data.get(..8)?.try_into().ok()?));
+    let piece_bytes = (occupied.popcnt() as usize).div_ceil(2);
+    let length = 8 + piece_bytes + 2;
+
+    let data = data.get(..length)?;
+
+    let mut builder = BoardBuilder::new();
+
+    for (index, square) in occupied.into_iter().enumerate() {
+        let nibble = (data[8 + index / 2] >> (index % 2 * 4)) & 0b1111;
+
+        builder.piece(
+            square,
+            *ALL_PIECES.get((nibble & 0b111) as usize)?,
+            if nibble & 0b1000 == 0 {
+                Color::White
+            } else {
+                Color::Black
+            },
+        );
+    }
+
+    let state = data[length - 2];
+    let en_passant = data[length - 1];
+
+    if state >> 5 != 0 || en_passant > 8 {
+        return None;
+    }
+
+    builder
+        .side_to_move(if state & 1 == 0 {
+            Color::White
+        } else {
+            Color::Black
+        })
+        .castle_rights(
+            Color::White,
+            CastleRights::from_index((state >> 1 & 0b11) as usize),
+        )
+        .castle_rights(
+            Color::Black,
+            CastleRights::from_index((state >> 3 & 0b11) as usize),
+        )
+        .en_passant(
+            en_passant
+                .checked_sub(1)
+                .map(|file| File::from_index(file as usize)),
+        );
+
+    Some((Board::try_from(builder).ok()?, length))
+}
+
+#[cfg(test)]
+mod tests {
+    use chess::Board;
+
+    use super::{decode_position, encode_position};
+
+    #[test]
+    fn default_round_trip() {
+        let data = encode_position(&Board::default());
+
+        assert_eq!(data.len(), 8 + 16 + 2);
+        assert_eq!(decode_position(&data), Some((Board::default(), data.len())));
+    }
+
+    #[test]
+    fn complex_round_trip() {
+        for fen in [
+            "8/8/8/4k3/8/8/8/4K2R w K - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "7k/8/8/8/8/8/8/K7 b - - 0 1",
+        ] {
+            let chess: Board = fen.parse().unwrap();
+            let data = encode_position(&chess);
+
+            assert_eq!(decode_position(&data), Some((chess, data.len())));
+            assert_eq!(decode_position(&data[..data.len() - 1]), None);
+        }
+    }
+}
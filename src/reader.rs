@@ -1,35 +1,1009 @@
+use std::{borrow::Cow, error::Error, fmt, ops::Range};
+
 use chess::{Board, ChessMove, Piece, Square};
 
 use crate::{
-    bitbuffer::BitBuffer,
-    filters::{diagonal::Diagonal, knight::Knight, straight::Straight, Filter},
-    outcome::Outcome,
-    PROMOTION_KEY,
+    annotations::{Annotation, Annotations},
+    bitbuffer::BitReader,
+    checkpoints::{self, Checkpoint},
+    checksum::crc32,
+    chess960::Castling,
+    entropy::EntropyDecoder,
+    filters::{
+        diagonal::Diagonal, get_candidates, get_code_length, get_legal_promotions, knight::Knight,
+        straight::Straight, Filter,
+    },
+    header::{
+        has_header, read_header, Header, CHECKPOINTS_SECTION, CHECKSUM, COMPACT_OVERFLOW, ENTROPY,
+        TAGS_SECTION,
+    },
+    outcome::{Outcome, Termination},
+    position::decode_position,
+    tags,
+    varint::read_varint,
+    PROMOTION_KEY, START_POSITION_MARKER, VARIATION_END, VARIATION_START,
 };
 
+/// The reason a buffer could not be decoded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeErrorKind {
+    /// The buffer ended before any core byte could be read
+    TruncatedCore,
+    /// The header is truncated or one of its sections is invalid
+    InvalidHeader,
+    /// The checksum at the end of the buffer does not match its contents
+    ChecksumMismatch,
+    /// The header is from a version of the format that is not supported
+    UnsupportedVersion(u8),
+    /// The header has flags set that are not known, which are returned
+    UnknownFlags(u16),
+    /// The start position is truncated or does not describe a valid position
+    InvalidStartPosition,
+    /// The core stream never reached a terminator byte
+    MissingTerminator,
+    /// The terminator byte does not hold a known outcome
+    InvalidTerminator(u8),
+    /// A move needed more overflow bits than were left after the terminator
+    OverflowExhausted,
+    /// No piece can reach the target square using the filter of the core byte
+    NoCandidates(Square),
+    /// The overflow index selected a direction that no piece can move from
+    EmptyCandidate(u8),
+    /// The decoded move is not legal in the current position
+    IllegalMove(ChessMove),
+    /// The entropy coded rank is past the last legal move of the current position
+    RankOutOfRange(usize),
+    /// There are unread bytes left over after the game has ended
+    TrailingData,
+    /// A variation starts before any move it could replace, ends without having started or is
+    /// still open at the terminator
+    InvalidVariation,
+    /// The main line ends before the ply that was sought
+    PlyOutOfRange(usize),
+}
+
+/// An error produced while decoding a buffer along with where it happened
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecodeError {
+    pub kind: DecodeErrorKind,
+    /// The index of the ply that was being decoded
+    pub ply: usize,
+    /// The offset in bytes into the buffer of the offending data
+    pub offset: usize,
+}
+
+impl DecodeError {
+    fn new(kind: DecodeErrorKind, ply: usize, offset: usize) -> Self {
+        Self { kind, ply, offset }
+    }
+}
+
+impl fmt::Display for DecodeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeErrorKind::TruncatedCore => write!(f, "the core stream is truncated"),
+            DecodeErrorKind::InvalidHeader => write!(f, "the header is invalid"),
+            DecodeErrorKind::ChecksumMismatch => {
+                write!(f, "the checksum does not match, the data is corrupt")
+            }
+            DecodeErrorKind::UnsupportedVersion(version) => {
+                write!(f, "format version {version} is not supported")
+            }
+            DecodeErrorKind::UnknownFlags(flags) => {
+                write!(f, "the header has unknown flags {flags:#010b}")
+            }
+            DecodeErrorKind::InvalidStartPosition => write!(f, "the start position is invalid"),
+            DecodeErrorKind::MissingTerminator => write!(f, "the terminator byte is missing"),
+            DecodeErrorKind::InvalidTerminator(byte) => {
+                write!(f, "the terminator byte {byte:#010b} is not a known outcome")
+            }
+            DecodeErrorKind::OverflowExhausted => write!(f, "ran out of overflow bits"),
+            DecodeErrorKind::NoCandidates(square) => {
+                write!(f, "no piece can move to {square}")
+            }
+            DecodeErrorKind::EmptyCandidate(index) => {
+                write!(f, "overflow index {index} points at an empty candidate")
+            }
+            DecodeErrorKind::IllegalMove(chess_move) => {
+                write!(f, "decoded the illegal move {chess_move}")
+            }
+            DecodeErrorKind::RankOutOfRange(rank) => {
+                write!(f, "there is no legal move with rank {rank}")
+            }
+            DecodeErrorKind::TrailingData => write!(f, "found trailing data after the game"),
+            DecodeErrorKind::InvalidVariation => write!(f, "the variations are unbalanced"),
+            DecodeErrorKind::PlyOutOfRange(ply) => {
+                write!(f, "the main line ends before ply {ply}")
+            }
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (ply {}, byte offset {})",
+            self.kind, self.ply, self.offset
+        )
+    }
+}
+
+impl Error for DecodeError {}
+
+/// A step through the move tree of a game, returned by `Reader::try_next_node`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Node {
+    /// A move along with the position after it
+    Move(ChessMove, Board),
+    /// The start of a variation that replaces the last move, so the moves that follow are played
+    /// from the position before it
+    StartVariation,
+    /// The end of the innermost variation, after which the line it branched from continues from
+    /// where it was
+    EndVariation,
+}
+
+/// A position along with the Chess960 castling rights the `chess` crate can not hold
+type State = (Board, Castling);
+
+/// Decodes a game one move at a time
+///
+/// The reader borrows the buffer it decodes and walks over it with cursors, so creating one and
+/// advancing it never copies the game. `from_vec` creates a reader that owns its buffer instead.
 #[derive(Clone)]
-pub struct Reader {
-    data: Vec<u8>,
+pub struct Reader<'a> {
+    data: Cow<'a, [u8]>,
     chess: Board,
-    bit_buffer: BitBuffer,
-    outcome: Option<Outcome>,
+    /// The Chess960 castling rights of the position the next move is played from
+    castling: Castling,
+    start_position: Board,
+    header: Option<Header>,
+    chess960: Option<u16>,
+    /// The termination stored in the header, which is returned once the terminator is read
+    extended_termination: Termination,
+    metadata: Option<Range<usize>>,
+    /// The ids of the extension sections of the header and where they are in the buffer
+    sections: Vec<(u8, Range<usize>)>,
+    /// The annotations that have not been returned yet, which are only parsed once
+    /// `try_next_annotated` is called
+    annotations: Option<Annotations>,
+    /// The checkpoints of the game, which are only parsed once `seek` is called
+    checkpoints: Option<Vec<Checkpoint>>,
+    outcome: Option<(Outcome, Termination)>,
+    /// The number of moves of the main line that have been decoded
+    ply: usize,
+    /// The position and castling rights before the last move of the current line, which a
+    /// variation starts from
+    previous: Option<State>,
+    /// The position and the previous position of every line that a variation branched from
+    variations: Vec<(State, Option<State>)>,
+    core_start: usize,
+    /// The number of core bytes that have been read, including variation markers
+    core_cursor: usize,
+    overflow_start: usize,
+    /// The end of the overflow section, which is before the checksum if there is one
+    overflow_end: usize,
+    /// The number of overflow bits that have been read
+    overflow_cursor: usize,
+    /// The offset of the terminator byte, which is only known up front for entropy coded games
+    terminator: usize,
+    /// The decoder of an entropy coded game, whose ranks start after the terminator
+    entropy: Option<EntropyDecoder>,
+    finished: bool,
 }
 
-impl Reader {
+impl<'a> Reader<'a> {
     /// Creates a new reader based on the buffer provided
-    /// It also creates a new default chess board
-    pub fn new(data: &[u8]) -> Self {
+    /// It also creates a new chess board from the start position stored in the buffer or the
+    /// default one if there is none
+    ///
+    /// Like the iterator, this does not look for the terminator up front, so a core stream that is
+    /// not terminated only fails once it is reached.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the header is invalid, use `try_new` to handle this
+    pub fn new(data: &'a [u8]) -> Self {
+        Self::from_cow(Cow::Borrowed(data), false).expect("Could not read the header")
+    }
+
+    /// Creates a new reader based on the buffer provided, returning an error if the header is
+    /// invalid or the core stream is not terminated
+    pub fn try_new(data: &'a [u8]) -> Result<Self, DecodeError> {
+        Self::from_cow(Cow::Borrowed(data), true)
+    }
+
+    /// Creates a new reader that owns its buffer, which is as lenient as `new`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the header is invalid, use `try_from_vec` to handle this
+    pub fn from_vec(data: Vec<u8>) -> Reader<'static> {
+        Reader::from_cow(Cow::Owned(data), false).expect("Could not read the header")
+    }
+
+    /// Creates a new reader that owns its buffer, returning an error if the header is invalid or
+    /// the core stream is not terminated
+    pub fn try_from_vec(data: Vec<u8>) -> Result<Reader<'static>, DecodeError> {
+        Reader::from_cow(Cow::Owned(data), true)
+    }
+
+    /// Copies the buffer if it is borrowed so the reader can outlive it
+    pub fn into_owned(self) -> Reader<'static> {
         Reader {
-            data: data.to_vec(),
-            chess: Board::default(),
-            bit_buffer: BitBuffer::from_bytes(data),
+            data: Cow::Owned(self.data.into_owned()),
+            chess: self.chess,
+            castling: self.castling,
+            start_position: self.start_position,
+            header: self.header,
+            chess960: self.chess960,
+            extended_termination: self.extended_termination,
+            metadata: self.metadata,
+            sections: self.sections,
+            annotations: self.annotations,
+            checkpoints: self.checkpoints,
+            outcome: self.outcome,
+            ply: self.ply,
+            previous: self.previous,
+            variations: self.variations,
+            core_start: self.core_start,
+            core_cursor: self.core_cursor,
+            overflow_start: self.overflow_start,
+            overflow_end: self.overflow_end,
+            overflow_cursor: self.overflow_cursor,
+            terminator: self.terminator,
+            entropy: self.entropy,
+            finished: self.finished,
+        }
+    }
+
+    /// Reads the header and finds the end of the core stream, which is only required to exist
+    /// when `strict` is set
+    fn from_cow(data: Cow<'a, [u8]>, strict: bool) -> Result<Self, DecodeError> {
+        let mut header = None;
+        let mut chess960 = None;
+        let mut extended_termination = Termination::Unspecified;
+        let mut metadata = None;
+        let mut sections = Vec::new();
+
+        let (chess, core_start) = if has_header(&data) {
+            let header_data =
+                read_header(&data).map_err(|(kind, offset)| DecodeError::new(kind, 0, offset))?;
+
+            header = Some(header_data.header);
+            chess960 = header_data.chess960;
+            extended_termination = header_data.termination;
+            metadata = header_data.metadata;
+            sections = header_data.sections;
+
+            (
+                header_data.start_position.unwrap_or_default(),
+                header_data.length,
+            )
+        } else if data.first() == Some(&START_POSITION_MARKER) {
+            let (chess, length) = decode_position(&data[1..]).ok_or(DecodeError::new(
+                DecodeErrorKind::InvalidStartPosition,
+                0,
+                1,
+            ))?;
+
+            (chess, length + 1)
+        } else {
+            (Board::default(), 0)
+        };
+
+        let end = if header.is_some_and(|header| header.has_flag(CHECKSUM)) {
+            let end = data
+                .len()
+                .checked_sub(4)
+                .filter(|end| *end >= core_start)
+                .ok_or(DecodeError::new(
+                    DecodeErrorKind::TruncatedCore,
+                    0,
+                    data.len(),
+                ))?;
+
+            if crc32(&data[..end]).to_le_bytes() != data[end..] {
+                return Err(DecodeError::new(DecodeErrorKind::ChecksumMismatch, 0, end));
+            }
+
+            end
+        } else {
+            data.len()
+        };
+
+        if end == core_start {
+            return Err(DecodeError::new(
+                DecodeErrorKind::TruncatedCore,
+                0,
+                core_start,
+            ));
+        }
+
+        if header.is_some_and(|header| header.has_flag(ENTROPY)) {
+            let (plies, length) = read_varint(&data[core_start..end]).ok_or(DecodeError::new(
+                DecodeErrorKind::TruncatedCore,
+                0,
+                core_start,
+            ))?;
+
+            let terminator = core_start + length;
+
+            if terminator == end {
+                return Err(DecodeError::new(
+                    DecodeErrorKind::MissingTerminator,
+                    0,
+                    terminator,
+                ));
+            }
+
+            let entropy = usize::try_from(plies)
+                .ok()
+                .and_then(|plies| EntropyDecoder::new(&data[terminator + 1..end], plies).ok())
+                .ok_or(DecodeError::new(
+                    DecodeErrorKind::TruncatedCore,
+                    0,
+                    terminator + 1,
+                ))?;
+
+            return Ok(Reader {
+                data,
+                chess,
+                castling: get_start_castling(chess960),
+                start_position: chess,
+                header,
+                chess960,
+                extended_termination,
+                metadata,
+                sections,
+                annotations: None,
+                checkpoints: None,
+                outcome: None,
+                ply: 0,
+                previous: None,
+                variations: Vec::new(),
+                core_start,
+                core_cursor: 0,
+                overflow_start: end,
+                overflow_end: end,
+                overflow_cursor: 0,
+                terminator,
+                entropy: Some(entropy),
+                finished: false,
+            });
+        }
+
+        let terminator = match data[core_start..end]
+            .iter()
+            .position(|byte| byte >> 6 == 0 && !is_variation_marker(*byte))
+        {
+            Some(position) => core_start + position,
+            None if strict => {
+                return Err(DecodeError::new(
+                    DecodeErrorKind::MissingTerminator,
+                    end - core_start,
+                    end,
+                ))
+            }
+            // there is no overflow section, and decoding fails once the end is reached
+            None => end - 1,
+        };
+
+        Ok(Reader {
+            data,
+            chess,
+            castling: get_start_castling(chess960),
+            start_position: chess,
+            header,
+            chess960,
+            extended_termination,
+            metadata,
+            sections,
+            annotations: None,
+            checkpoints: None,
             outcome: None,
+            ply: 0,
+            previous: None,
+            variations: Vec::new(),
+            core_start,
+            core_cursor: 0,
+            overflow_start: terminator + 1,
+            overflow_end: end,
+            overflow_cursor: 0,
+            terminator,
+            entropy: None,
+            finished: false,
+        })
+    }
+
+    fn get_bit_reader(&self) -> BitReader<'_> {
+        BitReader::with_cursor(
+            &self.data[self.overflow_start..self.overflow_end],
+            self.overflow_cursor,
+        )
+    }
+
+    /// The position the game started from
+    pub fn get_start_position(&self) -> &Board {
+        &self.start_position
+    }
+
+    /// The version and flags of the buffer, or `None` for a legacy buffer without a header
+    pub fn get_header(&self) -> Option<&Header> {
+        self.header.as_ref()
+    }
+
+    /// The number of the Chess960 start position the game started from, if it was stored as one
+    pub fn get_chess960_index(&self) -> Option<u16> {
+        self.chess960
+    }
+
+    /// The metadata section of the header, if there is one
+    pub fn get_metadata(&self) -> Option<&[u8]> {
+        self.metadata.clone().map(|range| &self.data[range])
+    }
+
+    /// The contents of the extension section of the header with `id`, if there is one
+    pub fn get_section(&self, id: u8) -> Option<&[u8]> {
+        self.sections
+            .iter()
+            .find(|(other, _)| *other == id)
+            .map(|(_, range)| &self.data[range.clone()])
+    }
+
+    /// Parses the comments, NAGs and clocks of the game, which are empty if it has none
+    pub fn get_annotations(&self) -> Result<Annotations, DecodeError> {
+        let mut annotations = Annotations::new();
+
+        for (id, range) in &self.sections {
+            annotations
+                .decode_section(*id, &self.data[range.clone()])
+                .ok_or(DecodeError::new(
+                    DecodeErrorKind::InvalidHeader,
+                    0,
+                    range.start,
+                ))?;
+        }
+
+        Ok(annotations)
+    }
+
+    /// Parses the tag pairs of the game in the order they were written, which are empty if it has
+    /// none
+    pub fn get_tags(&self) -> Result<Vec<(String, String)>, DecodeError> {
+        let Some((_, range)) = self.sections.iter().find(|(id, _)| *id == TAGS_SECTION) else {
+            return Ok(Vec::new());
+        };
+
+        tags::decode(&self.data[range.clone()]).ok_or(DecodeError::new(
+            DecodeErrorKind::InvalidHeader,
+            0,
+            range.start,
+        ))
+    }
+
+    /// The number of plies between the checkpoints of the game, if it has any
+    pub fn get_checkpoint_interval(&self) -> Option<usize> {
+        let (interval, _) = read_varint(self.get_section(CHECKPOINTS_SECTION)?)?;
+
+        usize::try_from(interval).ok()
+    }
+
+    /// Parses the checkpoints of the game, which are empty if it has none
+    fn get_checkpoints(&self) -> Result<Vec<Checkpoint>, DecodeError> {
+        let Some((_, range)) = self
+            .sections
+            .iter()
+            .find(|(id, _)| *id == CHECKPOINTS_SECTION)
+        else {
+            return Ok(Vec::new());
+        };
+
+        // entropy coded games are decoded with a model that a checkpoint can not hold
+        if self.entropy.is_some() {
+            return Ok(Vec::new());
+        }
+
+        let overflow_bits = (self.overflow_end - self.overflow_start) * 8;
+
+        checkpoints::decode(
+            &self.data[range.clone()],
+            &get_start_castling(self.chess960),
+        )
+        .map(|(_, checkpoints)| checkpoints)
+        .filter(|checkpoints| {
+            checkpoints.iter().all(|checkpoint| {
+                checkpoint.core_cursor < self.terminator - self.core_start
+                    && checkpoint.overflow_cursor <= overflow_bits
+            })
+        })
+        .ok_or(DecodeError::new(
+            DecodeErrorKind::InvalidHeader,
+            0,
+            range.start,
+        ))
+    }
+
+    /// Moves the reader to right after the move of the main line that leads to `ply`, so that
+    /// `get_position` is the position at that ply and the next node is the first variation that
+    /// replaces the move, if it has any. Seeking to ply 0 goes back to the start of the game.
+    ///
+    /// The reader starts from the closest checkpoint before the ply, or carries on from where it
+    /// is if that is closer, so with checkpoints every K plies at most K moves are replayed.
+    /// Without checkpoints seeking backwards replays the game from the start.
+    ///
+    /// If the main line ends before `ply` the reader is finished and `PlyOutOfRange` is returned.
+    pub fn seek(&mut self, ply: usize) -> Result<(), DecodeError> {
+        if self.checkpoints.is_none() {
+            self.checkpoints = Some(self.get_checkpoints()?);
+        }
+
+        let checkpoint = self
+            .checkpoints
+            .iter()
+            .flatten()
+            .rev()
+            .find(|checkpoint| checkpoint.ply < ply)
+            .cloned();
+
+        let carry_on = !self.finished
+            && self.variations.is_empty()
+            && self.ply < ply
+            && checkpoint
+                .as_ref()
+                .is_none_or(|checkpoint| self.ply >= checkpoint.ply);
+
+        if !carry_on {
+            self.restore(checkpoint);
+        }
+
+        while self.ply < ply {
+            if self.try_next()?.is_none() {
+                return Err(self.error(DecodeErrorKind::PlyOutOfRange(ply)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Puts the reader back in the state of a checkpoint, or of the start of the game
+    fn restore(&mut self, checkpoint: Option<Checkpoint>) {
+        let checkpoint = checkpoint.unwrap_or(Checkpoint {
+            ply: 0,
+            position: self.start_position,
+            castling: get_start_castling(self.chess960),
+            core_cursor: 0,
+            overflow_cursor: 0,
+        });
+
+        self.ply = checkpoint.ply;
+        self.chess = checkpoint.position;
+        self.castling = checkpoint.castling;
+        self.core_cursor = checkpoint.core_cursor;
+        self.overflow_cursor = checkpoint.overflow_cursor;
+        self.previous = None;
+        self.variations.clear();
+        self.outcome = None;
+        self.finished = false;
+
+        // the annotations of the plies that are decoded again may have been returned already
+        self.annotations = None;
+
+        if let Some(entropy) = &mut self.entropy {
+            entropy.restart(&self.data[self.terminator + 1..self.overflow_end]);
+        }
+    }
+
+    /// The number of moves of the main line that have been decoded so far
+    pub fn get_ply(&self) -> usize {
+        self.ply
+    }
+
+    /// The position the next move is played from
+    pub fn get_position(&self) -> &Board {
+        &self.chess
+    }
+
+    /// The Chess960 castling rights of the position the next move is played from, which are empty
+    /// unless the game started from a position that the `chess` crate can not castle from
+    pub fn get_castling(&self) -> &Castling {
+        &self.castling
+    }
+
+    /// The number of variations the next move is nested in, which is 0 on the main line
+    pub fn get_depth(&self) -> usize {
+        self.variations.len()
+    }
+
+    /// The number of moves and variation markers read from the core stream, so the node that
+    /// `try_next_node` returned last has the index one less, which its annotations are keyed by
+    /// inside a variation
+    pub fn get_node_count(&self) -> usize {
+        match self.entropy {
+            Some(_) => self.ply,
+            None => self.core_cursor,
+        }
+    }
+
+    /// The offset in bytes of the next core byte to be read
+    ///
+    /// Entropy coded games have no core bytes, so this is always the offset of their terminator
+    pub fn get_core_offset(&self) -> usize {
+        match self.entropy {
+            Some(_) => self.terminator,
+            None => self.core_start + self.core_cursor,
+        }
+    }
+
+    /// The offsets of the start of the core stream, the end of it after the terminator and the
+    /// end of the overflow section or the coded moves of an entropy coded game
+    pub(crate) fn get_sections(&self) -> (usize, usize, usize) {
+        (self.core_start, self.terminator + 1, self.overflow_end)
+    }
+
+    /// The number of overflow bits that have not been read yet
+    pub fn get_overflow_left(&self) -> usize {
+        self.get_bit_reader().len()
+    }
+
+    /// The result of the game and why it ended, which are only known once the terminator has been
+    /// read
+    pub fn get_outcome(&self) -> Option<(Outcome, Termination)> {
+        self.outcome
+    }
+
+    /// Decodes the next move of the main line, returning `Ok(None)` once the terminator has been
+    /// reached
+    ///
+    /// Variations are decoded and skipped, use `try_next_node` to walk them. After an error has
+    /// been returned the reader is finished and will only return `Ok(None)`.
+    pub fn try_next(&mut self) -> Result<Option<(ChessMove, Board)>, DecodeError> {
+        self.next_move(true)
+    }
+
+    /// Decodes the next move of the main line, only checking for trailing data when `strict` is
+    /// set
+    fn next_move(&mut self, strict: bool) -> Result<Option<(ChessMove, Board)>, DecodeError> {
+        while let Some(node) = self.next_node(strict)? {
+            if let Node::Move(chess_move, board) = node {
+                if self.variations.is_empty() {
+                    return Ok(Some((chess_move, board)));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Decodes the next move or variation marker, returning `Ok(None)` once the terminator has
+    /// been reached
+    ///
+    /// The moves of a variation are played from the position before the move it replaces, so the
+    /// board returned with every move is the one on its own line. After an error has been returned
+    /// the reader is finished and will only return `Ok(None)`.
+    pub fn try_next_node(&mut self) -> Result<Option<Node>, DecodeError> {
+        self.next_node(true)
+    }
+
+    fn next_node(&mut self, strict: bool) -> Result<Option<Node>, DecodeError> {
+        if self.finished {
+            return Ok(None);
+        }
+
+        let result = self.decode_next(strict);
+
+        if !matches!(result, Ok(Some(_))) {
+            self.finished = true;
+        }
+
+        result
+    }
+
+    /// Decodes the next move of the main line along with the comments, NAGs and clock that follow
+    /// it, which are empty if it has none
+    ///
+    /// The annotations are parsed the first time this is called, so reading a game with `try_next`
+    /// never pays for them. Annotations before the first move are only returned by
+    /// `get_annotations`.
+    pub fn try_next_annotated(
+        &mut self,
+    ) -> Result<Option<(ChessMove, Board, Annotation)>, DecodeError> {
+        if self.annotations.is_none() && !self.finished {
+            match self.get_annotations() {
+                Ok(annotations) => self.annotations = Some(annotations),
+                Err(error) => {
+                    self.finished = true;
+                    return Err(error);
+                }
+            }
+        }
+
+        let Some((chess_move, board)) = self.try_next()? else {
+            return Ok(None);
+        };
+
+        let annotation = self
+            .annotations
+            .as_mut()
+            .and_then(|annotations| annotations.take(self.ply))
+            .unwrap_or_default();
+
+        Ok(Some((chess_move, board, annotation)))
+    }
+
+    /// Returns an iterator that yields each move or the first error encountered
+    pub fn try_iter(
+        &mut self,
+    ) -> impl Iterator<Item = Result<(ChessMove, Board), DecodeError>> + use<'_, 'a> {
+        std::iter::from_fn(move || self.try_next().transpose())
+    }
+
+    fn error(&self, kind: DecodeErrorKind) -> DecodeError {
+        DecodeError::new(kind, self.ply, self.get_core_offset())
+    }
+
+    fn overflow_error(&self, kind: DecodeErrorKind) -> DecodeError {
+        // the overflow section is read backwards, so the unread bits are at the start of it
+        DecodeError::new(
+            kind,
+            self.ply,
+            self.overflow_start + self.get_overflow_left().saturating_sub(1) / 8,
+        )
+    }
+
+    /// Decodes the next node, checking that nothing follows the game when `strict` is set
+    fn decode_next(&mut self, strict: bool) -> Result<Option<Node>, DecodeError> {
+        if let Some(entropy) = &mut self.entropy {
+            if self.ply < entropy.get_plies() {
+                let start = self.terminator + 1;
+                let chess_move = entropy
+                    .decode_move(
+                        &self.data[start..self.overflow_end],
+                        &self.chess,
+                        &self.castling,
+                    )
+                    .map_err(|kind| {
+                        DecodeError::new(
+                            kind,
+                            self.ply,
+                            (start + entropy.get_position()).min(self.overflow_end),
+                        )
+                    })?;
+
+                self.ply += 1;
+                self.chess = self.castling.make_move(&self.chess, chess_move);
+
+                return Ok(Some(Node::Move(chess_move, self.chess)));
+            }
+        }
+
+        let byte = *self
+            .data
+            .get(self.get_core_offset())
+            .ok_or_else(|| self.error(DecodeErrorKind::MissingTerminator))?;
+
+        if byte >> 6 != 0 && self.entropy.is_some() {
+            return Err(self.error(DecodeErrorKind::InvalidTerminator(byte)));
+        }
+
+        if self.entropy.is_none() && byte == VARIATION_START {
+            let previous = self
+                .previous
+                .ok_or_else(|| self.error(DecodeErrorKind::InvalidVariation))?;
+
+            self.variations
+                .push(((self.chess, self.castling), self.previous));
+            (self.chess, self.castling) = previous;
+            self.previous = None;
+            self.core_cursor += 1;
+
+            return Ok(Some(Node::StartVariation));
+        }
+
+        if self.entropy.is_none() && byte == VARIATION_END {
+            ((self.chess, self.castling), self.previous) = self
+                .variations
+                .pop()
+                .ok_or_else(|| self.error(DecodeErrorKind::InvalidVariation))?;
+            self.core_cursor += 1;
+
+            return Ok(Some(Node::EndVariation));
+        }
+
+        if byte >> 6 == 0 {
+            if !self.variations.is_empty() {
+                return Err(self.error(DecodeErrorKind::InvalidVariation));
+            }
+
+            let outcome = Outcome::try_from(byte)
+                .map_err(|_| self.error(DecodeErrorKind::InvalidTerminator(byte)))?;
+
+            if !strict {
+                self.outcome = Some((outcome, self.extended_termination));
+                return Ok(None);
+            }
+
+            // the coded ranks of an entropy coded game end with its last move
+            if let Some(entropy) = &self.entropy {
+                let start = self.terminator + 1;
+
+                if !entropy.is_exhausted(&self.data[start..self.overflow_end]) {
+                    return Err(DecodeError::new(
+                        DecodeErrorKind::TrailingData,
+                        self.ply,
+                        start + entropy.get_position(),
+                    ));
+                }
+            }
+
+            // anything other than the zeroed padding of the last overflow byte is garbage
+            let mut bit_reader = self.get_bit_reader();
+            let remaining = bit_reader.len();
+
+            if remaining >= 8 || bit_reader.read(remaining as u8) != 0 {
+                return Err(self.overflow_error(DecodeErrorKind::TrailingData));
+            }
+
+            self.outcome = Some((outcome, self.extended_termination));
+            return Ok(None);
+        }
+
+        let mut bit_reader = self.get_bit_reader();
+
+        let compact = self
+            .header
+            .is_some_and(|header| header.has_flag(COMPACT_OVERFLOW));
+
+        let chess_move = decode_move(
+            byte,
+            &self.chess,
+            &self.castling,
+            compact,
+            self.chess960.is_some(),
+            |length| bit_reader.try_read(length).map(|bits| bits as u8),
+        );
+
+        let overflow_cursor = bit_reader.get_cursor();
+
+        let chess_move = chess_move.map_err(|kind| match kind {
+            DecodeErrorKind::OverflowExhausted | DecodeErrorKind::EmptyCandidate(_) => {
+                self.overflow_error(kind)
+            }
+            _ => self.error(kind),
+        })?;
+
+        self.overflow_cursor = overflow_cursor;
+        self.core_cursor += 1;
+
+        if self.variations.is_empty() {
+            self.ply += 1;
+        }
+
+        self.previous = Some((self.chess, self.castling));
+        self.chess = self.castling.make_move(&self.chess, chess_move);
+
+        Ok(Some(Node::Move(chess_move, self.chess)))
+    }
+}
+
+/// The castling rights at the start of a game that is stored as Chess960 start position `index`,
+/// which has no rights outside of the `Board` otherwise
+fn get_start_castling(index: Option<u16>) -> Castling {
+    index.map(Castling::from_start_index).unwrap_or_default()
+}
+
+/// Whether a byte with filter id 0 marks the start or the end of a variation instead of
+/// terminating the core stream
+fn is_variation_marker(byte: u8) -> bool {
+    byte == VARIATION_START || byte == VARIATION_END
+}
+
+/// Decodes a single move core byte against the position it was played in and its Chess960
+/// castling rights
+///
+/// Overflow bits are requested from `read_overflow` by length, which returns `None` once there are
+/// no bits left. With `compact` the overflow codes are read as described in `header::COMPACT_OVERFLOW`
+/// and with `chess960` the candidates are those of games flagged `header::CHESS960`.
+pub(crate) fn decode_move(
+    byte: u8,
+    position: &Board,
+    castling: &Castling,
+    compact: bool,
+    chess960: bool,
+    mut read_overflow: impl FnMut(u8) -> Option<u8>,
+) -> Result<ChessMove, DecodeErrorKind> {
+    let to = safe_get_square(byte & 0b00111111);
+    let raw_id = byte >> 6;
+
+    // castling is the only move onto a piece of the side to move, see `Straight`
+    if raw_id == Straight::get_raw_id() && position.color_on(to) == Some(position.side_to_move()) {
+        let chess_move = ChessMove::new(position.king_square(position.side_to_move()), to, None);
+
+        if !castling.legal(position, chess_move) {
+            return Err(DecodeErrorKind::IllegalMove(chess_move));
         }
+
+        return Ok(chess_move);
     }
 
-    pub fn get_outcome(&self) -> &Option<Outcome> {
-        &self.outcome
+    let mut read_index = |length| read_overflow(length).ok_or(DecodeErrorKind::OverflowExhausted);
+
+    let from = if compact {
+        let candidates = get_candidates(raw_id, &to, position, chess960);
+
+        let index = match candidates.len() {
+            0 => return Err(DecodeErrorKind::NoCandidates(to)),
+            1 => 0,
+            count => read_index(get_code_length(count))?,
+        };
+
+        *candidates
+            .get(index as usize)
+            .ok_or(DecodeErrorKind::EmptyCandidate(index))?
+    } else {
+        let (square_data, overflow_length) = match raw_id {
+            3 => (
+                Straight::get_square_data(&to, position, chess960),
+                Straight::get_overflow_length(),
+            ),
+            2 => (
+                Diagonal::get_square_data(&to, position, chess960),
+                Diagonal::get_overflow_length(),
+            ),
+            1 => (
+                Knight::get_square_data(&to, position, chess960),
+                Knight::get_overflow_length(),
+            ),
+            _ => unreachable!("A filter id of 0 is a terminator byte"),
+        };
+
+        let index = match square_data.iter().filter(|square| square.is_some()).count() {
+            0 => return Err(DecodeErrorKind::NoCandidates(to)),
+            1 => square_data
+                .iter()
+                .position(|square| square.is_some())
+                .expect("Could not find previously found valid move (radioactive particle?)"),
+            _ => read_index(overflow_length)? as usize,
+        };
+
+        square_data[index].ok_or(DecodeErrorKind::EmptyCandidate(index as u8))?
+    };
+
+    let from_piece = position
+        .piece_on(from)
+        .expect("Could not find piece at previously validated square (radioactive particle?)");
+
+    let promotion = if from_piece == Piece::Pawn
+        && position.color_on(from).unwrap().to_their_backrank() == to.get_rank()
+    {
+        if compact {
+            let promotions = get_legal_promotions(&from, &to, position);
+
+            let index = match promotions.len() {
+                0 | 1 => 0,
+                count => read_index(get_code_length(count))?,
+            };
+
+            Some(
+                *promotions
+                    .get(index as usize)
+                    .ok_or(DecodeErrorKind::EmptyCandidate(index))?,
+            )
+        } else {
+            Some(PROMOTION_KEY[read_index(2)? as usize])
+        }
+    } else {
+        None
+    };
+
+    let chess_move = ChessMove::new(from, to, promotion);
+
+    if !position.legal(chess_move) {
+        return Err(DecodeErrorKind::IllegalMove(chess_move));
     }
+
+    Ok(chess_move)
 }
 
 fn safe_get_square(index: u8) -> Square {
@@ -38,57 +1012,543 @@ fn safe_get_square(index: u8) -> Square {
     unsafe { Square::new(index) }
 }
 
-impl Iterator for Reader {
+impl Iterator for Reader<'_> {
     /// Returns the next move processed and the current state of the board after the move has been applied
+    ///
+    /// Unlike `try_next`, anything after the end of the game is ignored.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer is corrupt, use `try_next` to handle this
     fn next(&mut self) -> Option<Self::Item> {
-        let to = safe_get_square(self.data[0] & 0b00111111);
-        let id = self.data[0] >> 6;
-
-        let (square_data, overflow_length) = match id {
-            3 => (Straight::get_square_data(&to, &self.chess), 2),
-            2 => (Diagonal::get_square_data(&to, &self.chess), 2),
-            1 => (Knight::get_square_data(&to, &self.chess), 3),
-            0 => {
-                self.outcome = Some(Outcome::from_id(self.data[0]));
-                return None;
-            }
-            _ => panic!("Unknown filter"),
-        };
+        self.next_move(false)
+            .expect("Could not decode the next move")
+    }
 
-        self.data.remove(0);
+    type Item = (ChessMove, Board);
+}
 
-        let index = if square_data.iter().filter(|square| square.is_some()).count() == 1 {
-            square_data
-                .iter()
-                .position(|square| square.is_some())
-                .expect("Could not find previously found valid move (radioactive particle?)")
-        } else {
-            self.bit_buffer.read(overflow_length) as usize
-        };
+#[cfg(test)]
+mod tests {
+    use chess::{Board, ChessMove, MoveGen, Piece, Square};
+
+    use crate::{
+        header::{
+            Header, ANNOTATIONS_SECTION, CHECKSUM, CUSTOM_START, MAGIC, METADATA, SECTIONS, VERSION,
+        },
+        outcome::{Outcome, Termination},
+        writer::Writer,
+        VARIATION_END, VARIATION_START,
+    };
+
+    use super::{DecodeError, DecodeErrorKind, Node, Reader};
+
+    fn encode(moves: &[(Square, Square)]) -> Vec<u8> {
+        let mut writer = Writer::new();
+        let mut chess = Board::default();
+
+        for (from, to) in moves {
+            let chess_move = ChessMove::new(*from, *to, None);
+
+            writer.add_move(&chess_move, &chess);
+            chess = chess.make_move_new(chess_move);
+        }
 
-        let from = square_data[index].expect("Could not find valid move from overflow index");
+        writer.get_data(Some(Outcome::Draw))
+    }
 
-        let from_piece = self
-            .chess
-            .piece_on(from)
-            .expect("Could not find piece at previously validated square (radioactive particle?)");
+    #[test]
+    fn decodes_valid_data() {
+        let data = encode(&[(Square::E2, Square::E4), (Square::E7, Square::E5)]);
+        let mut reader = Reader::try_new(&data).unwrap();
 
-        let chess_move = ChessMove::new(
-            from,
-            to,
-            if from_piece == Piece::Pawn
-                && self.chess.color_on(from).unwrap().to_their_backrank() == to.get_rank()
-            {
-                Some(PROMOTION_KEY[self.bit_buffer.read(2) as usize])
-            } else {
-                None
-            },
+        assert_eq!(reader.try_iter().filter(Result::is_ok).count(), 2);
+        assert_eq!(reader.try_next(), Ok(None));
+        assert_eq!(
+            reader.get_outcome(),
+            Some((Outcome::Draw, Termination::Unspecified))
         );
+    }
 
-        self.chess.clone().make_move(chess_move, &mut self.chess);
+    #[test]
+    fn decodes_legacy_king_next_to_rook() {
+        // 1.f4 e6 2.Nf3 Bc5 3.e4 Nc6 4.Bc4 Nf6 5.Rg1 a6 6.Rh1 from the 0.3 encoder, where the king
+        // on e1 is still a candidate for h1 while the white kingside right is held
+        let data = [221, 236, 85, 162, 220, 106, 154, 109, 198, 232, 199, 3, 1];
+        let mut reader = Reader::try_new(&data).unwrap();
 
-        Some((chess_move, self.chess))
+        assert_eq!(reader.try_iter().filter(Result::is_ok).count(), 11);
+        assert_eq!(reader.try_next(), Ok(None));
+        assert_eq!(
+            reader.get_position().piece_on(Square::H1),
+            Some(Piece::Rook)
+        );
+        assert_eq!(
+            reader.get_outcome(),
+            Some((Outcome::Draw, Termination::Unspecified))
+        );
     }
 
-    type Item = (ChessMove, Board);
+    #[test]
+    fn owned_reader() {
+        let data = encode(&[
+            (Square::G1, Square::F3),
+            (Square::G8, Square::F6),
+            (Square::B1, Square::C3),
+        ]);
+
+        let mut borrowed = Reader::new(&data);
+        borrowed.next();
+
+        let owned = borrowed.clone().into_owned();
+        drop(data);
+
+        // the owned reader carries on from where the borrowed one was
+        assert_eq!(owned.get_core_offset(), 1);
+        assert_eq!(
+            owned.map(|(chess_move, _)| chess_move).collect::<Vec<_>>(),
+            vec![
+                ChessMove::new(Square::G8, Square::F6, None),
+                ChessMove::new(Square::B1, Square::C3, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_termination() {
+        let mut writer = Writer::new();
+        writer.set_termination(Termination::Agreement);
+
+        let mut reader = Reader::from_vec(writer.get_data(Some(Outcome::Draw)));
+
+        assert_eq!(reader.next(), None);
+        assert_eq!(
+            reader.get_outcome(),
+            Some((Outcome::Draw, Termination::Agreement))
+        );
+    }
+
+    #[test]
+    fn custom_start_position() {
+        let chess: Board = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1".parse().unwrap();
+        let chess_move = ChessMove::new(Square::E2, Square::E4, None);
+
+        let mut writer = Writer::with_start_position(chess);
+        writer.add_move(&chess_move, &chess);
+
+        let mut reader = Reader::from_vec(writer.get_data(None));
+
+        assert_eq!(reader.get_start_position(), &chess);
+        assert_eq!(
+            reader.next(),
+            Some((chess_move, chess.make_move_new(chess_move)))
+        );
+        assert_eq!(reader.next(), None);
+    }
+
+    #[test]
+    fn versioned_header() {
+        let chess: Board = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1".parse().unwrap();
+        let chess_move = ChessMove::new(Square::E2, Square::E4, None);
+
+        let mut writer = Writer::with_start_position(chess);
+        writer.add_move(&chess_move, &chess);
+        writer.set_header(true);
+
+        let mut legacy = writer.clone();
+        writer.set_metadata(b"metadata".to_vec());
+
+        let data = writer.get_data(Some(Outcome::WhiteWon));
+        let mut reader = Reader::new(&data);
+
+        assert!(data.starts_with(&MAGIC));
+        assert_eq!(
+            reader.get_header(),
+            Some(&Header {
+                version: VERSION,
+                flags: CUSTOM_START | METADATA
+            })
+        );
+        assert_eq!(reader.get_metadata(), Some(&b"metadata"[..]));
+        assert_eq!(reader.get_start_position(), &chess);
+        assert_eq!(
+            reader.next().map(|(chess_move, _)| chess_move),
+            Some(chess_move)
+        );
+        assert_eq!(reader.next(), None);
+        assert_eq!(
+            reader.get_outcome(),
+            Some((Outcome::WhiteWon, Termination::Unspecified))
+        );
+
+        legacy.set_header(false);
+
+        let reader = Reader::from_vec(legacy.get_data(None));
+
+        assert_eq!(reader.get_header(), None);
+        assert_eq!(reader.get_start_position(), &chess);
+    }
+
+    #[test]
+    fn checksum() {
+        let mut writer = Writer::new();
+        let mut chess = Board::default();
+
+        for (from, to) in [
+            (Square::D2, Square::D4),
+            (Square::D7, Square::D5),
+            (Square::G1, Square::F3),
+            (Square::G8, Square::F6),
+            (Square::B1, Square::D2),
+        ] {
+            let chess_move = ChessMove::new(from, to, None);
+
+            writer.add_move(&chess_move, &chess);
+            chess = chess.make_move_new(chess_move);
+        }
+
+        writer.set_checksum(true);
+
+        let data = writer.get_data(Some(Outcome::Draw));
+
+        assert!(Reader::new(&data).get_header().unwrap().has_flag(CHECKSUM));
+        assert_eq!(Reader::new(&data).count(), 5);
+
+        for bit in 0..data.len() * 8 {
+            let mut corrupt = data.clone();
+            corrupt[bit / 8] ^= 1 << (bit % 8);
+
+            // clearing the checksum flag leaves the checksum behind as trailing data
+            let decoded = Reader::try_new(&corrupt)
+                .and_then(|mut reader| reader.try_iter().collect::<Result<Vec<_>, _>>());
+
+            assert!(decoded.is_err(), "flipping bit {bit} went unnoticed");
+        }
+
+        assert_eq!(
+            Reader::try_new(&data[..data.len() - 1]).err().unwrap().kind,
+            DecodeErrorKind::ChecksumMismatch
+        );
+    }
+
+    #[test]
+    fn unknown_flag() {
+        let mut data = MAGIC.to_vec();
+        data.extend([VERSION, 0x80, 0x02, Outcome::BlackWon.get_id()]);
+
+        assert_eq!(
+            Reader::try_new(&data).err().unwrap(),
+            DecodeError::new(DecodeErrorKind::UnknownFlags(1 << 8), 0, 6)
+        );
+    }
+
+    #[test]
+    fn annotations() {
+        let e4 = ChessMove::new(Square::E2, Square::E4, None);
+        let e5 = ChessMove::new(Square::E7, Square::E5, None);
+
+        let mut writer = Writer::new();
+        writer.add_comment("Before the game");
+        writer.add_move(&e4, &Board::default());
+        writer.add_nag(1);
+        writer.add_comment("Best by test");
+        writer.add_move(&e5, &Board::default().make_move_new(e4));
+
+        let data = writer.get_data(Some(Outcome::Draw));
+        let mut reader = Reader::new(&data);
+
+        assert_eq!(
+            reader.get_header(),
+            Some(&Header {
+                version: VERSION,
+                flags: SECTIONS
+            })
+        );
+        assert!(reader.get_section(ANNOTATIONS_SECTION).is_some());
+        assert_eq!(
+            reader.get_annotations().unwrap().get(0).unwrap().comments,
+            ["Before the game"]
+        );
+
+        let (chess_move, _, annotation) = reader.try_next_annotated().unwrap().unwrap();
+
+        assert_eq!(chess_move, e4);
+        assert_eq!(annotation.comments, ["Best by test"]);
+        assert_eq!(annotation.nags, [1]);
+
+        let (chess_move, _, annotation) = reader.try_next_annotated().unwrap().unwrap();
+
+        assert_eq!(chess_move, e5);
+        assert!(annotation.is_empty());
+        assert_eq!(reader.try_next_annotated(), Ok(None));
+
+        // moves decode without touching the section, even when it is corrupt
+        let mut corrupt = data.clone();
+        corrupt[10] = 0x7F;
+
+        assert_eq!(Reader::new(&corrupt).count(), 2);
+        assert_eq!(
+            Reader::new(&corrupt).try_next_annotated().unwrap_err().kind,
+            DecodeErrorKind::InvalidHeader
+        );
+
+        // sections with unknown ids are skipped
+        let mut unknown = MAGIC.to_vec();
+        unknown.extend([VERSION, SECTIONS as u8, 1, 200, 2, 0xAB, 0xCD]);
+        unknown.push(Outcome::Draw.get_id());
+
+        let mut reader = Reader::new(&unknown);
+
+        assert_eq!(reader.get_section(200), Some(&[0xAB, 0xCD][..]));
+        assert_eq!(reader.get_annotations(), Ok(Default::default()));
+        assert_eq!(reader.try_next_annotated(), Ok(None));
+        assert_eq!(
+            reader.get_outcome(),
+            Some((Outcome::Draw, Termination::Unspecified))
+        );
+    }
+
+    #[test]
+    fn variations() {
+        let e4 = ChessMove::new(Square::E2, Square::E4, None);
+        let d4 = ChessMove::new(Square::D2, Square::D4, None);
+        let d5 = ChessMove::new(Square::D7, Square::D5, None);
+        let nf6 = ChessMove::new(Square::G8, Square::F6, None);
+        let e5 = ChessMove::new(Square::E7, Square::E5, None);
+
+        let start = Board::default();
+        let after_d4 = start.make_move_new(d4);
+        let after_e4 = start.make_move_new(e4);
+
+        // 1. e4 (1. d4 d5 (1... Nf6)) 1... e5
+        let mut writer = Writer::new();
+        writer.add_move(&e4, &start);
+        writer.start_variation();
+        writer.add_move(&d4, &start);
+        writer.add_move(&d5, &after_d4);
+        writer.start_variation();
+        writer.add_move(&nf6, &after_d4);
+        writer.end_variation();
+        writer.end_variation();
+        writer.add_move(&e5, &after_e4);
+
+        let data = writer.get_data(None);
+        let mut reader = Reader::new(&data);
+        let mut nodes = Vec::new();
+
+        while let Some(node) = reader.try_next_node().unwrap() {
+            nodes.push((node, reader.get_depth()));
+        }
+
+        assert_eq!(
+            nodes,
+            [
+                (Node::Move(e4, after_e4), 0),
+                (Node::StartVariation, 1),
+                (Node::Move(d4, after_d4), 1),
+                (Node::Move(d5, after_d4.make_move_new(d5)), 1),
+                (Node::StartVariation, 2),
+                (Node::Move(nf6, after_d4.make_move_new(nf6)), 2),
+                (Node::EndVariation, 1),
+                (Node::EndVariation, 0),
+                (Node::Move(e5, after_e4.make_move_new(e5)), 0),
+            ]
+        );
+        assert_eq!(reader.get_ply(), 2);
+
+        assert_eq!(
+            Reader::new(&data)
+                .map(|(chess_move, _)| chess_move)
+                .collect::<Vec<_>>(),
+            [e4, e5]
+        );
+
+        // a variation with no move to replace, one that ends without starting and one left open
+        let e4_byte = data[0];
+
+        for (data, ply, offset) in [
+            (vec![VARIATION_START, 0], 0, 0),
+            (vec![e4_byte, VARIATION_END, 0], 1, 1),
+            (vec![e4_byte, VARIATION_START, 0], 1, 2),
+        ] {
+            assert_eq!(
+                Reader::new(&data).try_iter().find_map(Result::err),
+                Some(DecodeError {
+                    kind: DecodeErrorKind::InvalidVariation,
+                    ply,
+                    offset
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn seek() {
+        let mut writer = Writer::new();
+        let mut chess = Board::default();
+        let mut positions = vec![chess];
+
+        writer.set_checkpoint_interval(3);
+
+        for ply in 0..10 {
+            let chess_move = MoveGen::new_legal(&chess).nth(ply * 7 % 20).unwrap();
+
+            writer.add_move(&chess_move, &chess);
+
+            // a variation that replaces the fourth move
+            if ply == 3 {
+                writer.start_variation();
+                writer.add_move(&MoveGen::new_legal(&chess).next().unwrap(), &chess);
+                writer.end_variation();
+            }
+
+            chess = chess.make_move_new(chess_move);
+            positions.push(chess);
+        }
+
+        let data = writer.get_data(None);
+        let mut reader = Reader::new(&data);
+
+        assert_eq!(reader.get_checkpoint_interval(), Some(3));
+
+        for ply in (0..=10).rev().chain([4, 7, 1, 10]) {
+            assert_eq!(reader.seek(ply), Ok(()));
+            assert_eq!(reader.get_position(), &positions[ply], "{ply}");
+        }
+
+        reader.seek(4).unwrap();
+
+        assert_eq!(reader.try_next_node().unwrap(), Some(Node::StartVariation));
+        assert_eq!(
+            reader.seek(11).unwrap_err().kind,
+            DecodeErrorKind::PlyOutOfRange(11)
+        );
+
+        // the moves before the checkpoint are not decoded, so breaking the first one only matters
+        // when seeking before the first checkpoint
+        let mut corrupt = data.clone();
+        let first = Reader::new(&data).get_core_offset();
+        corrupt[first] = 0b01000000 | Square::E5.to_int();
+
+        let mut reader = Reader::new(&corrupt);
+
+        assert_eq!(reader.seek(9), Ok(()));
+        assert_eq!(reader.get_position(), &positions[9]);
+        assert_eq!(
+            reader.seek(2).unwrap_err().kind,
+            DecodeErrorKind::NoCandidates(Square::E5)
+        );
+    }
+
+    #[test]
+    fn default_start_position_has_no_header() {
+        assert_eq!(
+            Writer::with_start_position(Board::default()).get_data(Some(Outcome::Draw)),
+            encode(&[])
+        );
+    }
+
+    #[test]
+    fn empty_data() {
+        assert_eq!(
+            Reader::try_new(&[]).err(),
+            Some(DecodeError {
+                kind: DecodeErrorKind::TruncatedCore,
+                ply: 0,
+                offset: 0
+            })
+        );
+    }
+
+    #[test]
+    fn missing_terminator() {
+        let mut data = encode(&[(Square::E2, Square::E4)]);
+        data.pop();
+
+        assert_eq!(
+            Reader::try_new(&data).err().map(|error| error.kind),
+            Some(DecodeErrorKind::MissingTerminator)
+        );
+
+        // a lenient reader only fails once it reaches the end
+        let mut reader = Reader::new(&data);
+
+        assert!(reader.try_next().unwrap().is_some());
+        assert_eq!(
+            reader.try_next().unwrap_err().kind,
+            DecodeErrorKind::MissingTerminator
+        );
+    }
+
+    #[test]
+    fn overflow_exhausted() {
+        // both knights can reach d2 after 1. d4 d5 so the second knight move needs overflow bits
+        let data = encode(&[
+            (Square::D2, Square::D4),
+            (Square::D7, Square::D5),
+            (Square::G1, Square::F3),
+            (Square::G8, Square::F6),
+            (Square::B1, Square::D2),
+        ]);
+        let mut reader = Reader::try_new(&data[..data.len() - 1]).unwrap();
+
+        assert_eq!(
+            reader.try_iter().find_map(Result::err),
+            Some(DecodeError {
+                kind: DecodeErrorKind::OverflowExhausted,
+                ply: 4,
+                offset: 6
+            })
+        );
+    }
+
+    #[test]
+    fn no_candidates() {
+        let mut data = encode(&[(Square::E2, Square::E4)]);
+        data[0] = (data[0] & 0b11000000) | Square::E5.to_int();
+
+        assert_eq!(
+            Reader::try_new(&data).unwrap().try_next(),
+            Err(DecodeError {
+                kind: DecodeErrorKind::NoCandidates(Square::E5),
+                ply: 0,
+                offset: 0
+            })
+        );
+    }
+
+    #[test]
+    fn trailing_data() {
+        let mut data = encode(&[(Square::E2, Square::E4)]);
+        data.push(0xFF);
+
+        let mut reader = Reader::try_new(&data).unwrap();
+
+        assert!(reader.try_next().unwrap().is_some());
+        assert_eq!(
+            reader.try_next().unwrap_err().kind,
+            DecodeErrorKind::TrailingData
+        );
+        assert_eq!(reader.try_next(), Ok(None));
+
+        // which the iterator ignores like older versions did
+        let mut reader = Reader::new(&data);
+
+        assert_eq!(reader.by_ref().count(), 1);
+        assert_eq!(
+            reader.get_outcome(),
+            Some((Outcome::Draw, Termination::Unspecified))
+        );
+    }
+
+    #[test]
+    fn invalid_terminator() {
+        let mut data = encode(&[]);
+        data[0] = 0b00101011;
+
+        assert_eq!(
+            Reader::try_new(&data).unwrap().try_next().unwrap_err().kind,
+            DecodeErrorKind::InvalidTerminator(0b00101011)
+        );
+    }
 }
@@ -0,0 +1,331 @@
+//! Serde support, enabled with the `serde` feature
+//!
+//! `Outcome` and `Termination` serialize as their PGN tokens. Encoded games are plain byte vectors,
+//! so they are serialized through one of the modules here with `#[serde(with = "...")]`:
+//!
+//! * `game` - the ASCN bytes in binary formats such as MessagePack and base64 in human-readable
+//!   formats such as JSON
+//! * `uci` - the start position, the moves in UCI notation and the result. This is readable
+//!   without this crate, but deserializing it encodes the game again without a header, so features
+//!   such as checksums or metadata are not kept.
+//!
+//! Both check that the game can be read when deserializing it.
+
+use std::fmt;
+
+use ::serde::{
+    de::{self, SeqAccess, Unexpected, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::{
+    outcome::{Outcome, Termination},
+    text::{decode, encode, BASE64},
+};
+
+impl Serialize for Outcome {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Outcome {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for Termination {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Termination {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Serializes an encoded game as bytes, or as base64 in human-readable formats
+pub mod game {
+    use super::*;
+
+    use crate::reader::Reader;
+
+    struct GameVisitor;
+
+    impl<'de> Visitor<'de> for GameVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "an ASCN game as bytes or base64")
+        }
+
+        fn visit_str<E: de::Error>(self, str: &str) -> Result<Vec<u8>, E> {
+            decode(str, BASE64).map_err(|error| E::custom(format!("{error} in {str:?}")))
+        }
+
+        fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Vec<u8>, E> {
+            Ok(bytes.to_vec())
+        }
+
+        fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>) -> Result<Vec<u8>, E> {
+            Ok(bytes)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+
+            while let Some(byte) = seq.next_element()? {
+                bytes.push(byte);
+            }
+
+            Ok(bytes)
+        }
+    }
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&encode(data, BASE64, true))
+        } else {
+            serializer.serialize_bytes(data)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let data = if deserializer.is_human_readable() {
+            deserializer.deserialize_str(GameVisitor)?
+        } else {
+            deserializer.deserialize_byte_buf(GameVisitor)?
+        };
+
+        Reader::try_new(&data).map_err(de::Error::custom)?;
+
+        Ok(data)
+    }
+}
+
+/// Serializes an encoded game as its start position, the moves of its main line in UCI notation
+/// and its result
+///
+/// Chess960 castling is written as the king moving onto its own rook, as in `UCI_Chess960`, and
+/// its castling rights as Shredder-FEN.
+pub mod uci {
+    use chess::{Board, ChessMove};
+
+    use super::*;
+
+    use crate::{chess960::get_start_fen, pgn::parse_fen, reader::Reader, writer::Writer};
+
+    #[derive(Serialize, Deserialize)]
+    struct UciGame {
+        /// The start position, left out for the default one
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fen: Option<String>,
+        moves: Vec<String>,
+        result: Outcome,
+        #[serde(default, skip_serializing_if = "is_unspecified")]
+        termination: Termination,
+    }
+
+    fn is_unspecified(termination: &Termination) -> bool {
+        *termination == Termination::Unspecified
+    }
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        use ::serde::ser::Error;
+
+        let mut reader = Reader::try_new(data).map_err(S::Error::custom)?;
+        let start_position = *reader.get_start_position();
+        let mut moves = Vec::new();
+
+        while let Some((chess_move, _)) = reader.try_next().map_err(S::Error::custom)? {
+            moves.push(chess_move.to_string());
+        }
+
+        let (result, termination) = reader.get_outcome().unwrap_or_default();
+
+        let fen = match reader.get_chess960_index().and_then(get_start_fen) {
+            Some(fen) => Some(fen),
+            None => Some(start_position)
+                .filter(|position| *position != Board::default())
+                .map(|position| position.to_string()),
+        };
+
+        UciGame {
+            fen,
+            moves,
+            result,
+            termination,
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        use de::Error;
+
+        let game = UciGame::deserialize(deserializer)?;
+
+        let (mut position, mut castling) = match game.fen {
+            Some(fen) => parse_fen(&fen)
+                .map(|(position, castling, _)| (position, castling))
+                .ok_or_else(|| D::Error::invalid_value(Unexpected::Str(&fen), &"a FEN"))?,
+            None => Default::default(),
+        };
+
+        let mut writer = Writer::with_castling(position, castling);
+
+        for chess_move in game.moves {
+            let parsed: ChessMove = chess_move.parse().map_err(|_| {
+                D::Error::invalid_value(Unexpected::Str(&chess_move), &"a UCI move")
+            })?;
+
+            writer
+                .try_add_move(&parsed, &position)
+                .map_err(D::Error::custom)?;
+
+            position = castling.make_move(&position, parsed);
+        }
+
+        writer.set_termination(game.termination);
+
+        Ok(writer.get_data(Some(game.result)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::serde::{Deserialize, Serialize};
+
+    use crate::{
+        outcome::{Outcome, Termination},
+        pgn::read_game,
+        writer::Writer,
+    };
+
+    use crate::text::{encode, BASE64};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Stored {
+        #[serde(with = "super::game")]
+        game: Vec<u8>,
+        outcome: Outcome,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Readable {
+        #[serde(with = "super::uci")]
+        game: Vec<u8>,
+    }
+
+    #[test]
+    fn outcome_tokens() {
+        assert_eq!(
+            serde_json::to_string(&Outcome::Draw).unwrap(),
+            "\"1/2-1/2\""
+        );
+        assert_eq!(
+            serde_json::from_str::<Outcome>("\"0-1\"").unwrap(),
+            Outcome::BlackWon
+        );
+        assert!(serde_json::from_str::<Outcome>("\"2-0\"").is_err());
+        assert_eq!(
+            serde_json::from_str::<Termination>(
+                &serde_json::to_string(&Termination::Checkmate).unwrap()
+            )
+            .unwrap(),
+            Termination::Checkmate
+        );
+    }
+
+    #[test]
+    fn game_formats() {
+        let game = read_game("1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 1/2-1/2")
+            .unwrap()
+            .unwrap();
+        let stored = Stored {
+            game: game.data.clone(),
+            outcome: game.outcome,
+        };
+
+        let json = serde_json::to_string(&stored).unwrap();
+
+        assert_eq!(
+            json,
+            format!(
+                "{{\"game\":\"{}\",\"outcome\":\"1/2-1/2\"}}",
+                encode(&game.data, BASE64, true)
+            )
+        );
+        assert_eq!(serde_json::from_str::<Stored>(&json).unwrap(), stored);
+
+        // the game is stored as raw bytes with a single byte of length in front
+        let msgpack = rmp_serde::to_vec(&stored).unwrap();
+
+        assert!(msgpack
+            .windows(game.data.len())
+            .any(|bytes| bytes == game.data));
+        assert!(msgpack.len() < json.len());
+        assert_eq!(rmp_serde::from_slice::<Stored>(&msgpack).unwrap(), stored);
+
+        assert!(serde_json::from_str::<Stored>("{\"game\":\"y*==\",\"outcome\":\"*\"}").is_err());
+
+        // no terminator
+        assert!(serde_json::from_str::<Stored>("{\"game\":\"yA==\",\"outcome\":\"*\"}").is_err());
+    }
+
+    #[test]
+    fn uci() {
+        let game = read_game("[FEN \"4k3/4p3/8/8/8/8/8/4K3 b - - 0 1\"]\n\n1... e5 2. Kd2 Kd7 *")
+            .unwrap()
+            .unwrap();
+        let readable = Readable { game: game.data };
+
+        let json = serde_json::to_string(&readable).unwrap();
+
+        assert_eq!(
+            json,
+            "{\"game\":{\"fen\":\"4k3/4p3/8/8/8/8/8/4K3 b - - 0 1\",\
+                \"moves\":[\"e7e5\",\"e1d2\",\"e8d7\"],\"result\":\"*\"}}"
+        );
+        assert_eq!(serde_json::from_str::<Readable>(&json).unwrap(), readable);
+
+        let mut writer = Writer::new();
+        writer.set_termination(Termination::Resignation);
+
+        let resigned = Readable {
+            game: writer.get_data(Some(Outcome::WhiteWon)),
+        };
+        let json = serde_json::to_string(&resigned).unwrap();
+
+        assert!(json.contains("\"termination\""), "{json}");
+        assert_eq!(serde_json::from_str::<Readable>(&json).unwrap(), resigned);
+
+        assert!(serde_json::from_str::<Readable>(
+            "{\"game\":{\"moves\":[\"e2e5\"],\"result\":\"*\"}}"
+        )
+        .is_err());
+
+        // the castling rights of a Chess960 start position are written as Shredder-FEN
+        let chess960 = read_game(
+            "[FEN \"bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w HFhf - 0 1\"]\n\n\
+            1. Nd3 Nd6 2. Ne3 Ne6 3. c3 c6 4. Qc2 Qc7 5. O-O-O *",
+        )
+        .unwrap()
+        .unwrap();
+        let readable = Readable {
+            game: chess960.data,
+        };
+
+        let json = serde_json::to_string(&readable).unwrap();
+
+        assert!(json.contains(" w HFhf - 0 1"), "{json}");
+        assert!(json.contains("\"g1f1\""), "{json}");
+        assert_eq!(serde_json::from_str::<Readable>(&json).unwrap(), readable);
+    }
+}
@@ -0,0 +1,326 @@
+//! Statistics about where the bytes of encoded games go
+//!
+//! `Writer::get_stats` reports them for a game while it is being written and `read_stats` for a game
+//! that has already been encoded. Both agree for the same game, and stats of many games can be
+//! summed to compare format changes or to find games that encode unusually badly.
+
+use std::{
+    fmt,
+    iter::Sum,
+    ops::{Add, AddAssign},
+};
+
+use chess::{Board, ChessMove};
+
+use crate::{
+    filters::{get_code_length, get_legal_promotions},
+    header::{COMPACT_OVERFLOW, ENTROPY},
+    reader::{DecodeError, Node, Reader},
+};
+
+/// Counts of how the moves of one or more games were encoded and how many bytes each part took
+///
+/// Entropy coded games have no filters or overflow bits, so only their plies and bytes are counted.
+/// Their ply count is part of `core_bytes` and the coded moves are `overflow_bytes`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct EncodingStats {
+    pub games: usize,
+    pub plies: usize,
+    /// Moves along a rank or file
+    pub straight: usize,
+    /// Moves along a diagonal
+    pub diagonal: usize,
+    /// Knight moves
+    pub knight: usize,
+    /// Moves that needed overflow bits to pick the piece that moved
+    pub overflow_plies: usize,
+    /// The overflow bits spent picking pieces, not counting promotions
+    pub overflow_bits: usize,
+    pub promotions: usize,
+    /// The overflow bits spent on promotion pieces
+    pub promotion_bits: usize,
+    /// The header or legacy start position before the core stream
+    pub header_bytes: usize,
+    /// The core stream including the terminator
+    pub core_bytes: usize,
+    pub overflow_bytes: usize,
+    pub checksum_bytes: usize,
+}
+
+impl EncodingStats {
+    /// The size of the encoded games in bytes
+    pub fn get_total_bytes(&self) -> usize {
+        self.header_bytes + self.core_bytes + self.overflow_bytes + self.checksum_bytes
+    }
+
+    /// The average number of bits each move took, counting everything but the header and the
+    /// checksum, or `None` if there are no moves
+    pub fn get_bits_per_ply(&self) -> Option<f64> {
+        if self.plies == 0 {
+            return None;
+        }
+
+        Some((self.core_bytes + self.overflow_bytes) as f64 * 8.0 / self.plies as f64)
+    }
+
+    /// Counts a move from its core byte and the overflow bits it needed
+    pub(crate) fn add_move(
+        &mut self,
+        byte: u8,
+        overflow_bits: usize,
+        promotion_bits: Option<usize>,
+    ) {
+        self.plies += 1;
+
+        match byte >> 6 {
+            3 => self.straight += 1,
+            2 => self.diagonal += 1,
+            _ => self.knight += 1,
+        }
+
+        let promotion_bits = promotion_bits.inspect(|_| self.promotions += 1);
+        let overflow_bits = overflow_bits - promotion_bits.unwrap_or(0);
+
+        if overflow_bits > 0 {
+            self.overflow_plies += 1;
+            self.overflow_bits += overflow_bits;
+        }
+
+        self.promotion_bits += promotion_bits.unwrap_or(0);
+    }
+}
+
+/// The number of overflow bits the promotion piece of a move takes, if it is a promotion
+pub(crate) fn get_promotion_bits(
+    chess_move: &ChessMove,
+    position: &Board,
+    compact: bool,
+) -> Option<usize> {
+    chess_move.get_promotion()?;
+
+    if compact {
+        let promotions =
+            get_legal_promotions(&chess_move.get_source(), &chess_move.get_dest(), position);
+
+        Some(get_code_length(promotions.len()) as usize)
+    } else {
+        Some(2)
+    }
+}
+
+impl AddAssign<&EncodingStats> for EncodingStats {
+    fn add_assign(&mut self, other: &EncodingStats) {
+        self.games += other.games;
+        self.plies += other.plies;
+        self.straight += other.straight;
+        self.diagonal += other.diagonal;
+        self.knight += other.knight;
+        self.overflow_plies += other.overflow_plies;
+        self.overflow_bits += other.overflow_bits;
+        self.promotions += other.promotions;
+        self.promotion_bits += other.promotion_bits;
+        self.header_bytes += other.header_bytes;
+        self.core_bytes += other.core_bytes;
+        self.overflow_bytes += other.overflow_bytes;
+        self.checksum_bytes += other.checksum_bytes;
+    }
+}
+
+impl AddAssign for EncodingStats {
+    fn add_assign(&mut self, other: EncodingStats) {
+        *self += &other;
+    }
+}
+
+impl Add for EncodingStats {
+    type Output = EncodingStats;
+
+    fn add(mut self, other: EncodingStats) -> EncodingStats {
+        self += &other;
+        self
+    }
+}
+
+impl Sum for EncodingStats {
+    fn sum<I: Iterator<Item = EncodingStats>>(iter: I) -> Self {
+        iter.fold(Self::default(), Add::add)
+    }
+}
+
+impl<'a> Sum<&'a EncodingStats> for EncodingStats {
+    fn sum<I: Iterator<Item = &'a EncodingStats>>(iter: I) -> Self {
+        iter.fold(Self::default(), |mut sum, stats| {
+            sum += stats;
+            sum
+        })
+    }
+}
+
+impl fmt::Display for EncodingStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "games           {}", self.games)?;
+        writeln!(f, "plies           {}", self.plies)?;
+        writeln!(f, "straight        {}", self.straight)?;
+        writeln!(f, "diagonal        {}", self.diagonal)?;
+        writeln!(f, "knight          {}", self.knight)?;
+        writeln!(
+            f,
+            "overflow        {} plies, {} bits",
+            self.overflow_plies, self.overflow_bits
+        )?;
+        writeln!(
+            f,
+            "promotions      {}, {} bits",
+            self.promotions, self.promotion_bits
+        )?;
+        writeln!(f, "header bytes    {}", self.header_bytes)?;
+        writeln!(f, "core bytes      {}", self.core_bytes)?;
+        writeln!(f, "overflow bytes  {}", self.overflow_bytes)?;
+        writeln!(f, "checksum bytes  {}", self.checksum_bytes)?;
+        write!(f, "total bytes     {}", self.get_total_bytes())?;
+
+        if let Some(bits) = self.get_bits_per_ply() {
+            write!(f, "\nbits per ply    {bits:.2}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Decodes a game only to count how it was encoded
+pub fn read_stats(data: &[u8]) -> Result<EncodingStats, DecodeError> {
+    let mut reader = Reader::try_new(data)?;
+
+    let has_flag = |flag| {
+        reader
+            .get_header()
+            .is_some_and(|header| header.has_flag(flag))
+    };
+
+    let compact = has_flag(COMPACT_OVERFLOW);
+    let entropy = has_flag(ENTROPY);
+
+    let mut stats = EncodingStats {
+        games: 1,
+        ..Default::default()
+    };
+
+    // variation markers are counted as core bytes but not as moves
+    loop {
+        let byte = data.get(reader.get_core_offset()).copied();
+        let overflow_left = reader.get_overflow_left();
+        let position = *reader.get_position();
+
+        let Some(node) = reader.try_next_node()? else {
+            break;
+        };
+
+        let Node::Move(chess_move, _) = node else {
+            continue;
+        };
+
+        match byte {
+            Some(byte) if !entropy => stats.add_move(
+                byte,
+                overflow_left - reader.get_overflow_left(),
+                get_promotion_bits(&chess_move, &position, compact),
+            ),
+            _ => stats.plies += 1,
+        }
+    }
+
+    let (core_start, overflow_start, overflow_end) = reader.get_sections();
+
+    stats.header_bytes = core_start;
+    stats.core_bytes = overflow_start - core_start;
+    stats.overflow_bytes = overflow_end - overflow_start;
+    stats.checksum_bytes = data.len() - overflow_end;
+
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use chess::{Board, ChessMove, Piece, Square};
+
+    use crate::{outcome::Outcome, pgn::read_game, writer::Writer};
+
+    use super::{read_stats, EncodingStats};
+
+    #[test]
+    fn writer_and_reader_agree() {
+        // two knights reach e4, then the king and the promoting pawn are the only pieces that can move
+        let chess: Board = "7k/1P6/8/K7/8/2N3N1/8/8 w - - 0 1".parse().unwrap();
+        let moves = [
+            ChessMove::new(Square::G3, Square::E4, None),
+            ChessMove::new(Square::H8, Square::G8, None),
+            ChessMove::new(Square::B7, Square::B8, Some(Piece::Knight)),
+        ];
+
+        for (compact, checksum) in [(false, false), (true, true)] {
+            let mut writer = Writer::with_start_position(chess);
+            let mut board = chess;
+
+            writer.set_compact_overflow(compact);
+            writer.set_checksum(checksum);
+
+            for chess_move in moves {
+                writer.add_move(&chess_move, &board);
+                board = board.make_move_new(chess_move);
+            }
+
+            let stats = writer.get_stats();
+            let data = writer.get_data(Some(Outcome::WhiteWon));
+
+            assert_eq!(read_stats(&data), Ok(stats));
+            assert_eq!(stats.get_total_bytes(), data.len());
+            assert_eq!(stats.plies, 3);
+            assert_eq!((stats.straight, stats.diagonal, stats.knight), (2, 0, 1));
+            assert_eq!(stats.overflow_plies, 1);
+            assert_eq!(stats.overflow_bits, if compact { 1 } else { 3 });
+            assert_eq!((stats.promotions, stats.promotion_bits), (1, 2));
+            assert_eq!(stats.checksum_bytes, if checksum { 4 } else { 0 });
+        }
+    }
+
+    #[test]
+    fn variations() {
+        let mut writer = Writer::new();
+        let e4 = ChessMove::new(Square::E2, Square::E4, None);
+        let d4 = ChessMove::new(Square::D2, Square::D4, None);
+        let e5 = ChessMove::new(Square::E7, Square::E5, None);
+
+        writer.add_move(&e4, &Board::default());
+        writer.start_variation();
+        writer.add_move(&d4, &Board::default());
+        writer.end_variation();
+        writer.add_move(&e5, &Board::default().make_move_new(e4));
+
+        let stats = writer.get_stats();
+        let data = writer.get_data(None);
+
+        // the markers take a core byte each
+        assert_eq!(read_stats(&data), Ok(stats));
+        assert_eq!((stats.plies, stats.core_bytes), (3, 6));
+    }
+
+    #[test]
+    fn aggregate() {
+        let game = read_game("1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 1/2-1/2")
+            .unwrap()
+            .unwrap();
+        let stats = read_stats(&game.data).unwrap();
+
+        assert_eq!(stats.header_bytes, 0);
+        assert_eq!(stats.core_bytes, 7);
+        assert_eq!(stats.get_bits_per_ply(), Some(7.0 * 8.0 / 6.0));
+
+        let total: EncodingStats = [stats, stats, stats].iter().sum();
+
+        assert_eq!(total.games, 3);
+        assert_eq!(total.plies, 18);
+        assert_eq!(total.get_total_bytes(), 3 * game.data.len());
+        assert_eq!(total, stats + stats + stats);
+        assert_eq!(EncodingStats::default().get_bits_per_ply(), None);
+    }
+}
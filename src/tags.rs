@@ -0,0 +1,402 @@
+//! The tag pairs of a game, such as `Event`, `White` or `Date`, so that an encoded game can stand
+//! on its own as a game record
+//!
+//! Tags are stored in the `TAGS_SECTION` of the header, which readers that only want the moves skip
+//! like any other section. The section is a varint count of tags followed by each tag in the order
+//! they were given. A tag starts with a byte holding the index of its name in `KNOWN_TAGS` plus one,
+//! or 0 followed by the name as a string, with the high bit set when the value is stored in the
+//! typed form of the tag instead of as a string. Strings are a varint length and their UTF-8 bytes.
+//! The typed forms are:
+//!
+//! * dates: the number of days since 0000.01.01 as a varint
+//! * Elo ratings: a little endian `u16`
+//! * ECO codes: a little endian `u16` holding the letter, counted from `A`, times 100 plus the number
+//! * time controls: the base as a varint followed by the increment plus one, or 0 if there is none,
+//!   as a varint
+//! * rounds, ply counts and FIDE ids: a varint
+//!
+//! A value is only stored in its typed form if it would be written back as the same text, so values
+//! such as `2023.??.??` or `-` are stored as strings.
+
+use crate::varint::{read_varint, write_varint};
+
+/// Marks a tag whose value is stored in its typed form
+const TYPED: u8 = 0x80;
+
+/// How the value of a known tag can be stored besides as a string
+#[derive(Clone, Copy)]
+enum Kind {
+    Text,
+    Date,
+    Elo,
+    Eco,
+    TimeControl,
+    Number,
+}
+
+/// The tags that are stored by their index, which is part of the format, so new tags may only be
+/// added at the end
+const KNOWN_TAGS: [(&str, Kind); 26] = [
+    ("Event", Kind::Text),
+    ("Site", Kind::Text),
+    ("Date", Kind::Date),
+    ("Round", Kind::Number),
+    ("White", Kind::Text),
+    ("Black", Kind::Text),
+    ("Result", Kind::Text),
+    ("WhiteElo", Kind::Elo),
+    ("BlackElo", Kind::Elo),
+    ("ECO", Kind::Eco),
+    ("TimeControl", Kind::TimeControl),
+    ("Termination", Kind::Text),
+    ("Opening", Kind::Text),
+    ("Variation", Kind::Text),
+    ("Annotator", Kind::Text),
+    ("PlyCount", Kind::Number),
+    ("EventDate", Kind::Date),
+    ("UTCDate", Kind::Date),
+    ("UTCTime", Kind::Text),
+    ("WhiteTitle", Kind::Text),
+    ("BlackTitle", Kind::Text),
+    ("WhiteFideId", Kind::Number),
+    ("BlackFideId", Kind::Number),
+    ("Variant", Kind::Text),
+    ("SetUp", Kind::Text),
+    ("FEN", Kind::Text),
+];
+
+/// The number of days in each month of a year that is not a leap year
+const MONTH_LENGTHS: [u32; 12] = [31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+
+fn is_leap_year(year: u32) -> bool {
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
+}
+
+fn get_month_length(year: u32, month: u32) -> u32 {
+    MONTH_LENGTHS[month as usize - 1] + (month == 2 && is_leap_year(year)) as u32
+}
+
+/// The number of days from 0000.01.01 to the start of `year`
+fn get_days_before_year(year: u32) -> u32 {
+    365 * year + year.div_ceil(4) - year.div_ceil(100) + year.div_ceil(400)
+}
+
+/// Parses a complete `YYYY.MM.DD` date into a day number
+fn parse_date(value: &str) -> Option<u32> {
+    let mut fields = value.split('.');
+    let mut next = |length| {
+        fields
+            .next()
+            .filter(|field: &&str| {
+                field.len() == length && field.bytes().all(|byte| byte.is_ascii_digit())
+            })
+            .and_then(|field| field.parse::<u32>().ok())
+    };
+
+    let (year, month, day) = (next(4)?, next(2)?, next(2)?);
+
+    if fields.next().is_some()
+        || !(1..=12).contains(&month)
+        || !(1..=get_month_length(year, month)).contains(&day)
+    {
+        return None;
+    }
+
+    let days_before_month: u32 = (1..month).map(|month| get_month_length(year, month)).sum();
+
+    Some(get_days_before_year(year) + days_before_month + day - 1)
+}
+
+/// Writes a day number as a `YYYY.MM.DD` date, returning `None` past the year 9999
+fn write_date(mut days: u32) -> Option<String> {
+    if days >= get_days_before_year(10000) {
+        return None;
+    }
+
+    // every year has at least 365 days, so this is never past the actual year
+    let mut year = days / 366;
+
+    while get_days_before_year(year + 1) <= days {
+        year += 1;
+    }
+
+    days -= get_days_before_year(year);
+
+    let mut month = 1;
+
+    while days >= get_month_length(year, month) {
+        days -= get_month_length(year, month);
+        month += 1;
+    }
+
+    Some(format!("{year:04}.{month:02}.{:02}", days + 1))
+}
+
+/// Parses a number that is written back as the same text
+fn parse_number<T: std::str::FromStr + ToString>(value: &str) -> Option<T> {
+    value
+        .parse::<T>()
+        .ok()
+        .filter(|number| number.to_string() == value)
+}
+
+/// Returns the typed form of a value, or `None` if it would not be written back as the same text
+fn encode_value(kind: Kind, value: &str) -> Option<Vec<u8>> {
+    let mut result = Vec::new();
+
+    match kind {
+        Kind::Text => return None,
+        Kind::Date => write_varint(&mut result, parse_date(value)? as u64),
+        Kind::Elo => result.extend(parse_number::<u16>(value)?.to_le_bytes()),
+        Kind::Eco => {
+            let (letter, number) = value.split_at_checked(1)?;
+            let letter = b"ABCDE"
+                .iter()
+                .position(|byte| letter.as_bytes() == [*byte])?;
+
+            if number.len() != 2 || !number.bytes().all(|byte| byte.is_ascii_digit()) {
+                return None;
+            }
+
+            let code = letter as u16 * 100 + number.parse::<u16>().ok()?;
+
+            result.extend(code.to_le_bytes());
+        }
+        Kind::TimeControl => {
+            let (base, increment) = match value.split_once('+') {
+                Some((base, increment)) => (base, parse_number::<u64>(increment)?.checked_add(1)?),
+                None => (value, 0),
+            };
+
+            write_varint(&mut result, parse_number(base)?);
+            write_varint(&mut result, increment);
+        }
+        Kind::Number => write_varint(&mut result, parse_number(value)?),
+    }
+
+    Some(result)
+}
+
+fn write_string(output: &mut Vec<u8>, string: &str) {
+    write_varint(output, string.len() as u64);
+    output.extend(string.as_bytes());
+}
+
+fn read_number(data: &mut &[u8]) -> Option<u64> {
+    let (value, length) = read_varint(data)?;
+    *data = &data[length..];
+    Some(value)
+}
+
+fn read_u16(data: &mut &[u8]) -> Option<u16> {
+    let (bytes, rest) = data.split_first_chunk()?;
+    *data = rest;
+    Some(u16::from_le_bytes(*bytes))
+}
+
+fn read_string(data: &mut &[u8]) -> Option<String> {
+    let length = usize::try_from(read_number(data)?).ok()?;
+    let (bytes, rest) = data.split_at_checked(length)?;
+    *data = rest;
+    String::from_utf8(bytes.to_vec()).ok()
+}
+
+/// Reads the typed form of a value written by `encode_value`
+fn decode_value(kind: Kind, data: &mut &[u8]) -> Option<String> {
+    match kind {
+        Kind::Text => None,
+        Kind::Date => write_date(u32::try_from(read_number(data)?).ok()?),
+        Kind::Elo => Some(read_u16(data)?.to_string()),
+        Kind::Eco => {
+            let code = read_u16(data)?;
+
+            (code < 500)
+                .then(|| format!("{}{:02}", (b'A' + (code / 100) as u8) as char, code % 100))
+        }
+        Kind::TimeControl => {
+            let base = read_number(data)?;
+
+            match read_number(data)? {
+                0 => Some(base.to_string()),
+                increment => Some(format!("{base}+{}", increment - 1)),
+            }
+        }
+        Kind::Number => Some(read_number(data)?.to_string()),
+    }
+}
+
+/// Creates the contents of the tag section from the tags in order
+pub(crate) fn encode(tags: &[(String, String)]) -> Vec<u8> {
+    let mut result = Vec::new();
+
+    write_varint(&mut result, tags.len() as u64);
+
+    for (name, value) in tags {
+        let known = KNOWN_TAGS
+            .iter()
+            .position(|(known, _)| known == name)
+            .map(|index| (index as u8 + 1, KNOWN_TAGS[index].1));
+
+        let typed = known.and_then(|(_, kind)| encode_value(kind, value));
+        let id = known.map_or(0, |(id, _)| id);
+
+        match typed {
+            Some(typed) => {
+                result.push(id | TYPED);
+                result.extend(typed);
+            }
+            None => {
+                result.push(id);
+
+                if id == 0 {
+                    write_string(&mut result, name);
+                }
+
+                write_string(&mut result, value);
+            }
+        }
+    }
+
+    result
+}
+
+/// Reads the contents of a tag section, returning the tags in order, or `None` if the section is
+/// invalid
+pub(crate) fn decode(mut data: &[u8]) -> Option<Vec<(String, String)>> {
+    let data = &mut data;
+    let count = read_number(data)?;
+
+    // every tag takes at least two bytes, which bounds the count before anything is allocated
+    let mut tags = Vec::with_capacity(usize::try_from(count).ok()?.min(data.len() / 2));
+
+    for _ in 0..count {
+        let (&byte, rest) = data.split_first()?;
+        *data = rest;
+
+        let id = byte & !TYPED;
+
+        let (name, kind) = match id {
+            0 => (read_string(data)?, Kind::Text),
+            id => {
+                let (name, kind) = KNOWN_TAGS.get(id as usize - 1)?;
+                (name.to_string(), *kind)
+            }
+        };
+
+        let value = if byte & TYPED != 0 {
+            decode_value(kind, data)?
+        } else {
+            read_string(data)?
+        };
+
+        tags.push((name, value));
+    }
+
+    data.is_empty().then_some(tags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, parse_date, write_date};
+
+    fn to_tags(tags: &[(&str, &str)]) -> Vec<(String, String)> {
+        tags.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn dates() {
+        for date in [
+            "0000.01.01",
+            "1858.07.14",
+            "2000.02.29",
+            "2023.12.31",
+            "9999.12.31",
+        ] {
+            assert_eq!(
+                parse_date(date).and_then(write_date),
+                Some(date.to_string())
+            );
+        }
+
+        assert_eq!(parse_date("0000.01.01"), Some(0));
+        assert_eq!(
+            parse_date("2024.03.01").unwrap() - parse_date("2024.02.28").unwrap(),
+            2
+        );
+
+        for date in [
+            "2023.??.??",
+            "1900.02.29",
+            "2023.13.01",
+            "2023.1.01",
+            "23.01.01",
+            "",
+        ] {
+            assert_eq!(parse_date(date), None, "{date}");
+        }
+
+        assert_eq!(write_date(parse_date("9999.12.31").unwrap() + 1), None);
+        assert_eq!(write_date(u32::MAX), None);
+    }
+
+    #[test]
+    fn round_trip() {
+        let tags = to_tags(&[
+            ("Event", "Casual Game"),
+            ("Date", "1858.07.14"),
+            ("Round", "3"),
+            ("White", "Morphy, Paul"),
+            ("WhiteElo", "2690"),
+            ("BlackElo", "-"),
+            ("ECO", "C41"),
+            ("TimeControl", "180+2"),
+            ("EventDate", "1858.??.??"),
+            ("PlyCount", "033"),
+            ("Source", "Paris"),
+        ]);
+
+        let data = encode(&tags);
+
+        assert_eq!(decode(&data), Some(tags));
+
+        // the date, round, rating, ECO code and time control take a byte for the tag and three,
+        // one, two, two and three bytes for their values
+        let typed = encode(&to_tags(&[
+            ("Date", "1858.07.14"),
+            ("Round", "3"),
+            ("WhiteElo", "2690"),
+            ("ECO", "E99"),
+            ("TimeControl", "180+2"),
+        ]));
+
+        assert_eq!(typed.len(), 1 + 5 + 3 + 1 + 2 + 2 + 3);
+
+        for value in ["A00", "E99", "60", "5400+30"] {
+            let name = if value.len() == 3 {
+                "ECO"
+            } else {
+                "TimeControl"
+            };
+            let tags = to_tags(&[(name, value)]);
+
+            assert_eq!(decode(&encode(&tags)), Some(tags));
+        }
+
+        assert_eq!(decode(&encode(&[])), Some(Vec::new()));
+    }
+
+    #[test]
+    fn rejects_invalid_sections() {
+        let data = encode(&to_tags(&[("Event", "Test"), ("ECO", "B01")]));
+
+        assert_eq!(decode(&data[..data.len() - 1]), None);
+        assert_eq!(decode(&[data.as_slice(), &[0]].concat()), None);
+
+        // an unknown tag id, an ECO code past E99 and a string that is not UTF-8
+        assert_eq!(decode(&[1, 100, 0]), None);
+        assert_eq!(decode(&[1, 0x8A, 0xF4, 0x01]), None);
+        assert_eq!(decode(&[1, 1, 1, 0xFF]), None);
+    }
+}
@@ -0,0 +1,218 @@
+//! A URL-safe text form of encoded games for share links and QR codes
+//!
+//! Games are written in base64url, the URL and filename safe base64 alphabet from RFC 4648, either
+//! without padding, which is the compact form meant for links, or padded to a multiple of four
+//! characters. Both forms are accepted when decoding.
+
+use std::{error::Error, fmt};
+
+const BASE64URL: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+/// The standard base64 alphabet, which is used where URL safety does not matter
+#[cfg(any(feature = "serde", test))]
+pub(crate) const BASE64: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+const PADDING: char = '=';
+
+/// The reason a text could not be decoded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextErrorKind {
+    /// A character that is not part of the alphabet
+    InvalidCharacter(char),
+    /// The text ends in the middle of a byte
+    InvalidLength,
+    /// There is too much padding or the padded text is not a multiple of four characters
+    InvalidPadding,
+    /// The bits after the last byte are not zero, so the text is not the one the bytes encode to
+    TrailingBits,
+}
+
+/// An error produced while decoding a text along with where it happened
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextError {
+    pub kind: TextErrorKind,
+    /// The index in characters of the offending character
+    pub position: usize,
+}
+
+impl fmt::Display for TextErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextErrorKind::InvalidCharacter(char) => write!(f, "invalid character {char:?}"),
+            TextErrorKind::InvalidLength => write!(f, "the text ends in the middle of a byte"),
+            TextErrorKind::InvalidPadding => write!(f, "the padding is invalid"),
+            TextErrorKind::TrailingBits => {
+                write!(f, "the unused bits of the last character are set")
+            }
+        }
+    }
+}
+
+impl fmt::Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (position {})", self.kind, self.position)
+    }
+}
+
+impl Error for TextError {}
+
+/// Encodes a game in the compact text form, without padding
+pub fn to_text(data: &[u8]) -> String {
+    encode(data, BASE64URL, false)
+}
+
+/// Encodes a game in the text form padded to a multiple of four characters
+pub fn to_padded_text(data: &[u8]) -> String {
+    encode(data, BASE64URL, true)
+}
+
+/// Decodes a game from either text form
+pub fn from_text(text: &str) -> Result<Vec<u8>, TextError> {
+    decode(text, BASE64URL)
+}
+
+pub(crate) fn encode(data: &[u8], alphabet: &[u8; 64], padding: bool) -> String {
+    let mut result = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (index, byte)| {
+            group | (*byte as u32) << (16 - index * 8)
+        });
+
+        // n bytes take n + 1 characters
+        for index in 0..=chunk.len() {
+            result.push(alphabet[(group >> (18 - index * 6)) as usize & 0x3F] as char);
+        }
+
+        if padding {
+            for _ in chunk.len()..3 {
+                result.push(PADDING);
+            }
+        }
+    }
+
+    result
+}
+
+pub(crate) fn decode(text: &str, alphabet: &[u8; 64]) -> Result<Vec<u8>, TextError> {
+    let chars: Vec<char> = text.chars().collect();
+    let padding = chars
+        .iter()
+        .rev()
+        .take_while(|char| **char == PADDING)
+        .count();
+    let length = chars.len() - padding;
+
+    let error = |kind, position| Err(TextError { kind, position });
+
+    if padding > 0 && (padding > 2 || !chars.len().is_multiple_of(4)) {
+        return error(TextErrorKind::InvalidPadding, length);
+    }
+
+    if length % 4 == 1 {
+        return error(TextErrorKind::InvalidLength, length);
+    }
+
+    let mut result = Vec::with_capacity(length * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for (position, char) in chars[..length].iter().enumerate() {
+        let Some(value) = alphabet.iter().position(|digit| *digit as char == *char) else {
+            return error(TextErrorKind::InvalidCharacter(*char), position);
+        };
+
+        buffer = buffer << 6 | value as u32;
+        bits += 6;
+
+        if bits >= 8 {
+            bits -= 8;
+            result.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+
+    if buffer != 0 {
+        return error(TextErrorKind::TrailingBits, length - 1);
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        outcome::{Outcome, Termination},
+        pgn::read_game,
+        reader::Reader,
+    };
+
+    use super::{
+        decode, encode, from_text, to_padded_text, to_text, TextError, TextErrorKind, BASE64,
+    };
+
+    #[test]
+    fn known_values() {
+        for (data, text) in [
+            (&b""[..], ""),
+            (b"f", "Zg=="),
+            (b"fo", "Zm8="),
+            (b"foo", "Zm9v"),
+            (b"foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(encode(data, BASE64, true), text);
+            assert_eq!(decode(text, BASE64).as_deref(), Ok(data));
+        }
+
+        assert_eq!(to_text(&[0xFB, 0xFF]), "-_8");
+        assert_eq!(to_padded_text(&[0xFB, 0xFF]), "-_8=");
+        assert_eq!(from_text("-_8"), Ok(vec![0xFB, 0xFF]));
+        assert_eq!(from_text("-_8="), Ok(vec![0xFB, 0xFF]));
+    }
+
+    #[test]
+    fn round_trip() {
+        let game = read_game("1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 1-0")
+            .unwrap()
+            .unwrap();
+        let text = to_text(&game.data);
+
+        assert!(text
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || char == '-' || char == '_'));
+
+        let mut reader = Reader::from_vec(from_text(&text).unwrap());
+
+        assert_eq!(reader.by_ref().count(), 6);
+        assert_eq!(
+            reader.get_outcome(),
+            Some((Outcome::WhiteWon, Termination::Unspecified))
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let error = |text| from_text(text).err().unwrap();
+
+        assert_eq!(
+            error("Zm9v Ym"),
+            TextError {
+                kind: TextErrorKind::InvalidCharacter(' '),
+                position: 4
+            }
+        );
+        assert_eq!(error("Zé9v").kind, TextErrorKind::InvalidCharacter('é'));
+        assert_eq!(error("Zm9+").kind, TextErrorKind::InvalidCharacter('+'));
+        assert_eq!(error("Zm9vY").kind, TextErrorKind::InvalidLength);
+        assert_eq!(error("Zg=").kind, TextErrorKind::InvalidPadding);
+        assert_eq!(error("Z===").kind, TextErrorKind::InvalidPadding);
+        assert_eq!(error("Zg==Zm9v").kind, TextErrorKind::InvalidCharacter('='));
+        assert_eq!(
+            error("Zh"),
+            TextError {
+                kind: TextErrorKind::TrailingBits,
+                position: 1
+            }
+        );
+    }
+}
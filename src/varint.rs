@@ -0,0 +1,85 @@
+/// Appends `value` as a LEB128 varint, seven bits per byte with the high bit set on all but the
+/// last byte
+pub fn write_varint(output: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        output.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+
+    output.push(value as u8);
+}
+
+/// Reads a LEB128 varint from the start of `data`, returning it and the number of bytes it took
+///
+/// Returns `None` if the data ends in the middle of the varint or it does not fit in a `u64`
+pub fn read_varint(data: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+
+    for (index, byte) in data.iter().enumerate().take(10) {
+        // the tenth byte only has room for the top bit of the value
+        if index == 9 && *byte > 1 {
+            return None;
+        }
+
+        value |= ((byte & 0x7F) as u64) << (index * 7);
+
+        if byte & 0x80 == 0 {
+            return Some((value, index + 1));
+        }
+    }
+
+    None
+}
+
+/// Maps a signed value to an unsigned one so that values close to zero stay small, with
+/// 0, -1, 1, -2, 2 becoming 0, 1, 2, 3, 4
+pub fn zigzag_encode(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Reverses `zigzag_encode`
+pub fn zigzag_decode(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_varint, write_varint, zigzag_decode, zigzag_encode};
+
+    #[test]
+    fn round_trip() {
+        for value in [0, 1, 127, 128, 300, u32::MAX as u64, u64::MAX] {
+            let mut data = Vec::new();
+            write_varint(&mut data, value);
+
+            assert_eq!(read_varint(&data), Some((value, data.len())));
+            assert_eq!(read_varint(&data[..data.len() - 1]), None);
+        }
+    }
+
+    #[test]
+    fn overflow() {
+        let mut data = vec![0xFF; 9];
+        data.push(0x01);
+
+        assert_eq!(read_varint(&data), Some((u64::MAX, 10)));
+
+        // a tenth byte with more than the top bit, or an eleventh byte, does not fit in a u64
+        data[9] = 0x02;
+        assert_eq!(read_varint(&data), None);
+
+        data[9] = 0x81;
+        data.push(0x00);
+        assert_eq!(read_varint(&data), None);
+    }
+
+    #[test]
+    fn zigzag() {
+        for (value, encoded) in [(0, 0), (-1, 1), (1, 2), (-2, 3), (i64::MAX, u64::MAX - 1)] {
+            assert_eq!(zigzag_encode(value), encoded);
+            assert_eq!(zigzag_decode(encoded), value);
+        }
+
+        assert_eq!(zigzag_decode(zigzag_encode(i64::MIN)), i64::MIN);
+    }
+}
@@ -1,16 +1,97 @@
+mod extras;
+
+use std::{error::Error, fmt};
+
 use chess::{Board, ChessMove};
 
 use crate::{
+    annotations::{Annotations, Clock, Eval},
     bitbuffer::BitBuffer,
-    filters::{diagonal::Diagonal, knight::Knight, straight::Straight, Filter},
-    outcome::Outcome,
-    PROMOTION_KEY,
+    checkpoints::{self, Checkpoint},
+    chess960::{is_castle, Castling},
+    filters::{
+        diagonal::Diagonal, get_candidates, get_code_length, get_legal_promotions, knight::Knight,
+        straight::Straight, Filter,
+    },
+    header::{CHECKPOINTS_SECTION, COMPACT_OVERFLOW},
+    outcome::{Outcome, Termination},
+    position::encode_position,
+    reader::decode_move,
+    stats::{get_promotion_bits, EncodingStats},
+    PROMOTION_KEY, START_POSITION_MARKER, VARIATION_END, VARIATION_START,
 };
 
+pub(crate) use self::extras::Extras;
+
+/// The reason a move could not be encoded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EncodeErrorKind {
+    /// The move is not legal in the position it was given with
+    IllegalMove(ChessMove),
+    /// The filter chosen for the move does not list its source square as a candidate
+    UnreachableSource(ChessMove),
+    /// The encoded move did not decode back to the same move
+    VerificationFailed(ChessMove),
+    /// A variation was started before any move it could replace or ended without being started
+    InvalidVariation,
+}
+
+/// An error produced while encoding a move along with the ply it happened at
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EncodeError {
+    pub kind: EncodeErrorKind,
+    /// The index of the ply of the main line that was being encoded
+    pub ply: usize,
+}
+
+impl fmt::Display for EncodeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodeErrorKind::IllegalMove(chess_move) => {
+                write!(f, "the move {chess_move} is illegal")
+            }
+            EncodeErrorKind::UnreachableSource(chess_move) => {
+                write!(f, "the source square of {chess_move} can not be encoded")
+            }
+            EncodeErrorKind::VerificationFailed(chess_move) => {
+                write!(f, "the move {chess_move} did not decode to itself")
+            }
+            EncodeErrorKind::InvalidVariation => {
+                write!(f, "there is no move or variation to replace or end")
+            }
+        }
+    }
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (ply {})", self.kind, self.ply)
+    }
+}
+
+impl Error for EncodeError {}
+
 #[derive(Clone)]
 pub struct Writer {
     core: Vec<u8>,
     overflow: Vec<(u8, u8)>, // data, # of bits
+    verify: bool,
+    extras: Extras,
+    header: bool,
+    compact: bool,
+    /// The number of moves of the main line
+    ply: usize,
+    /// The Chess960 castling rights of the position the next move is played from
+    castling: Castling,
+    /// The Chess960 castling rights before the last move of the current line
+    previous_castling: Castling,
+    /// The number of moves of every open variation, innermost last, and the castling rights and
+    /// previous castling rights of the line it branched from
+    variations: Vec<(usize, Castling, Castling)>,
+    /// The number of plies between checkpoints, or 0 if none are taken
+    checkpoint_interval: usize,
+    checkpoints: Vec<Checkpoint>,
+    stats: EncodingStats,
 }
 
 impl Default for Writer {
@@ -25,25 +106,308 @@ impl Writer {
         Self {
             core: Vec::new(),
             overflow: Vec::new(),
+            verify: false,
+            extras: Extras::new(Board::default()),
+            header: false,
+            compact: false,
+            ply: 0,
+            castling: Castling::default(),
+            previous_castling: Castling::default(),
+            variations: Vec::new(),
+            checkpoint_interval: 0,
+            checkpoints: Vec::new(),
+            stats: EncodingStats::default(),
+        }
+    }
+
+    /// Creates a new empty instance of the `Writer` for a game that starts from `position`
+    ///
+    /// The position is only stored if it differs from the default one, so the output of a game
+    /// from the default position is the same as with `new`
+    ///
+    /// The position has no Chess960 castling rights, use `with_castling` for those
+    pub fn with_start_position(position: Board) -> Self {
+        Self {
+            extras: Extras::new(position),
+            ..Self::new()
+        }
+    }
+
+    /// Creates a new empty instance of the `Writer` for a game that starts from a Chess960 start
+    /// position with the castling rights `castling`, which the `Board` can not hold
+    ///
+    /// Only all four rights of the start position can be stored, anything else is dropped so that
+    /// castling with it is rejected as illegal.
+    pub fn with_castling(position: Board, castling: Castling) -> Self {
+        let mut writer = Self::with_start_position(position);
+
+        writer.castling = writer.extras.set_castling(castling);
+        writer
+    }
+
+    /// Whether the game is stored as a Chess960 start position, which has all of its castling
+    /// rights
+    pub(crate) fn is_chess960(&self) -> bool {
+        self.extras.is_chess960()
+    }
+
+    /// Sets whether `try_add_move` decodes every move right after encoding it to make sure it
+    /// round-trips. This roughly doubles the cost of encoding.
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify = verify;
+    }
+
+    /// Sets why the game ended, which is stored in the header as there is no room for it next to
+    /// the outcome in the terminator byte
+    pub fn set_termination(&mut self, termination: Termination) {
+        self.extras.termination = termination;
+    }
+
+    /// Sets whether the output starts with a versioned header. Without one a custom start
+    /// position is marked the legacy way, which older readers understand.
+    ///
+    /// The header is always written when the game has a termination, metadata, tags, annotations,
+    /// a checksum, compact overflow codes or starts from a Chess960 start position.
+    pub fn set_header(&mut self, header: bool) {
+        self.header = header;
+    }
+
+    /// Sets the bytes stored in the metadata section of the header
+    pub fn set_metadata(&mut self, metadata: Vec<u8>) {
+        self.extras.metadata = Some(metadata);
+    }
+
+    /// Sets the tag pairs stored in the tag section of the header, such as `Event` or `White`
+    pub fn set_tags(&mut self, tags: Vec<(String, String)>) {
+        self.extras.tags = tags;
+    }
+
+    /// Adds a comment after the last move that was added, or before the first move if there are
+    /// none yet
+    ///
+    /// Inside a variation the comment follows the last move or variation marker that was added
+    pub fn add_comment(&mut self, comment: impl Into<String>) {
+        match self.get_node() {
+            Some(node) => self
+                .extras
+                .annotations
+                .get_node_mut(node)
+                .comments
+                .push(comment.into()),
+            None => self.extras.annotations.add_comment(self.ply, comment),
+        }
+    }
+
+    /// Sets the time left on the clock of the side that made the last move that was added
+    pub fn set_clock(&mut self, clock: Clock) {
+        match self.get_node() {
+            Some(node) => self.extras.annotations.get_node_mut(node).clock = Some(clock),
+            None => self.extras.annotations.set_clock(self.ply, clock),
+        }
+    }
+
+    /// Sets the engine evaluation of the position after the last move that was added
+    pub fn set_eval(&mut self, eval: Eval) {
+        match self.get_node() {
+            Some(node) => self.extras.annotations.get_node_mut(node).eval = Some(eval),
+            None => self.extras.annotations.set_eval(self.ply, eval),
+        }
+    }
+
+    /// Sets the increment of the time control in milliseconds, which makes the clocks smaller
+    pub fn set_clock_increment(&mut self, increment: u32) {
+        self.extras.annotations.set_clock_increment(increment);
+    }
+
+    /// Replaces the annotations of the game, which are keyed by ply of the main line and by node
+    /// inside variations
+    pub fn set_annotations(&mut self, annotations: Annotations) {
+        self.extras.annotations = annotations;
+    }
+
+    /// Adds a numeric annotation glyph to the last move that was added
+    pub fn add_nag(&mut self, nag: u8) {
+        match self.get_node() {
+            Some(node) => self.extras.annotations.get_node_mut(node).nags.push(nag),
+            None => self.extras.annotations.add_nag(self.ply, nag),
+        }
+    }
+
+    /// The index of the last move or variation marker in the core stream when it is inside a
+    /// variation, which is what the annotations that follow it are keyed by
+    fn get_node(&self) -> Option<usize> {
+        (!self.variations.is_empty()).then(|| self.core.len() - 1)
+    }
+
+    /// Starts a variation that replaces the last move that was added, so the moves that follow are
+    /// played from the position before it. Variations can be nested and several can replace the
+    /// same move.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no move has been added to the current line, use `try_start_variation` to handle
+    /// this
+    pub fn start_variation(&mut self) {
+        self.try_start_variation()
+            .expect("There is no move for the variation to replace");
+    }
+
+    /// Starts a variation that replaces the last move that was added, returning an error if no
+    /// move has been added to the current line
+    pub fn try_start_variation(&mut self) -> Result<(), EncodeError> {
+        if self
+            .variations
+            .last()
+            .map_or(self.ply, |(plies, ..)| *plies)
+            == 0
+        {
+            return Err(EncodeError {
+                kind: EncodeErrorKind::InvalidVariation,
+                ply: self.ply,
+            });
+        }
+
+        self.core.push(VARIATION_START);
+        self.variations
+            .push((0, self.castling, self.previous_castling));
+        self.castling = self.previous_castling;
+
+        Ok(())
+    }
+
+    /// Ends the innermost variation, after which the line it branched from continues
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no open variation, use `try_end_variation` to handle this
+    pub fn end_variation(&mut self) {
+        self.try_end_variation()
+            .expect("There is no variation to end");
+    }
+
+    /// Ends the innermost variation, returning an error if there is no open variation
+    pub fn try_end_variation(&mut self) -> Result<(), EncodeError> {
+        (_, self.castling, self.previous_castling) = self.variations.pop().ok_or(EncodeError {
+            kind: EncodeErrorKind::InvalidVariation,
+            ply: self.ply,
+        })?;
+
+        self.core.push(VARIATION_END);
+
+        Ok(())
+    }
+
+    /// Sets whether the output ends with a CRC-32 of its contents, so that readers can detect
+    /// corruption instead of decoding a different game
+    pub fn set_checksum(&mut self, checksum: bool) {
+        self.extras.checksum = checksum;
+    }
+
+    /// Sets whether overflow codes only count the legal candidates and use as few bits as possible,
+    /// which makes the output smaller but unreadable to readers that do not support the header
+    ///
+    /// # Panics
+    ///
+    /// Panics if moves have already been added, as they were encoded the other way
+    pub fn set_compact_overflow(&mut self, compact: bool) {
+        assert!(
+            self.core.is_empty(),
+            "The overflow mode can not be changed after moves have been added"
+        );
+
+        self.compact = compact;
+    }
+
+    /// Sets the number of plies between checkpoints, which let `Reader::seek` replay at most that
+    /// many moves to reach any ply of the main line. Each checkpoint holds a whole position, so
+    /// this costs about 30 bytes per checkpoint. An interval of 0, the default, takes none.
+    ///
+    /// # Panics
+    ///
+    /// Panics if moves have already been added, as no checkpoints were taken for them
+    pub fn set_checkpoint_interval(&mut self, interval: usize) {
+        assert!(
+            self.core.is_empty(),
+            "The checkpoint interval can not be changed after moves have been added"
+        );
+
+        self.checkpoint_interval = interval;
+    }
+
+    /// Returns how the moves so far were encoded and how many bytes `get_data` would return
+    pub fn get_stats(&self) -> EncodingStats {
+        let overflow_bits: usize = self
+            .overflow
+            .iter()
+            .map(|(_, length)| *length as usize)
+            .sum();
+
+        EncodingStats {
+            games: 1,
+            header_bytes: self.get_prefix().len(),
+            core_bytes: self.core.len() + self.variations.len() + 1,
+            overflow_bytes: overflow_bits.div_ceil(8),
+            checksum_bytes: if self.extras.checksum { 4 } else { 0 },
+            ..self.stats
         }
     }
 
     /// Consumes a mutable self and creates a vector of bytes that represents the data
+    ///
+    /// Variations that are still open are ended first.
     pub fn get_data(mut self, outcome: Option<Outcome>) -> Vec<u8> {
+        self.core
+            .extend(std::iter::repeat_n(VARIATION_END, self.variations.len()));
+
         self.core.push(outcome.unwrap_or_default().get_id());
 
-        self.core
-            .iter()
-            .chain(Self::get_overflow_data(self.overflow).iter())
-            .copied()
-            .collect()
+        let mut result = self.get_prefix();
+
+        result.extend(self.core);
+        result.extend(Self::get_overflow_data(self.overflow));
+        self.extras.write_checksum(&mut result);
+
+        result
+    }
+
+    /// Returns the header or the legacy start position marker that comes before the core stream
+    fn get_prefix(&self) -> Vec<u8> {
+        let mut result = Vec::new();
+
+        let mut flags = 0;
+
+        if self.compact {
+            flags |= COMPACT_OVERFLOW;
+        }
+
+        let mut sections = self.extras.get_sections();
+
+        if !self.checkpoints.is_empty() {
+            sections.push((
+                CHECKPOINTS_SECTION,
+                checkpoints::encode(
+                    self.checkpoint_interval,
+                    &self.checkpoints,
+                    &self.extras.castling,
+                ),
+            ));
+        }
+
+        if self.header || self.extras.needs_header() || !sections.is_empty() || flags != 0 {
+            result.extend(self.extras.write_header(&sections, flags));
+        } else if let Some(start_position) = self.extras.get_start_position() {
+            result.push(START_POSITION_MARKER);
+            result.extend(encode_position(start_position));
+        }
+
+        result
     }
 
     fn get_overflow_data(overflow: Vec<(u8, u8)>) -> Vec<u8> {
         let mut bit_buffer = BitBuffer::new();
 
         for (data, length) in overflow {
-            bit_buffer.add(data, length);
+            bit_buffer.add(data.into(), length);
         }
 
         bit_buffer.to_bytes()
@@ -55,8 +419,114 @@ impl Writer {
     ///
     /// * `chess_move` - A valid move for the position inputted
     /// * `position` - The current chess position before the `chess_move` has been processed
+    ///
+    /// # Panics
+    ///
+    /// Panics if the move can not be encoded, use `try_add_move` to handle this
     pub fn add_move(&mut self, chess_move: &ChessMove, position: &Board) {
+        self.try_add_move(chess_move, position)
+            .expect("Could not encode move");
+    }
+
+    /// Checks that the move is legal and can be encoded before adding it to the output. Nothing is
+    /// added if an error is returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `chess_move` - A move for the position inputted
+    /// * `position` - The current chess position before the `chess_move` has been processed
+    pub fn try_add_move(
+        &mut self,
+        chess_move: &ChessMove,
+        position: &Board,
+    ) -> Result<(), EncodeError> {
+        let error = |kind| EncodeError {
+            kind,
+            ply: self.ply,
+        };
+
+        if !self.castling.legal(position, *chess_move) {
+            return Err(error(EncodeErrorKind::IllegalMove(*chess_move)));
+        }
+
+        let chess960 = self.extras.is_chess960();
+
+        let (byte, overflow) = Self::encode_move(chess_move, position, self.compact, chess960)
+            .map_err(|_| error(EncodeErrorKind::UnreachableSource(*chess_move)))?;
+
+        if self.verify {
+            let mut pending = overflow.iter();
+
+            let decoded = decode_move(
+                byte,
+                position,
+                &self.castling,
+                self.compact,
+                chess960,
+                |length| {
+                    pending
+                        .next()
+                        .filter(|(_, bits)| *bits == length)
+                        .map(|(data, _)| *data)
+                },
+            );
+
+            if decoded != Ok(*chess_move) || pending.next().is_some() {
+                return Err(error(EncodeErrorKind::VerificationFailed(*chess_move)));
+            }
+        }
+
+        self.stats.add_move(
+            byte,
+            overflow.iter().map(|(_, length)| *length as usize).sum(),
+            get_promotion_bits(chess_move, position, self.compact),
+        );
+
+        if self.variations.is_empty()
+            && self.checkpoint_interval > 0
+            && (self.ply + 1).is_multiple_of(self.checkpoint_interval)
+        {
+            self.checkpoints.push(Checkpoint {
+                ply: self.ply,
+                position: *position,
+                castling: self.castling,
+                core_cursor: self.core.len(),
+                overflow_cursor: self
+                    .overflow
+                    .iter()
+                    .map(|(_, length)| *length as usize)
+                    .sum(),
+            });
+        }
+
+        self.core.push(byte);
+        self.overflow.extend(overflow);
+
+        self.previous_castling = self.castling;
+        self.castling.update(position, *chess_move);
+
+        match self.variations.last_mut() {
+            Some((plies, ..)) => *plies += 1,
+            None => self.ply += 1,
+        }
+
+        Ok(())
+    }
+
+    /// Returns the core byte and the overflow entries for a legal move
+    fn encode_move(
+        chess_move: &ChessMove,
+        position: &Board,
+        compact: bool,
+        chess960: bool,
+    ) -> Result<(u8, Vec<(u8, u8)>), ()> {
         let to_square = chess_move.get_dest();
+
+        // castling is the only move onto a piece of the side to move, so it needs no overflow
+        if is_castle(position, *chess_move) {
+            return Ok((to_square.to_int() | Straight::get_id(), Vec::new()));
+        }
+
         let id: u8;
         let overflow: Option<(u8, u8)>; // data, num bits
 
@@ -72,33 +542,177 @@ impl Writer {
                 .abs_diff(chess_move.get_dest().get_file().to_index())
         {
             id = Diagonal::get_id();
-            overflow =
-                Diagonal::get_overflow(&chess_move.get_dest(), &chess_move.get_source(), position);
+            overflow = Diagonal::get_overflow(
+                &chess_move.get_dest(),
+                &chess_move.get_source(),
+                position,
+                chess960,
+            )?;
         } else if chess_move.get_dest().get_rank() == chess_move.get_source().get_rank()
             || chess_move.get_dest().get_file() == chess_move.get_source().get_file()
         {
             id = Straight::get_id();
-            overflow =
-                Straight::get_overflow(&chess_move.get_dest(), &chess_move.get_source(), position);
+            overflow = Straight::get_overflow(
+                &chess_move.get_dest(),
+                &chess_move.get_source(),
+                position,
+                chess960,
+            )?;
         } else {
             id = Knight::get_id();
-            overflow =
-                Knight::get_overflow(&chess_move.get_dest(), &chess_move.get_source(), position);
+            overflow = Knight::get_overflow(
+                &chess_move.get_dest(),
+                &chess_move.get_source(),
+                position,
+                chess960,
+            )?;
         }
 
-        self.core.push(to_square.to_int() | id);
+        let mut overflow: Vec<(u8, u8)> = if compact {
+            let candidates = get_candidates(id >> 6, &to_square, position, chess960);
 
-        if let Some(data) = overflow {
-            self.overflow.push(data);
-        }
+            let index = candidates
+                .iter()
+                .position(|square| *square == chess_move.get_source())
+                .ok_or(())?;
+
+            vec![(index as u8, get_code_length(candidates.len()))]
+        } else {
+            overflow.into_iter().collect()
+        };
 
         if let Some(promotion) = chess_move.get_promotion() {
-            let promotion_index = PROMOTION_KEY
-                .iter()
-                .position(|role| *role == promotion)
-                .expect("Not a valid promotion piece") as u8;
+            if compact {
+                let promotions =
+                    get_legal_promotions(&chess_move.get_source(), &to_square, position);
+
+                let index = promotions
+                    .iter()
+                    .position(|piece| *piece == promotion)
+                    .ok_or(())?;
 
-            self.overflow.push((promotion_index, 2))
+                overflow.push((index as u8, get_code_length(promotions.len())));
+            } else {
+                let promotion_index = PROMOTION_KEY
+                    .iter()
+                    .position(|role| *role == promotion)
+                    .expect("Not a valid promotion piece")
+                    as u8;
+
+                overflow.push((promotion_index, 2))
+            }
         }
+
+        // a single candidate needs no bits to be picked
+        overflow.retain(|(_, length)| *length > 0);
+
+        Ok((to_square.to_int() | id, overflow))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chess::{Board, ChessMove, Piece, Square};
+
+    use crate::{outcome::Outcome, reader::Reader};
+
+    use super::{EncodeError, EncodeErrorKind, Writer};
+
+    #[test]
+    fn rejects_illegal_move() {
+        let mut writer = Writer::new();
+        let chess_move = ChessMove::new(Square::E2, Square::E5, None);
+
+        assert_eq!(
+            writer.try_add_move(&chess_move, &Board::default()),
+            Err(EncodeError {
+                kind: EncodeErrorKind::IllegalMove(chess_move),
+                ply: 0
+            })
+        );
+        assert_eq!(writer.get_data(None), vec![0]);
+    }
+
+    #[test]
+    fn rejects_invalid_variations() {
+        let mut writer = Writer::new();
+        let error = Err(EncodeError {
+            kind: EncodeErrorKind::InvalidVariation,
+            ply: 0,
+        });
+
+        assert_eq!(writer.try_start_variation(), error);
+        assert_eq!(writer.try_end_variation(), error);
+
+        // an open variation is ended by get_data, right before the terminator
+        writer.add_move(
+            &ChessMove::new(Square::E2, Square::E4, None),
+            &Board::default(),
+        );
+        writer.start_variation();
+
+        assert_eq!(writer.get_stats().core_bytes, 4);
+        assert_eq!(&writer.get_data(None)[1..], [0b00111000, 0b00111001, 0]);
+    }
+
+    #[test]
+    fn verified_promotion() {
+        let chess: Board = "k7/4P3/8/8/8/8/8/K7 w - - 0 1".parse().unwrap();
+        let mut writer = Writer::new();
+        writer.set_verify(true);
+
+        for piece in [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight] {
+            assert_eq!(
+                writer.try_add_move(&ChessMove::new(Square::E7, Square::E8, Some(piece)), &chess),
+                Ok(())
+            );
+        }
+    }
+
+    #[test]
+    fn compact_overflow() {
+        // the knights on c3 and g3 both reach e4, then the pawn promotes with all four pieces legal
+        let chess: Board = "7k/1P6/8/K7/8/2N3N1/8/8 w - - 0 1".parse().unwrap();
+        let moves = [
+            ChessMove::new(Square::G3, Square::E4, None),
+            ChessMove::new(Square::H8, Square::G8, None),
+            ChessMove::new(Square::B7, Square::B8, Some(Piece::Knight)),
+        ];
+
+        let encode = |compact| {
+            let mut writer = Writer::with_start_position(chess);
+            let mut board = chess;
+
+            writer.set_verify(true);
+            writer.set_compact_overflow(compact);
+
+            for chess_move in moves {
+                writer.add_move(&chess_move, &board);
+                board = board.make_move_new(chess_move);
+            }
+
+            writer.get_data(Some(Outcome::WhiteWon))
+        };
+
+        let legacy = encode(false);
+        let compact = encode(true);
+
+        assert_eq!(
+            Reader::new(&compact)
+                .map(|(chess_move, _)| chess_move)
+                .collect::<Vec<_>>(),
+            moves
+        );
+
+        let overflow_bits = |data: &[u8]| {
+            let mut reader = Reader::new(data);
+            let bits = reader.get_overflow_left();
+            reader.by_ref().count();
+            bits - reader.get_overflow_left()
+        };
+
+        // two knights need one bit instead of three, the promotion still takes two bits
+        assert_eq!(overflow_bits(&legacy), 3 + 2);
+        assert_eq!(overflow_bits(&compact), 1 + 2);
     }
 }
@@ -0,0 +1,107 @@
+use chess::Board;
+
+use crate::{
+    annotations::Annotations,
+    checksum::crc32,
+    chess960::{get_start_index, Castling},
+    header::{write_header, CHECKSUM, TAGS_SECTION},
+    outcome::Termination,
+    tags,
+};
+
+/// Everything about a game besides its moves that both `Writer` and `EntropyWriter` store, and
+/// the parts of the output they write the same way
+#[derive(Clone)]
+pub(crate) struct Extras {
+    pub termination: Termination,
+    pub start_position: Board,
+    /// The Chess960 castling rights at the start, which are only set by `set_castling`
+    pub castling: Castling,
+    pub metadata: Option<Vec<u8>>,
+    pub annotations: Annotations,
+    pub tags: Vec<(String, String)>,
+    pub checksum: bool,
+}
+
+impl Extras {
+    pub fn new(start_position: Board) -> Self {
+        Self {
+            termination: Termination::Unspecified,
+            start_position,
+            castling: Castling::default(),
+            metadata: None,
+            annotations: Annotations::new(),
+            tags: Vec::new(),
+            checksum: false,
+        }
+    }
+
+    /// The start position, unless it is the default one
+    pub fn get_start_position(&self) -> Option<&Board> {
+        Some(&self.start_position).filter(|position| **position != Board::default())
+    }
+
+    /// Whether the game starts from a Chess960 start position other than the standard one with all
+    /// of its castling rights, which the header flags with `CHESS960`
+    pub fn is_chess960(&self) -> bool {
+        self.get_start_position()
+            .and_then(get_start_index)
+            .is_some_and(|index| Castling::from_start_index(index) == self.castling)
+    }
+
+    /// Keeps the Chess960 castling rights at the start if they are all four rights of the start
+    /// position, which are the only ones that can be stored, and returns the rights that are kept
+    pub fn set_castling(&mut self, castling: Castling) -> Castling {
+        let start = self
+            .get_start_position()
+            .and_then(get_start_index)
+            .map(Castling::from_start_index);
+
+        if start == Some(castling) {
+            self.castling = castling;
+        }
+
+        self.castling
+    }
+
+    /// Whether anything stored here needs a header, not counting the sections
+    pub fn needs_header(&self) -> bool {
+        self.termination != Termination::Unspecified
+            || self.metadata.is_some()
+            || self.checksum
+            || self.is_chess960()
+    }
+
+    /// The extension sections holding the annotations and the tags
+    pub fn get_sections(&self) -> Vec<(u8, Vec<u8>)> {
+        let mut sections = self.annotations.encode();
+
+        if !self.tags.is_empty() {
+            sections.push((TAGS_SECTION, tags::encode(&self.tags)));
+        }
+
+        sections
+    }
+
+    /// Creates the header with `sections` and `flags`, which are the flags of the writer itself
+    pub fn write_header(&self, sections: &[(u8, Vec<u8>)], mut flags: u16) -> Vec<u8> {
+        if self.checksum {
+            flags |= CHECKSUM;
+        }
+
+        write_header(
+            self.get_start_position(),
+            &self.termination,
+            self.metadata.as_deref(),
+            sections,
+            flags,
+        )
+    }
+
+    /// Appends the checksum to the finished output if there is one
+    pub fn write_checksum(&self, result: &mut Vec<u8>) {
+        if self.checksum {
+            result.extend(crc32(result).to_le_bytes());
+        }
+    }
+}
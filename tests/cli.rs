@@ -0,0 +1,38 @@
+use std::process::{Command, Output};
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_ascn"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn rejects_unknown_arguments() {
+    for args in [
+        &["encode", "--txet", "tests/master_games.pgn"][..],
+        &["encode", "--checkpoints", "tests/master_games.pgn"],
+        &["decode", "--compact", "tests/master_games.pgn"],
+        &["inspect", "-x", "tests/master_games.pgn"],
+        &["stats", "tests/master_games.pgn", "tests/master_games.pgn"],
+        &["frob"],
+    ] {
+        let output = run(args);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+
+        assert!(!output.status.success(), "{args:?}");
+        assert!(stderr.contains("Usage: ascn"), "{args:?}: {stderr}");
+        assert!(output.stdout.is_empty(), "{args:?}");
+    }
+
+    let output = run(&[
+        "encode",
+        "--archive",
+        "--checkpoints=8",
+        "--text",
+        "tests/master_games.pgn",
+    ]);
+
+    assert!(output.status.success());
+    assert!(!output.stdout.is_empty());
+}
@@ -0,0 +1,131 @@
+//! Measures how much smaller the optional encodings make real games
+//!
+//! `master_games` runs on six well known games kept in the repository. The lichess collection that
+//! `large_test.rs` reads is too large to keep in it, so the same measurement of it is ignored:
+//!
+//! ```sh
+//! cargo test --release --test compression -- --ignored --nocapture
+//! ```
+
+use std::fs::read_to_string;
+
+use ascn_rs::{
+    entropy::EntropyWriter,
+    outcome::Outcome,
+    pgn::read_games,
+    reader::Reader,
+    stats::{read_stats, EncodingStats},
+    writer::Writer,
+};
+use chess::{Board, ChessMove};
+
+/// The moves and outcome of every game of a PGN file that converts without errors
+fn read_moves(path: &str) -> Vec<(Vec<ChessMove>, Outcome)> {
+    read_games(&read_to_string(path).unwrap())
+        .filter_map(Result::ok)
+        .map(|game| {
+            let moves = Reader::new(&game.data)
+                .map(|(chess_move, _)| chess_move)
+                .collect();
+
+            (moves, game.outcome)
+        })
+        .collect()
+}
+
+/// Encodes only the moves and outcome of every game, returning the summed stats
+///
+/// Every game gets a header, as the compact codes need one for their flag.
+fn measure(games: &[(Vec<ChessMove>, Outcome)], compact: bool) -> EncodingStats {
+    games
+        .iter()
+        .map(|(moves, outcome)| {
+            let mut writer = Writer::new();
+            let mut position = Board::default();
+
+            writer.set_header(true);
+            writer.set_compact_overflow(compact);
+
+            for chess_move in moves {
+                writer.add_move(chess_move, &position);
+                position = position.make_move_new(*chess_move);
+            }
+
+            read_stats(&writer.get_data(Some(*outcome))).unwrap()
+        })
+        .sum()
+}
+
+/// Entropy codes the moves and outcome of every game, returning the summed stats
+fn measure_entropy(games: &[(Vec<ChessMove>, Outcome)]) -> EncodingStats {
+    games
+        .iter()
+        .map(|(moves, outcome)| {
+            let mut writer = EntropyWriter::new();
+            let mut position = Board::default();
+
+            for chess_move in moves {
+                writer.add_move(chess_move, &position);
+                position = position.make_move_new(*chess_move);
+            }
+
+            read_stats(&writer.get_data(Some(*outcome))).unwrap()
+        })
+        .sum()
+}
+
+/// Prints how the compact overflow codes and entropy coding compare with the default encoding,
+/// returning the share of the total size the compact codes save and how many times smaller the
+/// entropy coded moves are
+fn report(path: &str) -> (f64, f64) {
+    let games = read_moves(path);
+    let plain = measure(&games, false);
+    let compact = measure(&games, true);
+    let entropy = measure_entropy(&games);
+
+    let moves = |stats: &EncodingStats| stats.core_bytes + stats.overflow_bytes;
+    let saving = 1.0 - compact.get_total_bytes() as f64 / plain.get_total_bytes() as f64;
+    let ratio = moves(&plain) as f64 / moves(&entropy) as f64;
+
+    println!("{path}: {} games, {} plies", plain.games, plain.plies);
+    println!(
+        "default overflow {} bits, moves {} bytes, total {} bytes",
+        plain.overflow_bits + plain.promotion_bits,
+        moves(&plain),
+        plain.get_total_bytes()
+    );
+    println!(
+        "compact overflow {} bits, moves {} bytes, total {} bytes",
+        compact.overflow_bits + compact.promotion_bits,
+        moves(&compact),
+        compact.get_total_bytes()
+    );
+    println!(
+        "entropy          moves {} bytes, total {} bytes",
+        moves(&entropy),
+        entropy.get_total_bytes()
+    );
+    println!("compact saving   {:.1}% of the total size", saving * 100.0);
+    println!(
+        "entropy ratio    {ratio:.2}x for the moves, {:.2}x in total, {:.2} bits per ply",
+        plain.get_total_bytes() as f64 / entropy.get_total_bytes() as f64,
+        entropy.get_bits_per_ply().unwrap_or_default()
+    );
+
+    (saving, ratio)
+}
+
+#[test]
+fn master_games() {
+    let (saving, ratio) = report("tests/master_games.pgn");
+
+    assert!(saving > 0.0);
+    // short of the two times smaller that entropy coding set out to reach, see the README
+    assert!(ratio > 1.75);
+}
+
+#[test]
+#[ignore]
+fn lichess_collection() {
+    report("tests/lichess_test_collection.pgn");
+}
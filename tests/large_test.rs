@@ -40,11 +40,10 @@ impl<'a> Visitor<'a> for TestVisitor {
     }
 
     fn end_game(&mut self, outcome: &str) {
-        let mut reader = Reader::new(
-            &self
-                .writer
+        let mut reader = Reader::from_vec(
+            self.writer
                 .clone()
-                .get_data(Some(Outcome::from_string(outcome))),
+                .get_data(Some(Outcome::parse_lenient(outcome).unwrap())),
         );
 
         let (_, board) = reader.clone().last().unwrap();
@@ -53,7 +52,10 @@ impl<'a> Visitor<'a> for TestVisitor {
         #[allow(clippy::while_let_on_iterator)] // consumes without taking ownership
         while let Some(_) = reader.next() {}
 
-        assert_eq!(reader.get_outcome(), &Some(Outcome::from_string(outcome)));
+        assert_eq!(
+            reader.get_outcome().map(|(outcome, _)| outcome),
+            Some(Outcome::parse_lenient(outcome).unwrap())
+        );
 
         self.progress_bar.inc(1);
     }
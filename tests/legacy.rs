@@ -0,0 +1,98 @@
+use std::{fs::read_to_string, str::FromStr};
+
+use ascn_rs::{
+    header::{has_header, EXTENDED_OUTCOME},
+    outcome::{Outcome, Termination},
+    reader::Reader,
+    writer::Writer,
+};
+use chess::{Board, ChessMove};
+
+/// Games encoded by the 0.3 encoder, which wrote neither a header nor a flag for Chess960
+///
+/// Every line holds the hex of the encoded game, its result and the moves in UCI notation
+fn legacy_games() -> Vec<(Vec<u8>, Outcome, Vec<ChessMove>)> {
+    read_to_string("tests/legacy_games.txt")
+        .unwrap()
+        .lines()
+        .map(|line| {
+            let mut fields = line.split(' ');
+            let hex = fields.next().unwrap();
+            let data = (0..hex.len())
+                .step_by(2)
+                .map(|index| u8::from_str_radix(&hex[index..index + 2], 16).unwrap())
+                .collect();
+            let outcome = fields.next().unwrap().parse().unwrap();
+            let moves = fields
+                .map(|uci| ChessMove::from_str(uci).unwrap())
+                .collect();
+
+            (data, outcome, moves)
+        })
+        .collect()
+}
+
+#[test]
+fn decodes_legacy_games() {
+    for (data, outcome, moves) in legacy_games() {
+        let mut reader = Reader::try_new(&data).unwrap();
+        let mut decoded = Vec::new();
+
+        assert_eq!(reader.get_header(), None);
+
+        while let Some((chess_move, _)) = reader.try_next().unwrap() {
+            decoded.push(chess_move);
+        }
+
+        assert_eq!(decoded, moves);
+        assert_eq!(
+            reader.get_outcome(),
+            Some((outcome, Termination::Unspecified))
+        );
+        assert!(reader.get_overflow_left() < 8);
+    }
+}
+
+#[test]
+fn encodes_legacy_games_unchanged() {
+    for (data, outcome, moves) in legacy_games() {
+        let mut writer = Writer::new();
+        let mut board = Board::default();
+
+        for chess_move in moves {
+            writer.add_move(&chess_move, &board);
+            board = board.make_move_new(chess_move);
+        }
+
+        assert_eq!(writer.get_data(Some(outcome)), data);
+    }
+}
+
+#[test]
+fn termination_keeps_the_legacy_core() {
+    for (data, outcome, moves) in legacy_games() {
+        let mut writer = Writer::new();
+        let mut board = Board::default();
+
+        for chess_move in moves {
+            writer.add_move(&chess_move, &board);
+            board = board.make_move_new(chess_move);
+        }
+
+        writer.set_termination(Termination::Resignation);
+
+        let extended = writer.get_data(Some(outcome));
+        let mut reader = Reader::try_new(&extended).unwrap();
+
+        assert!(has_header(&extended));
+        assert!(reader.get_header().unwrap().has_flag(EXTENDED_OUTCOME));
+        assert_eq!(&extended[reader.get_core_offset()..], data);
+
+        while reader.try_next().unwrap().is_some() {}
+
+        assert_eq!(
+            reader.get_outcome(),
+            Some((outcome, Termination::Resignation))
+        );
+    }
+}